@@ -0,0 +1,315 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::times::format_utc_stamp;
+use crate::{copy_file, copy_times};
+
+/// What to keep of a file before an operation overwrites it.
+///
+/// The old contents are copied (not moved) to the backup, along with the
+/// permissions and modification time, so appending to or rewriting the
+/// original behaves as it would without a backup. Only existing regular
+/// files are backed up.
+///
+/// Parses from the names GNU tools use for `--backup`: `none`/`off`,
+/// `simple`/`never` and `numbered`/`t`.
+///
+/// Examples
+/// ```
+/// use soil::{read_text, remove_file, write_file, Backup, WriteOptions};
+/// write_file("backup_example.txt", "v1").unwrap();
+///
+/// let options = WriteOptions::new().backup(Backup::Numbered { keep: None });
+/// options.write("backup_example.txt", "v2").unwrap();
+/// options.write("backup_example.txt", "v3").unwrap();
+/// assert_eq!(read_text("backup_example.txt.~1~").unwrap(), "v1");
+/// assert_eq!(read_text("backup_example.txt.~2~").unwrap(), "v2");
+/// assert_eq!(read_text("backup_example.txt").unwrap(), "v3");
+/// # for file in ["backup_example.txt", "backup_example.txt.~1~", "backup_example.txt.~2~"] {
+/// #     remove_file(file).unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Backup {
+    /// Overwrite without a backup.
+    #[default]
+    None,
+    /// A single backup next to the file, named `file~`.
+    Simple,
+    /// Backups next to the file named `file.~1~`, `file.~2~`, ... keeping at
+    /// most `keep` of them when set.
+    Numbered { keep: Option<usize> },
+    /// Backups in `dir` named `file.20240131T235959Z` (UTC), keeping at most
+    /// `keep` per file name when set.
+    Directory { dir: PathBuf, keep: Option<usize> },
+}
+
+impl Backup {
+    /// Copy `path` to its backup location if it is an existing file, and
+    /// return where the backup went.
+    ///
+    /// Older backups beyond the retention limit are removed afterwards.
+    pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<Option<PathBuf>, Error> {
+        let path = path.as_ref();
+        if matches!(self, Backup::None) || !fs::metadata(path).is_ok_and(|meta| meta.is_file()) {
+            return Ok(None);
+        }
+        let name = path
+            .file_name()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "path has no file name"))?
+            .to_string_lossy()
+            .into_owned();
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        let target = match self {
+            Backup::None => unreachable!(),
+            Backup::Simple => parent.join(format!("{}~", name)),
+            Backup::Numbered { .. } => {
+                let next = numbered_backups(parent, &name)?.last().map_or(1, |n| n + 1);
+                parent.join(format!("{}.~{}~", name, next))
+            }
+            Backup::Directory { dir, .. } => {
+                crate::ensure_dir(dir)?;
+                let stamped = format!("{}.{}", name, format_utc_stamp(SystemTime::now()));
+                let mut target = dir.join(&stamped);
+                let mut extra = 1;
+                while target.exists() {
+                    target = dir.join(format!("{}.{}", stamped, extra));
+                    extra += 1;
+                }
+                target
+            }
+        };
+
+        copy_file(path, &target)?;
+        copy_times(path, &target)?;
+        self.prune(parent, &name)?;
+        Ok(Some(target))
+    }
+
+    /// Remove the oldest backups of `name` beyond the retention limit.
+    fn prune(&self, parent: &Path, name: &str) -> Result<(), Error> {
+        match self {
+            Backup::Numbered { keep: Some(keep) } => {
+                let numbers = numbered_backups(parent, name)?;
+                let excess = numbers.len().saturating_sub(*keep);
+                for number in &numbers[..excess] {
+                    crate::remove_file(parent.join(format!("{}.~{}~", name, number)))?;
+                }
+            }
+            Backup::Directory {
+                dir,
+                keep: Some(keep),
+            } => {
+                let prefix = format!("{}.", name);
+                let mut stamped = Vec::new();
+                for entry in fs::read_dir(dir)? {
+                    let file_name = entry?.file_name().to_string_lossy().into_owned();
+                    let order = file_name.strip_prefix(&prefix).and_then(stamp_order);
+                    if let Some((stamp, extra)) = order {
+                        stamped.push(((stamp.to_string(), extra), file_name));
+                    }
+                }
+                stamped.sort();
+                let excess = stamped.len().saturating_sub(*keep);
+                for (_, file_name) in &stamped[..excess] {
+                    crate::remove_file(dir.join(file_name))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Backup {
+    type Err = Error;
+
+    fn from_str(control: &str) -> Result<Backup, Error> {
+        match control {
+            "none" | "off" => Ok(Backup::None),
+            "simple" | "never" => Ok(Backup::Simple),
+            "numbered" | "t" => Ok(Backup::Numbered { keep: None }),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "invalid backup method '{}': expected none, simple or numbered",
+                    control
+                ),
+            )),
+        }
+    }
+}
+
+/// The numbers of the existing `name.~N~` backups in `parent`, ascending.
+fn numbered_backups(parent: &Path, name: &str) -> Result<Vec<u64>, Error> {
+    let prefix = format!("{}.~", name);
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(parent)? {
+        let file_name = entry?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let number = file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix('~'))
+            .and_then(|digits| digits.parse::<u64>().ok());
+        numbers.extend(number);
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+/// Where the backup stamped `text` sorts among its siblings: the stamp,
+/// then the `.N` suffix as a number (0 without one). `None` unless `text`
+/// is a backup stamp: `YYYYMMDDTHHMMSSZ`, optionally followed by `.N` for
+/// backups taken within the same second.
+fn stamp_order(text: &str) -> Option<(&str, u64)> {
+    let (stamp, extra) = text.split_once('.').unwrap_or((text, "0"));
+    let bytes = stamp.as_bytes();
+    let valid = bytes.len() == 16
+        && bytes[8] == b'T'
+        && bytes[15] == b'Z'
+        && bytes[..8]
+            .iter()
+            .chain(&bytes[9..15])
+            .all(u8::is_ascii_digit)
+        && !extra.is_empty()
+        && extra.bytes().all(|byte| byte.is_ascii_digit());
+    valid.then(|| Some((stamp, extra.parse().ok()?))).flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{ensure_dir, read_text, write_file};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("off".parse::<Backup>().unwrap(), Backup::None);
+        assert_eq!("simple".parse::<Backup>().unwrap(), Backup::Simple);
+        assert_eq!(
+            "t".parse::<Backup>().unwrap(),
+            Backup::Numbered { keep: None }
+        );
+        assert!("sometimes".parse::<Backup>().is_err());
+    }
+
+    #[test]
+    fn test_simple_and_missing() {
+        let guard = setup_test();
+        let file = format!("{}/config", guard.test_root);
+        assert_eq!(Backup::Simple.backup(&file).unwrap(), None);
+
+        write_file(&file, "one").unwrap();
+        Backup::Simple.backup(&file).unwrap();
+        write_file(&file, "two").unwrap();
+        let target = Backup::Simple.backup(&file).unwrap().unwrap();
+        assert_eq!(target, PathBuf::from(format!("{}~", file)));
+        assert_eq!(read_text(&target).unwrap(), "two");
+        assert_eq!(Backup::None.backup(&file).unwrap(), None);
+        assert_eq!(Backup::Simple.backup(&guard.test_root).unwrap(), None);
+    }
+
+    #[test]
+    fn test_numbered_retention() {
+        let guard = setup_test();
+        let file = format!("{}/state.json", guard.test_root);
+        let policy = Backup::Numbered { keep: Some(2) };
+        for version in 1..=4 {
+            write_file(&file, format!("v{}", version)).unwrap();
+            policy.backup(&file).unwrap();
+        }
+        assert_eq!(
+            numbered_backups(Path::new(&guard.test_root), "state.json").unwrap(),
+            [3, 4]
+        );
+        assert_eq!(read_text(format!("{}.~4~", file)).unwrap(), "v4");
+    }
+
+    #[test]
+    fn test_directory_retention() {
+        let guard = setup_test();
+        let file = format!("{}/app.conf", guard.test_root);
+        let dir = PathBuf::from(format!("{}/backups", guard.test_root));
+        let policy = Backup::Directory {
+            dir: dir.clone(),
+            keep: Some(2),
+        };
+        for version in 1..=3 {
+            write_file(&file, format!("v{}", version)).unwrap();
+            policy.backup(&file).unwrap();
+        }
+        write_file(dir.join("app.conf.notes"), "not a backup").unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 3);
+        assert!(stamp_order(names[0].strip_prefix("app.conf.").unwrap()).is_some());
+        assert_eq!(read_text(dir.join(&names[1])).unwrap(), "v3");
+        assert_eq!(names[2], "app.conf.notes");
+    }
+
+    #[test]
+    fn test_directory_retention_orders_suffixes_numerically() {
+        let guard = setup_test();
+        let file = format!("{}/app.conf", guard.test_root);
+        let dir = PathBuf::from(format!("{}/backups", guard.test_root));
+        let stamp = "20240131T235959Z";
+        write_file(&file, "current").unwrap();
+        ensure_dir(&dir).unwrap();
+        for extra in ["", ".2", ".10"] {
+            write_file(dir.join(format!("app.conf.{}{}", stamp, extra)), extra).unwrap();
+        }
+
+        let policy = Backup::Directory {
+            dir: dir.clone(),
+            keep: Some(2),
+        };
+        policy
+            .prune(Path::new(&guard.test_root), "app.conf")
+            .unwrap();
+        let mut names = crate::list_dir(&dir).unwrap();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                format!("app.conf.{}.10", stamp),
+                format!("app.conf.{}.2", stamp)
+            ]
+        );
+        assert_eq!(
+            stamp_order("20240131T235959Z.10"),
+            Some(("20240131T235959Z", 10))
+        );
+        assert_eq!(stamp_order("20240131T235959Z.x"), None);
+    }
+
+    #[test]
+    fn test_failed_operation_takes_no_backup() {
+        use crate::{CopyOptions, copy_file_with_backup, move_path_with_backup};
+
+        let guard = setup_test();
+        let file = format!("{}/target.txt", guard.test_root);
+        let missing = format!("{}/missing.txt", guard.test_root);
+        let policy = Backup::Numbered { keep: None };
+        write_file(&file, "kept").unwrap();
+
+        assert!(copy_file_with_backup(&missing, &file, &policy).is_err());
+        assert!(move_path_with_backup(&missing, &file, &policy).is_err());
+        let options = CopyOptions::new().backup(policy.clone());
+        assert!(options.copy(&missing, &file).is_err());
+        assert!(options.copy(&guard.test_root, &file).is_err());
+
+        assert_eq!(crate::list_dir(&guard.test_root).unwrap(), ["target.txt"]);
+        assert_eq!(read_text(&file).unwrap(), "kept");
+    }
+}
@@ -0,0 +1,191 @@
+use clap::Parser;
+use serde_json::{Value, json};
+use std::fs;
+use std::io;
+
+use crate::output::{EX_USAGE, Exit, Status, fail, output};
+use crate::{Commands, run};
+
+/// One command of a batch, parsed like the arguments after `soil`.
+#[derive(Parser)]
+#[command(name = "soil", no_binary_name = true)]
+struct Line {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+/// A command read from a batch: the line it came from (or its position in
+/// a JSON array) and its arguments.
+struct Operation {
+    line: usize,
+    args: Vec<String>,
+}
+
+/// Split batch input into operations, as a JSON array of argument arrays
+/// or line by line, along with the lines that cannot be read and why.
+fn read_operations(input: &str) -> (Vec<Operation>, Vec<(usize, String)>) {
+    if input.trim_start().starts_with('[')
+        && let Ok(Value::Array(items)) = serde_json::from_str(input)
+        && items.iter().all(Value::is_array)
+    {
+        let mut operations = Vec::new();
+        let mut errors = Vec::new();
+        for (index, item) in items.into_iter().enumerate() {
+            match serde_json::from_value(item) {
+                Ok(args) => operations.push(Operation {
+                    line: index + 1,
+                    args,
+                }),
+                Err(error) => errors.push((index + 1, error.to_string())),
+            }
+        }
+        return (operations, errors);
+    }
+
+    let mut operations = Vec::new();
+    let mut errors = Vec::new();
+    for (index, text) in input.lines().enumerate() {
+        let args = if text.trim_start().starts_with('[') {
+            serde_json::from_str(text).map_err(|error| error.to_string())
+        } else {
+            shlex::split(text).ok_or_else(|| "unbalanced quotes".to_string())
+        };
+        match args {
+            Ok(args) if args.is_empty() => {}
+            Ok(args) => operations.push(Operation {
+                line: index + 1,
+                args,
+            }),
+            Err(error) => errors.push((index + 1, error)),
+        }
+    }
+    (operations, errors)
+}
+
+pub(crate) fn run_batch(file: &str, keep_going: bool) -> Status {
+    let out = output();
+    let input = if file == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(file)
+    };
+    let input = input.map_err(|error| {
+        out.error(&format!("reading batch '{}'", file), Some(file), &error);
+        fail()
+    })?;
+
+    // Check every line first, so a typo near the end does not leave the
+    // work half done.
+    let (operations, mut errors) = read_operations(&input);
+    let mut commands = Vec::new();
+    for operation in operations {
+        match Line::try_parse_from(&operation.args) {
+            Ok(Line {
+                command: Commands::Batch { .. } | Commands::Shell { .. },
+            }) => errors.push((
+                operation.line,
+                "batch and shell cannot run in a batch".to_string(),
+            )),
+            Ok(line) => commands.push((operation, line.command)),
+            Err(error) => {
+                let message = error.render().to_string();
+                let message = message.lines().next().unwrap_or_default();
+                let message = message.strip_prefix("error: ").unwrap_or(message);
+                errors.push((operation.line, message.to_string()));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        for (line, message) in errors {
+            let error = io::Error::new(io::ErrorKind::InvalidInput, message);
+            out.error(
+                &format!("at line {} of '{}'", line, file),
+                Some(file),
+                &error,
+            );
+        }
+        return Err(Exit(EX_USAGE));
+    }
+
+    let total = commands.len();
+    let (mut ran, mut failed, mut first_failure) = (0, 0, 0);
+    for (operation, command) in commands {
+        let (status, records) = out.capture(|| run(command));
+        ran += 1;
+        out.record(json!({
+            "line": operation.line,
+            "args": operation.args,
+            "status": status,
+            "records": records,
+        }));
+        if status == 0 {
+            continue;
+        }
+        failed += 1;
+        if first_failure == 0 {
+            first_failure = status;
+        }
+        if out.is_text() {
+            eprintln!(
+                "soil: line {} failed with status {}",
+                operation.line, status
+            );
+        }
+        if !keep_going {
+            break;
+        }
+    }
+    if ran < total {
+        out.status(format_args!(
+            "Ran {} of {} commands, {} failed; stopped at the first failure",
+            ran, total, failed
+        ));
+    } else {
+        out.status(format_args!("Ran {} commands, {} failed", ran, failed));
+    }
+    Exit::with(first_failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{EX_IOERR, Format, OUTPUT, Output, reader_gone};
+    use std::process;
+
+    #[test]
+    fn test_read_operations() {
+        let input = "# comment\n\ncopy 'a b' c # trailing\n[\"rm\", \"x y\"]\nls \"open\n";
+        let (operations, errors) = read_operations(input);
+        let parsed: Vec<_> = operations
+            .iter()
+            .map(|op| (op.line, op.args.clone()))
+            .collect();
+        assert_eq!(
+            parsed,
+            [(3, vec!["copy", "a b", "c"]), (4, vec!["rm", "x y"])]
+                .map(|(line, args)| (line, args.into_iter().map(String::from).collect()))
+        );
+        assert_eq!(errors, [(5, "unbalanced quotes".to_string())]);
+
+        let (operations, errors) = read_operations("[[\"ls\"], [\"exists\", \"x\"]]");
+        assert!(errors.is_empty());
+        assert_eq!(operations[1].line, 2);
+        assert_eq!(operations[1].args, ["exists", "x"]);
+    }
+
+    #[test]
+    fn test_capture_returns_the_status() {
+        let _ = OUTPUT.set(Output::new(Format::Json, false, true));
+        let out = output();
+        let missing = std::env::temp_dir().join(format!("soil-capture-{}", process::id()));
+        let line = ["exists", &missing.to_string_lossy()];
+        let command = Line::try_parse_from(line).unwrap().command;
+
+        let (status, records) = out.capture(|| run(command));
+        assert_eq!(status, 1);
+        assert_eq!(records.len(), 1);
+        assert_eq!(out.capture(|| Err(reader_gone(0))).0, EX_IOERR);
+        assert_eq!(out.capture(|| Ok(())).0, 0);
+        assert_eq!(Exit::code(Err(reader_gone(0))), 0);
+    }
+}
@@ -0,0 +1,1008 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::backup::Backup;
+use crate::progress::Tracker;
+#[cfg(unix)]
+use crate::set_symlink_times;
+use crate::set_times;
+use crate::trace::TraceLevel::{All, Changes};
+use crate::trace::{emit, traced};
+
+const CHUNK_SIZE: usize = 128 * 1024;
+/// Bytes per `copy_file_range` call; small enough to report progress and
+/// notice cancellation regularly.
+#[cfg(target_os = "linux")]
+const KERNEL_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// How [`copy_file_with`] moves the bytes.
+///
+/// Every strategy except [`CopyStrategy::Auto`] is strict: if it cannot be
+/// used for the given pair of files the copy fails with
+/// [`ErrorKind::Unsupported`] instead of falling back, and the destination
+/// is left as it was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum CopyStrategy {
+    /// Try a reflink, then `copy_file_range`, then a userspace copy.
+    #[default]
+    Auto,
+    /// Share the source's extents copy-on-write (`FICLONE` on Linux,
+    /// `clonefile` on macOS). Instant, but needs btrfs, xfs, APFS or similar,
+    /// and both files on the same filesystem.
+    Reflink,
+    /// Let the kernel copy the data with `copy_file_range` (Linux only).
+    CopyFileRange,
+    /// Read and write in userspace, skipping runs of zeroes so holes in
+    /// sparse files stay holes.
+    Userspace,
+}
+
+impl fmt::Display for CopyStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CopyStrategy::Auto => "auto",
+            CopyStrategy::Reflink => "reflink",
+            CopyStrategy::CopyFileRange => "copy-file-range",
+            CopyStrategy::Userspace => "userspace",
+        })
+    }
+}
+
+impl FromStr for CopyStrategy {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<CopyStrategy, Error> {
+        match name {
+            "auto" => Ok(CopyStrategy::Auto),
+            "reflink" => Ok(CopyStrategy::Reflink),
+            "copy-file-range" => Ok(CopyStrategy::CopyFileRange),
+            "userspace" => Ok(CopyStrategy::Userspace),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "unknown copy strategy '{}' (expected auto, reflink, copy-file-range or userspace)",
+                    name
+                ),
+            )),
+        }
+    }
+}
+
+/// Copy a file from `src` to `dst` with an explicit [`CopyStrategy`].
+///
+/// Permission bits are copied along with the contents, like [`crate::copy_file`].
+/// Returns the strategy that actually did the work, which is never
+/// [`CopyStrategy::Auto`].
+///
+/// Examples
+/// ```
+/// use soil::{copy_file_with, write_file, read_text, remove_file, CopyStrategy};
+/// write_file("copy_with_src.txt", "content").unwrap();
+/// let used = copy_file_with("copy_with_src.txt", "copy_with_dst.txt", CopyStrategy::Userspace).unwrap();
+/// assert_eq!(used, CopyStrategy::Userspace);
+/// assert_eq!(read_text("copy_with_dst.txt").unwrap(), "content");
+///
+/// // Demanding a reflink either clones or fails; it never silently copies.
+/// match copy_file_with("copy_with_src.txt", "copy_with_clone.txt", CopyStrategy::Reflink) {
+///     Ok(used) => assert_eq!(used, CopyStrategy::Reflink),
+///     Err(error) => assert_eq!(error.kind(), std::io::ErrorKind::Unsupported),
+/// }
+/// remove_file("copy_with_src.txt").unwrap();
+/// remove_file("copy_with_dst.txt").unwrap();
+/// let _ = remove_file("copy_with_clone.txt");
+/// ```
+pub fn copy_file_with<P1: AsRef<Path>, P2: AsRef<Path>>(
+    src: P1,
+    dst: P2,
+    strategy: CopyStrategy,
+) -> Result<CopyStrategy, Error> {
+    copy_file_tracked(
+        src.as_ref(),
+        dst.as_ref(),
+        strategy,
+        true,
+        &Backup::None,
+        &mut Tracker::silent(),
+    )
+}
+
+/// [`copy_file_with`], feeding copied byte counts into `tracker` and
+/// checking it for cancellation between chunks. The permission bits are
+/// copied only when `keep_mode` is set.
+///
+/// `backup` is taken of an existing `dst` only once the source has been
+/// opened and checked, right before `dst` is overwritten.
+pub(crate) fn copy_file_tracked(
+    src: &Path,
+    dst: &Path,
+    strategy: CopyStrategy,
+    keep_mode: bool,
+    backup: &Backup,
+    tracker: &mut Tracker,
+) -> Result<CopyStrategy, Error> {
+    let mut source = traced(All, format_args!("open({:?})", src), File::open(src))?;
+    let source_metadata = source.metadata()?;
+    if !source_metadata.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{}' is not a regular file", src.display()),
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    if matches!(strategy, CopyStrategy::Auto | CopyStrategy::Reflink)
+        && clone_file(src, dst, backup)?
+    {
+        emit(Changes, format_args!("clonefile({:?}, {:?})", src, dst));
+        tracker.add_bytes(source_metadata.len())?;
+        return Ok(CopyStrategy::Reflink);
+    }
+
+    // A strict strategy may turn out not to work here, so it fills a
+    // temporary sibling and only replaces `dst` once it has succeeded.
+    let strict = matches!(
+        strategy,
+        CopyStrategy::Reflink | CopyStrategy::CopyFileRange
+    );
+    let target = if strict {
+        let mut staging = dst.as_os_str().to_owned();
+        staging.push(format!(".soil-copy-{}", std::process::id()));
+        PathBuf::from(staging)
+    } else {
+        backup.backup(dst)?;
+        dst.to_path_buf()
+    };
+    let open = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&target);
+    let mut destination = traced(
+        Changes,
+        format_args!("open({:?}, O_WRONLY|O_CREAT|O_TRUNC)", target),
+        open,
+    )?;
+
+    let result = (|| {
+        if keep_mode {
+            destination.set_permissions(source_metadata.permissions())?;
+        }
+        fill(&mut source, &mut destination, strategy, tracker)
+    })();
+    drop(destination);
+    let result = match result {
+        Ok(Some(used)) => Ok(traced_copy(used, src, dst)),
+        Ok(None) => Err(unsupported(strategy, src, dst)),
+        Err(error) => Err(error),
+    };
+    if strict {
+        match &result {
+            Ok(_) => {
+                let replaced = backup
+                    .backup(dst)
+                    .and_then(|_| crate::move_path(&target, dst));
+                if let Err(error) = replaced {
+                    let _ = crate::remove_file(&target);
+                    return Err(error);
+                }
+            }
+            Err(_) => {
+                let _ = crate::remove_file(&target);
+            }
+        }
+    }
+    result
+}
+
+/// Move the bytes of `source` into `destination` with `strategy`. Returns
+/// the strategy that did it, or `None` when a strict one cannot be used.
+fn fill(
+    source: &mut File,
+    destination: &mut File,
+    strategy: CopyStrategy,
+    tracker: &mut Tracker,
+) -> Result<Option<CopyStrategy>, Error> {
+    #[cfg(target_os = "linux")]
+    let len = source.metadata()?.len();
+    match strategy {
+        CopyStrategy::Auto => {
+            #[cfg(target_os = "linux")]
+            {
+                if reflink(source, destination)? {
+                    tracker.add_bytes(len)?;
+                    return Ok(Some(CopyStrategy::Reflink));
+                }
+                if copy_file_range(source, destination, tracker)? {
+                    return Ok(Some(CopyStrategy::CopyFileRange));
+                }
+            }
+            sparse_copy(source, destination, tracker)?;
+            Ok(Some(CopyStrategy::Userspace))
+        }
+        CopyStrategy::Reflink => {
+            #[cfg(target_os = "linux")]
+            if reflink(source, destination)? {
+                tracker.add_bytes(len)?;
+                return Ok(Some(CopyStrategy::Reflink));
+            }
+            Ok(None)
+        }
+        CopyStrategy::CopyFileRange => {
+            #[cfg(target_os = "linux")]
+            if copy_file_range(source, destination, tracker)? {
+                return Ok(Some(CopyStrategy::CopyFileRange));
+            }
+            Ok(None)
+        }
+        CopyStrategy::Userspace => {
+            sparse_copy(source, destination, tracker)?;
+            Ok(Some(CopyStrategy::Userspace))
+        }
+    }
+}
+
+/// Report which call moved the bytes from `src` to `dst`, and pass `used` on.
+fn traced_copy(used: CopyStrategy, src: &Path, dst: &Path) -> CopyStrategy {
+    let call = match used {
+        CopyStrategy::Reflink => "ioctl(FICLONE)",
+        CopyStrategy::CopyFileRange => "copy_file_range",
+        _ => "read/write",
+    };
+    emit(Changes, format_args!("{}({:?}, {:?})", call, src, dst));
+    used
+}
+
+/// Which metadata a copy carries over, as in `cp --preserve`.
+///
+/// Parses from a comma-separated list of `mode`, `timestamps`, `ownership`,
+/// `xattrs` and `links`, or `all`.
+///
+/// Examples
+/// ```
+/// use soil::Preserve;
+/// let preserve: Preserve = "mode,timestamps".parse().unwrap();
+/// assert!(preserve.mode && preserve.timestamps && !preserve.ownership);
+/// assert_eq!("all".parse::<Preserve>().unwrap(), Preserve::all());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Preserve {
+    /// Permission bits.
+    pub mode: bool,
+    /// Access and modification times.
+    pub timestamps: bool,
+    /// Owning user and group. Without the privilege to give files away, only
+    /// the group is kept, and only if the caller belongs to it.
+    pub ownership: bool,
+    /// Extended attributes (Linux and macOS). Skipped when the destination
+    /// filesystem does not support them.
+    pub xattrs: bool,
+    /// Symbolic links are copied as links instead of followed, and files
+    /// hard-linked together within a copied tree stay linked in the copy.
+    pub links: bool,
+}
+
+impl Preserve {
+    /// Everything, like `cp -a`.
+    pub fn all() -> Preserve {
+        Preserve {
+            mode: true,
+            timestamps: true,
+            ownership: true,
+            xattrs: true,
+            links: true,
+        }
+    }
+}
+
+impl FromStr for Preserve {
+    type Err = Error;
+
+    fn from_str(list: &str) -> Result<Preserve, Error> {
+        let mut preserve = Preserve::default();
+        for item in list.split(',') {
+            match item.trim() {
+                "mode" => preserve.mode = true,
+                "timestamps" => preserve.timestamps = true,
+                "ownership" => preserve.ownership = true,
+                "xattrs" | "xattr" => preserve.xattrs = true,
+                "links" => preserve.links = true,
+                "all" => preserve = Preserve::all(),
+                other => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "unknown attribute '{}' (expected mode, timestamps, ownership, xattrs, links or all)",
+                            other
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(preserve)
+    }
+}
+
+/// How [`CopyOptions::copy`] and [`Walker::copy_to_with`](crate::Walker::copy_to_with)
+/// copy files.
+///
+/// The default uses [`CopyStrategy::Auto`], keeps only the permission bits
+/// and follows symbolic links, like [`crate::copy_file`].
+///
+/// Examples
+/// ```
+/// use soil::{metadata, read_text, remove_file, set_times, write_file, CopyOptions, Timestamp};
+/// use std::time::{Duration, UNIX_EPOCH};
+/// write_file("copy_options_src.txt", "cached").unwrap();
+/// let mtime = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// set_times("copy_options_src.txt", Timestamp::Now, mtime).unwrap();
+///
+/// CopyOptions::archive().copy("copy_options_src.txt", "copy_options_dst.txt").unwrap();
+/// assert_eq!(read_text("copy_options_dst.txt").unwrap(), "cached");
+/// assert_eq!(metadata("copy_options_dst.txt").unwrap().modified().unwrap(), mtime);
+/// remove_file("copy_options_src.txt").unwrap();
+/// remove_file("copy_options_dst.txt").unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct CopyOptions {
+    pub(crate) strategy: CopyStrategy,
+    pub(crate) preserve: Preserve,
+    pub(crate) backup: Backup,
+}
+
+impl Default for CopyOptions {
+    fn default() -> CopyOptions {
+        CopyOptions {
+            strategy: CopyStrategy::Auto,
+            preserve: Preserve {
+                mode: true,
+                ..Preserve::default()
+            },
+            backup: Backup::None,
+        }
+    }
+}
+
+impl CopyOptions {
+    /// Automatic strategy, permission bits kept, links followed, no backup.
+    pub fn new() -> CopyOptions {
+        CopyOptions::default()
+    }
+
+    /// Preserve everything, like `cp -a`.
+    pub fn archive() -> CopyOptions {
+        CopyOptions::new().preserve(Preserve::all())
+    }
+
+    /// Move the bytes with this strategy.
+    pub fn strategy(mut self, strategy: CopyStrategy) -> CopyOptions {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Carry over this metadata.
+    pub fn preserve(mut self, preserve: Preserve) -> CopyOptions {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Back up an existing destination file first (see [`Backup`]). Tree
+    /// copies never overwrite, so they ignore this.
+    pub fn backup(mut self, backup: Backup) -> CopyOptions {
+        self.backup = backup;
+        self
+    }
+
+    /// Copy the file (or, when preserving links, the symbolic link) `src` to
+    /// `dst`.
+    pub fn copy<P1: AsRef<Path>, P2: AsRef<Path>>(&self, src: P1, dst: P2) -> Result<(), Error> {
+        self.copy_tracked(src.as_ref(), dst.as_ref(), &mut Tracker::silent())
+    }
+
+    /// [`CopyOptions::copy`], reporting to `tracker`.
+    pub(crate) fn copy_tracked(
+        &self,
+        src: &Path,
+        dst: &Path,
+        tracker: &mut Tracker,
+    ) -> Result<(), Error> {
+        let metadata = if self.preserve.links {
+            fs::symlink_metadata(src)?
+        } else {
+            fs::metadata(src)?
+        };
+        if metadata.file_type().is_symlink() {
+            self.backup.backup(dst)?;
+            if fs::symlink_metadata(dst).is_ok_and(|existing| !existing.is_dir()) {
+                crate::remove_file(dst)?;
+            }
+            copy_symlink(src, dst)?;
+        } else {
+            copy_file_tracked(
+                src,
+                dst,
+                self.strategy,
+                self.preserve.mode,
+                &self.backup,
+                tracker,
+            )?;
+        }
+        self.copy_metadata(src, dst, &metadata)
+    }
+
+    /// Apply the preserved metadata of `src` (described by `metadata`, which
+    /// must not follow a link that is being copied as a link) to `dst`.
+    ///
+    /// Ownership goes before the mode, since changing the owner clears the
+    /// setuid and setgid bits, and the times go last.
+    pub(crate) fn copy_metadata(
+        &self,
+        src: &Path,
+        dst: &Path,
+        metadata: &fs::Metadata,
+    ) -> Result<(), Error> {
+        let is_link = metadata.file_type().is_symlink();
+        if self.preserve.xattrs {
+            copy_xattrs(src, dst)?;
+        }
+        #[cfg(unix)]
+        if self.preserve.ownership {
+            copy_owner(dst, metadata)?;
+        }
+        if self.preserve.mode && !is_link {
+            crate::set_permissions(dst, metadata.permissions())?;
+        }
+        if self.preserve.timestamps {
+            let (accessed, modified) = (metadata.accessed()?, metadata.modified()?);
+            if !is_link {
+                set_times(dst, accessed, modified)?;
+            }
+            #[cfg(unix)]
+            if is_link {
+                set_symlink_times(dst, accessed, modified)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Give `dst` the owner and group from `metadata`, or just the group when
+/// the caller may not give files away.
+#[cfg(unix)]
+fn copy_owner(dst: &Path, metadata: &fs::Metadata) -> Result<(), Error> {
+    use std::os::unix::fs::{MetadataExt, lchown};
+
+    let owner = lchown(dst, Some(metadata.uid()), Some(metadata.gid()));
+    let call = format_args!("lchown({:?}, {}, {})", dst, metadata.uid(), metadata.gid());
+    match traced(Changes, call, owner) {
+        Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+            let _ = lchown(dst, None, Some(metadata.gid()));
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Copy the extended attributes of `src` to `dst`, without following links.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_xattrs(src: &Path, dst: &Path) -> Result<(), Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = CString::new(src.as_os_str().as_bytes())?;
+    let dst = CString::new(dst.as_os_str().as_bytes())?;
+    let names = match read_sized(|buf, size| xattr::list(&src, buf, size)) {
+        Err(error) if error.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+        result => result?,
+    };
+    for name in names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let name = CString::new(name)?;
+        let value = read_sized(|buf, size| xattr::get(&src, &name, buf, size))?;
+        let set = xattr::set(&dst, &name, &value);
+        if let Err(error) = traced(
+            Changes,
+            format_args!("setxattr({:?}, {:?})", dst, name),
+            set,
+        ) {
+            if error.raw_os_error() == Some(libc::ENOTSUP) {
+                return Ok(());
+            }
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn copy_xattrs(_src: &Path, _dst: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Call a size-probing attribute function until the buffer is big enough:
+/// first with an empty buffer to learn the size, then for real, retrying if
+/// the value grew in between.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn read_sized(
+    mut call: impl FnMut(*mut libc::c_void, usize) -> libc::ssize_t,
+) -> Result<Vec<u8>, Error> {
+    loop {
+        let size = call(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let read = call(buf.as_mut_ptr().cast(), buf.len());
+        if read >= 0 {
+            buf.truncate(read as usize);
+            return Ok(buf);
+        }
+        let error = Error::last_os_error();
+        if error.raw_os_error() != Some(libc::ERANGE) {
+            return Err(error);
+        }
+    }
+}
+
+/// Thin wrappers over the platform's no-follow attribute calls.
+#[cfg(target_os = "linux")]
+mod xattr {
+    use std::ffi::CStr;
+    use std::io::Error;
+
+    pub(super) fn list(path: &CStr, buf: *mut libc::c_void, size: usize) -> libc::ssize_t {
+        // SAFETY: `buf` is null with size 0 or points to `size` writable bytes.
+        unsafe { libc::llistxattr(path.as_ptr(), buf.cast(), size) }
+    }
+
+    pub(super) fn get(
+        path: &CStr,
+        name: &CStr,
+        buf: *mut libc::c_void,
+        size: usize,
+    ) -> libc::ssize_t {
+        // SAFETY: as for `list`.
+        unsafe { libc::lgetxattr(path.as_ptr(), name.as_ptr(), buf, size) }
+    }
+
+    pub(super) fn set(path: &CStr, name: &CStr, value: &[u8]) -> Result<(), Error> {
+        // SAFETY: `value` is a live slice of the given length.
+        let result = unsafe {
+            libc::lsetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod xattr {
+    use std::ffi::CStr;
+    use std::io::Error;
+
+    pub(super) fn list(path: &CStr, buf: *mut libc::c_void, size: usize) -> libc::ssize_t {
+        // SAFETY: `buf` is null with size 0 or points to `size` writable bytes.
+        unsafe { libc::listxattr(path.as_ptr(), buf.cast(), size, libc::XATTR_NOFOLLOW) }
+    }
+
+    pub(super) fn get(
+        path: &CStr,
+        name: &CStr,
+        buf: *mut libc::c_void,
+        size: usize,
+    ) -> libc::ssize_t {
+        // SAFETY: as for `list`.
+        unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buf,
+                size,
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        }
+    }
+
+    pub(super) fn set(path: &CStr, name: &CStr, value: &[u8]) -> Result<(), Error> {
+        // SAFETY: `value` is a live slice of the given length.
+        let result = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        };
+        if result < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn unsupported(strategy: CopyStrategy, src: &Path, dst: &Path) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!(
+            "{} is not supported for '{}' -> '{}'",
+            strategy,
+            src.display(),
+            dst.display()
+        ),
+    )
+}
+
+/// Whether a failed fast-path syscall just means "not here", so the caller
+/// may fall back to something slower.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn is_unsupported(error: &Error) -> bool {
+    #[cfg(target_os = "macos")]
+    if error.raw_os_error() == Some(libc::ENOTSUP) {
+        return true;
+    }
+    matches!(
+        error.raw_os_error(),
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EXDEV | libc::EINVAL | libc::ENOSYS)
+    )
+}
+
+/// Clone `source` into `destination` with `FICLONE`. Returns `false` when
+/// the filesystem cannot do it.
+#[cfg(target_os = "linux")]
+fn reflink(source: &File, destination: &File) -> Result<bool, Error> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: both descriptors are open for the duration of the call.
+    let result = unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if result == 0 {
+        return Ok(true);
+    }
+    let error = Error::last_os_error();
+    if is_unsupported(&error) {
+        Ok(false)
+    } else {
+        Err(error)
+    }
+}
+
+/// Clone `src` to `dst` with `clonefile`, replacing `dst` atomically after
+/// taking `backup` of it. Returns `false` when the filesystem cannot do it.
+#[cfg(target_os = "macos")]
+fn clone_file(src: &Path, dst: &Path, backup: &Backup) -> Result<bool, Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut staging = dst.as_os_str().to_owned();
+    staging.push(format!(".soil-clone-{}", std::process::id()));
+    let c_src = CString::new(src.as_os_str().as_bytes())
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+    let c_staging = CString::new(staging.as_bytes())
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+
+    // SAFETY: both paths are valid NUL-terminated strings.
+    let result = unsafe { libc::clonefile(c_src.as_ptr(), c_staging.as_ptr(), 0) };
+    if result != 0 {
+        let error = Error::last_os_error();
+        return if is_unsupported(&error) {
+            Ok(false)
+        } else {
+            Err(error)
+        };
+    }
+
+    if let Err(error) = backup.backup(dst).and_then(|_| fs::rename(&staging, dst)) {
+        let _ = fs::remove_file(&staging);
+        return Err(error);
+    }
+    Ok(true)
+}
+
+/// Copy with `copy_file_range`. Returns `false` if the kernel refused before
+/// any data was copied.
+#[cfg(target_os = "linux")]
+fn copy_file_range(
+    source: &File,
+    destination: &File,
+    tracker: &mut Tracker,
+) -> Result<bool, Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut copied: u64 = 0;
+    loop {
+        // SAFETY: both descriptors are open; null offsets make the kernel
+        // use and advance the file positions.
+        let result = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                std::ptr::null_mut(),
+                destination.as_raw_fd(),
+                std::ptr::null_mut(),
+                KERNEL_CHUNK_SIZE,
+                0,
+            )
+        };
+        match result {
+            0 => return Ok(true),
+            n if n > 0 => {
+                copied += n as u64;
+                tracker.add_bytes(n as u64)?;
+            }
+            _ => {
+                let error = Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                if copied == 0 && is_unsupported(&error) {
+                    return Ok(false);
+                }
+                return Err(error);
+            }
+        }
+    }
+}
+
+/// Copy through a userspace buffer, seeking over all-zero chunks so the
+/// destination gets holes where the source had them.
+fn sparse_copy(
+    source: &mut File,
+    destination: &mut File,
+    tracker: &mut Tracker,
+) -> Result<u64, Error> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut total: u64 = 0;
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        let chunk = &buffer[..read];
+        if chunk.iter().all(|&byte| byte == 0) {
+            destination.seek(SeekFrom::Current(read as i64))?;
+        } else {
+            destination.write_all(chunk)?;
+        }
+        total += read as u64;
+        tracker.add_bytes(read as u64)?;
+    }
+    // A trailing hole leaves the file short; extend it to the full size.
+    destination.set_len(total)?;
+    Ok(total)
+}
+
+/// Recreate the symbolic link `from` at `to`, pointing at the same target.
+#[cfg(unix)]
+pub(crate) fn copy_symlink(from: &Path, to: &Path) -> Result<(), Error> {
+    crate::create_symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(windows)]
+pub(crate) fn copy_symlink(from: &Path, to: &Path) -> Result<(), Error> {
+    let target = fs::read_link(from)?;
+    if fs::metadata(from).map(|md| md.is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+
+    #[test]
+    fn test_strategy_round_trip() {
+        for strategy in [
+            CopyStrategy::Auto,
+            CopyStrategy::Reflink,
+            CopyStrategy::CopyFileRange,
+            CopyStrategy::Userspace,
+        ] {
+            assert_eq!(
+                strategy.to_string().parse::<CopyStrategy>().unwrap(),
+                strategy
+            );
+        }
+        assert!("fast".parse::<CopyStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_copy_strategies() {
+        let guard = setup_test();
+        let src = &*format!("{}/strategy_src.bin", guard.test_root);
+        let content: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(src, &content).unwrap();
+
+        for strategy in [
+            CopyStrategy::Auto,
+            CopyStrategy::Reflink,
+            CopyStrategy::CopyFileRange,
+            CopyStrategy::Userspace,
+        ] {
+            let dst = format!("{}/strategy_{}.bin", guard.test_root, strategy);
+            match copy_file_with(src, &dst, strategy) {
+                Ok(used) => {
+                    assert_ne!(used, CopyStrategy::Auto);
+                    if strategy != CopyStrategy::Auto {
+                        assert_eq!(used, strategy);
+                    }
+                    assert_eq!(fs::read(&dst).unwrap(), content);
+                }
+                Err(error) => {
+                    assert_ne!(strategy, CopyStrategy::Auto);
+                    assert_ne!(strategy, CopyStrategy::Userspace);
+                    assert_eq!(error.kind(), ErrorKind::Unsupported);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_copy_keeps_size_and_content() {
+        let guard = setup_test();
+        let src = &*format!("{}/sparse_src.bin", guard.test_root);
+        let dst = &*format!("{}/sparse_dst.bin", guard.test_root);
+
+        let mut file = File::create(src).unwrap();
+        file.write_all(b"head").unwrap();
+        file.seek(SeekFrom::Start(4 * CHUNK_SIZE as u64)).unwrap();
+        file.write_all(b"middle").unwrap();
+        file.set_len(8 * CHUNK_SIZE as u64).unwrap();
+        drop(file);
+
+        // Overwrite a longer existing file to make sure it gets truncated.
+        fs::write(dst, vec![1u8; 10 * CHUNK_SIZE]).unwrap();
+        copy_file_with(src, dst, CopyStrategy::Userspace).unwrap();
+        assert_eq!(fs::read(dst).unwrap(), fs::read(src).unwrap());
+    }
+
+    #[test]
+    fn test_failed_strict_copy_leaves_destination_alone() {
+        let guard = setup_test();
+        let src = &*format!("{}/strict_src.txt", guard.test_root);
+        let kept = &*format!("{}/strict_kept.txt", guard.test_root);
+        let fresh = &*format!("{}/strict_fresh.txt", guard.test_root);
+        fs::write(src, "new").unwrap();
+
+        for strategy in [CopyStrategy::Reflink, CopyStrategy::CopyFileRange] {
+            fs::write(kept, "precious").unwrap();
+            match copy_file_with(src, kept, strategy) {
+                Ok(_) => assert_eq!(fs::read_to_string(kept).unwrap(), "new"),
+                Err(error) => {
+                    assert_eq!(error.kind(), ErrorKind::Unsupported);
+                    assert_eq!(fs::read_to_string(kept).unwrap(), "precious");
+                }
+            }
+            match copy_file_with(src, fresh, strategy) {
+                Ok(_) => crate::remove_file(fresh).unwrap(),
+                Err(_) => assert!(!Path::new(fresh).exists()),
+            }
+        }
+
+        // A source that cannot be copied at all leaves nothing behind either.
+        let error = copy_file_with(&guard.test_root, fresh, CopyStrategy::Reflink).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let mut names = crate::list_dir(&guard.test_root).unwrap();
+        names.sort();
+        assert_eq!(names, ["strict_kept.txt", "strict_src.txt"]);
+    }
+
+    #[test]
+    fn test_preserve_parse() {
+        let preserve: Preserve = "links, xattr".parse().unwrap();
+        assert!(preserve.links && preserve.xattrs && !preserve.mode);
+        assert!("mode,colour".parse::<Preserve>().is_err());
+    }
+
+    #[test]
+    fn test_copy_options_timestamps_and_mode() {
+        use crate::{Timestamp, set_times};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let guard = setup_test();
+        let src = &*format!("{}/options_src.txt", guard.test_root);
+        let dst = &*format!("{}/options_dst.txt", guard.test_root);
+        fs::write(src, "content").unwrap();
+        let mtime = UNIX_EPOCH + Duration::new(1_500_000_000, 500);
+        set_times(src, Timestamp::Now, mtime).unwrap();
+
+        CopyOptions::new().copy(src, dst).unwrap();
+        assert_ne!(fs::metadata(dst).unwrap().modified().unwrap(), mtime);
+
+        let timestamps = Preserve {
+            timestamps: true,
+            ..Preserve::default()
+        };
+        CopyOptions::new()
+            .preserve(timestamps)
+            .copy(src, dst)
+            .unwrap();
+        assert_eq!(fs::metadata(dst).unwrap().modified().unwrap(), mtime);
+        assert_eq!(fs::read_to_string(dst).unwrap(), "content");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_options_links_and_ownership() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt, symlink};
+
+        let guard = setup_test();
+        let target = &*format!("{}/target.sh", guard.test_root);
+        let link = &*format!("{}/link.sh", guard.test_root);
+        let followed = &*format!("{}/followed.sh", guard.test_root);
+        let kept = &*format!("{}/kept.sh", guard.test_root);
+        fs::write(target, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(target, fs::Permissions::from_mode(0o750)).unwrap();
+        symlink("target.sh", link).unwrap();
+
+        CopyOptions::new().copy(link, followed).unwrap();
+        let metadata = fs::symlink_metadata(followed).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.mode() & 0o777, 0o750);
+
+        CopyOptions::archive().copy(link, kept).unwrap();
+        assert_eq!(fs::read_link(kept).unwrap(), Path::new("target.sh"));
+        // Copying over an existing link replaces it.
+        CopyOptions::archive().copy(link, kept).unwrap();
+
+        let copy = &*format!("{}/owned.sh", guard.test_root);
+        CopyOptions::archive().copy(target, copy).unwrap();
+        let (source, copied) = (fs::metadata(target).unwrap(), fs::metadata(copy).unwrap());
+        assert_eq!((copied.uid(), copied.gid()), (source.uid(), source.gid()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_copy_options_xattrs() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let guard = setup_test();
+        let src = &*format!("{}/xattr_src", guard.test_root);
+        let dst = &*format!("{}/xattr_dst", guard.test_root);
+        fs::write(src, "x").unwrap();
+        let path = CString::new(Path::new(src).as_os_str().as_bytes()).unwrap();
+        let name = CString::new("user.soil.test").unwrap();
+        // SAFETY: valid C strings and a live value slice.
+        let set =
+            unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), b"42".as_ptr().cast(), 2, 0) };
+        if set != 0 {
+            // The filesystem under the test root has no user xattrs.
+            return;
+        }
+
+        let xattrs = Preserve {
+            xattrs: true,
+            ..Preserve::default()
+        };
+        CopyOptions::new().preserve(xattrs).copy(src, dst).unwrap();
+        let dst = CString::new(Path::new(dst).as_os_str().as_bytes()).unwrap();
+        let value = read_sized(|buf, size| xattr::get(&dst, &name, buf, size)).unwrap();
+        assert_eq!(value, b"42");
+    }
+
+    #[test]
+    fn test_copy_rejects_directories() {
+        let guard = setup_test();
+        let dst = &*format!("{}/dir_copy", guard.test_root);
+        let error = copy_file_with(&guard.test_root, dst, CopyStrategy::Auto).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt::Write;
+
+/// One step of a line diff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Render a unified diff between two texts, like `diff -u`.
+///
+/// `old_name` and `new_name` label the `---` and `+++` headers and
+/// `context` is the number of unchanged lines kept around each change.
+/// Identical texts give an empty string.
+///
+/// Examples
+/// ```
+/// use soil::unified_diff;
+/// let diff = unified_diff("a\nb\nc\n", "a\nB\nc\n", "a/file", "b/file", 1);
+/// assert_eq!(diff, "--- a/file\n+++ b/file\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n");
+/// ```
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    context: usize,
+) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(..)))
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Line positions in both texts before each edit.
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_at, mut new_at) = (0, 0);
+    for edit in &edits {
+        positions.push((old_at, new_at));
+        match edit {
+            Edit::Equal(..) => {
+                old_at += 1;
+                new_at += 1;
+            }
+            Edit::Delete(_) => old_at += 1,
+            Edit::Insert(_) => new_at += 1,
+        }
+    }
+    positions.push((old_at, new_at));
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let mut index = 0;
+    while index < changes.len() {
+        let start = changes[index].saturating_sub(context);
+        let mut end = (changes[index] + context + 1).min(edits.len());
+        index += 1;
+        // Merge changes whose context would touch or overlap.
+        while index < changes.len() && changes[index].saturating_sub(context) <= end {
+            end = (changes[index] + context + 1).min(edits.len());
+            index += 1;
+        }
+
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let header_start = |at: usize, count: usize| if count == 0 { at } else { at + 1 };
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            header_start(old_start, old_end - old_start),
+            old_end - old_start,
+            header_start(new_start, new_end - new_start),
+            new_end - new_start
+        );
+        for edit in &edits[start..end] {
+            let (marker, line) = match *edit {
+                Edit::Equal(old_index, _) => (' ', old_lines[old_index]),
+                Edit::Delete(old_index) => ('-', old_lines[old_index]),
+                Edit::Insert(new_index) => ('+', new_lines[new_index]),
+            };
+            out.push(marker);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// Shortest edit script between two line lists (Myers' algorithm), after
+/// trimming the common prefix and suffix.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    let middle = myers(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    edits.extend(middle.into_iter().map(|edit| match edit {
+        Edit::Equal(a, b) => Edit::Equal(a + prefix, b + prefix),
+        Edit::Delete(a) => Edit::Delete(a + prefix),
+        Edit::Insert(b) => Edit::Insert(b + prefix),
+    }));
+    let (old_tail, new_tail) = (old.len() - suffix, new.len() - suffix);
+    edits.extend((0..suffix).map(|i| Edit::Equal(old_tail + i, new_tail + i)));
+    edits
+}
+
+fn myers(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut frontier = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(frontier.clone());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| (k + offset) as usize;
+            let mut x = if k == -d || (k != d && frontier[at(k - 1)] < frontier[at(k + 1)]) {
+                frontier[at(k + 1)]
+            } else {
+                frontier[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            frontier[at(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk the recorded frontiers backwards to recover the path.
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, frontier) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| (k + offset) as usize;
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && frontier[at(k - 1)] < frontier[at(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = frontier[at(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == previous_x {
+                edits.push(Edit::Insert(previous_y as usize));
+            } else {
+                edits.push(Edit::Delete(previous_x as usize));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "x", "y", 3), "");
+        assert_eq!(unified_diff("", "", "x", "y", 3), "");
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        assert_eq!(
+            unified_diff("", "new\n", "a", "b", 3),
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+new\n"
+        );
+        assert_eq!(
+            unified_diff("a\nb\nc\n", "a\nc\n", "a", "b", 3),
+            "--- a\n+++ b\n@@ -1,3 +1,2 @@\n a\n-b\n c\n"
+        );
+    }
+
+    #[test]
+    fn test_separate_hunks() {
+        let old: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+        let new: String = (1..=20)
+            .map(|n| match n {
+                3 => "three\n".to_string(),
+                18 => "eighteen\n".to_string(),
+                n => format!("{}\n", n),
+            })
+            .collect();
+        let diff = unified_diff(&old, &new, "a", "b", 1);
+        assert_eq!(
+            diff,
+            "--- a\n+++ b\n\
+             @@ -2,3 +2,3 @@\n 2\n-3\n+three\n 4\n\
+             @@ -17,3 +17,3 @@\n 17\n-18\n+eighteen\n 19\n"
+        );
+        // With more context the two hunks merge into one.
+        assert_eq!(
+            unified_diff(&old, &new, "a", "b", 8)
+                .matches("@@ -")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_missing_final_newline() {
+        assert_eq!(
+            unified_diff("a\nb", "a\nc", "a", "b", 3),
+            "--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_shortest_script() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let edits = myers(&old, &new);
+        let changes = edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Equal(..)))
+            .count();
+        assert_eq!(changes, 5);
+    }
+}
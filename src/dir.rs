@@ -0,0 +1,500 @@
+use std::ffi::{CStr, CString, OsString};
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::trace::TraceLevel::{All, Changes};
+use crate::trace::traced;
+use crate::{EntryType, Mode};
+
+/// An open directory, with operations relative to it rather than to a path.
+///
+/// Every method uses the `*at` system calls on the directory's file
+/// descriptor, so a sequence of operations keeps working on the same
+/// directory even if it, or any directory above it, is renamed or replaced
+/// in the meantime. Names are resolved relative to the directory; a name
+/// containing `/` is allowed and resolved from there.
+///
+/// The path the directory was opened by is kept only for error messages.
+///
+/// Examples
+/// ```
+/// use soil::{ensure_dir, remove_dir_all, Dir, EntryType};
+/// use std::io::{Read, Write};
+/// ensure_dir("dir_example").unwrap();
+/// let dir = Dir::open("dir_example").unwrap();
+///
+/// // Renaming the directory does not disturb the handle.
+/// std::fs::rename("dir_example", "dir_example_moved").unwrap();
+/// dir.create_file("notes.txt").unwrap().write_all(b"hello").unwrap();
+/// dir.create_dir("sub").unwrap();
+/// dir.symlink("notes.txt", "link").unwrap();
+/// assert_eq!(dir.list().unwrap(), ["link", "notes.txt", "sub"]);
+/// assert_eq!(dir.stat("link").unwrap().kind, EntryType::Symlink);
+///
+/// let sub = dir.open_dir("sub").unwrap();
+/// dir.rename_to("notes.txt", &sub, "moved.txt").unwrap();
+/// let mut text = String::new();
+/// sub.open_file("moved.txt").unwrap().read_to_string(&mut text).unwrap();
+/// assert_eq!(text, "hello");
+/// dir.remove("link").unwrap();
+/// remove_dir_all("dir_example_moved").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Dir {
+    fd: OwnedFd,
+    path: PathBuf,
+}
+
+/// What [`Dir::stat`] reports about an entry. Symbolic links are not
+/// followed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirStat {
+    /// The kind of entry.
+    pub kind: EntryType,
+    /// Permission bits, including setuid, setgid and sticky.
+    pub mode: Mode,
+    /// Size in bytes.
+    pub size: u64,
+    /// Owning user id.
+    pub uid: u32,
+    /// Owning group id.
+    pub gid: u32,
+    /// Device the entry lives on.
+    pub dev: u64,
+    /// Inode number on that device.
+    pub ino: u64,
+    /// Number of hard links.
+    pub nlink: u64,
+    /// Last modification time.
+    pub modified: SystemTime,
+}
+
+impl Dir {
+    /// Open the directory at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Dir, Error> {
+        let path = path.as_ref();
+        let c_path = c_name(path)?;
+        // SAFETY: `c_path` is a valid NUL-terminated string.
+        let fd = unsafe {
+            libc::open(
+                c_path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        Ok(Dir {
+            fd: owned(fd).map_err(|error| with_path(path, error))?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The path this directory was opened by. It may no longer lead here.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Open the subdirectory `name`. A symbolic link as the last component
+    /// is not followed.
+    pub fn open_dir<P: AsRef<Path>>(&self, name: P) -> Result<Dir, Error> {
+        let name = name.as_ref();
+        let fd = traced(
+            All,
+            format_args!("openat({:?}, {:?}, O_DIRECTORY)", self.path, name),
+            self.open_at(
+                name,
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW,
+                0,
+            ),
+        )?;
+        Ok(Dir {
+            fd,
+            path: self.path.join(name),
+        })
+    }
+
+    /// Open the file `name` for reading.
+    pub fn open_file<P: AsRef<Path>>(&self, name: P) -> Result<File, Error> {
+        let name = name.as_ref();
+        Ok(File::from(traced(
+            All,
+            format_args!("openat({:?}, {:?}, O_RDONLY)", self.path, name),
+            self.open_at(name, libc::O_RDONLY, 0),
+        )?))
+    }
+
+    /// Create the file `name`, or truncate it if it exists, and open it for
+    /// writing.
+    pub fn create_file<P: AsRef<Path>>(&self, name: P) -> Result<File, Error> {
+        let name = name.as_ref();
+        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+        Ok(File::from(traced(
+            Changes,
+            format_args!(
+                "openat({:?}, {:?}, O_WRONLY|O_CREAT|O_TRUNC)",
+                self.path, name
+            ),
+            self.open_at(name, flags, 0o666),
+        )?))
+    }
+
+    /// Create the directory `name`. Fails if it already exists.
+    pub fn create_dir<P: AsRef<Path>>(&self, name: P) -> Result<(), Error> {
+        let name = name.as_ref();
+        let c_name = c_name(name)?;
+        // SAFETY: the descriptor is open and `c_name` is NUL-terminated.
+        let result = unsafe { libc::mkdirat(self.fd.as_raw_fd(), c_name.as_ptr(), 0o777) };
+        traced(
+            Changes,
+            format_args!("mkdirat({:?}, {:?})", self.path, name),
+            self.check(result, name),
+        )
+    }
+
+    /// Remove the entry `name`: a file, a symbolic link (not its target) or
+    /// an empty directory.
+    pub fn remove<P: AsRef<Path>>(&self, name: P) -> Result<(), Error> {
+        let name = name.as_ref();
+        traced(
+            Changes,
+            format_args!("unlinkat({:?}, {:?})", self.path, name),
+            self.unlink(name),
+        )
+    }
+
+    /// [`Dir::remove`], untraced.
+    fn unlink(&self, name: &Path) -> Result<(), Error> {
+        let c_name = c_name(name)?;
+        let fd = self.fd.as_raw_fd();
+        // SAFETY: the descriptor is open and `c_name` is NUL-terminated.
+        let result = unsafe { libc::unlinkat(fd, c_name.as_ptr(), 0) };
+        if result == 0 {
+            return Ok(());
+        }
+        // Linux reports a directory with EISDIR, POSIX allows EPERM.
+        let error = Error::last_os_error();
+        if !matches!(error.raw_os_error(), Some(libc::EISDIR | libc::EPERM)) {
+            return Err(self.error(name, error));
+        }
+        // SAFETY: as above.
+        let result = unsafe { libc::unlinkat(fd, c_name.as_ptr(), libc::AT_REMOVEDIR) };
+        if result == 0 {
+            Ok(())
+        } else {
+            // Not a directory after all: the first error is the real one.
+            let retry = Error::last_os_error();
+            let error = if retry.raw_os_error() == Some(libc::ENOTDIR) {
+                error
+            } else {
+                retry
+            };
+            Err(self.error(name, error))
+        }
+    }
+
+    /// Move the entry `name` into `to_dir` as `to_name`, replacing an
+    /// existing file there (like `rename`). `to_dir` may be this directory.
+    pub fn rename_to<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        name: P,
+        to_dir: &Dir,
+        to_name: Q,
+    ) -> Result<(), Error> {
+        let name = name.as_ref();
+        let from = c_name(name)?;
+        let to = c_name(to_name.as_ref())?;
+        // SAFETY: both descriptors are open and both names NUL-terminated.
+        let result = unsafe {
+            libc::renameat(
+                self.fd.as_raw_fd(),
+                from.as_ptr(),
+                to_dir.fd.as_raw_fd(),
+                to.as_ptr(),
+            )
+        };
+        let call = format_args!(
+            "renameat({:?}, {:?}, {:?}, {:?})",
+            self.path,
+            name,
+            to_dir.path,
+            to_name.as_ref()
+        );
+        traced(Changes, call, self.check(result, name))
+    }
+
+    /// Create a symbolic link `name` pointing to `target`. The target is
+    /// stored as given; a relative one is resolved from this directory when
+    /// the link is followed.
+    pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, target: P, name: Q) -> Result<(), Error> {
+        let name = name.as_ref();
+        let c_target = c_name(target.as_ref())?;
+        let c_name = c_name(name)?;
+        // SAFETY: the descriptor is open and both strings are NUL-terminated.
+        let result =
+            unsafe { libc::symlinkat(c_target.as_ptr(), self.fd.as_raw_fd(), c_name.as_ptr()) };
+        let call = format_args!(
+            "symlinkat({:?}, {:?}, {:?})",
+            target.as_ref(),
+            self.path,
+            name
+        );
+        traced(Changes, call, self.check(result, name))
+    }
+
+    /// Information about the entry `name`, without following a symbolic
+    /// link.
+    pub fn stat<P: AsRef<Path>>(&self, name: P) -> Result<DirStat, Error> {
+        let name = name.as_ref();
+        let c_name = c_name(name)?;
+        let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+        // SAFETY: the descriptor is open, `c_name` is NUL-terminated and
+        // `stat` has room for the structure fstatat fills in.
+        let result = unsafe {
+            libc::fstatat(
+                self.fd.as_raw_fd(),
+                c_name.as_ptr(),
+                stat.as_mut_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        traced(
+            All,
+            format_args!("fstatat({:?}, {:?})", self.path, name),
+            self.check(result, name),
+        )?;
+        // SAFETY: fstatat succeeded, so it filled in `stat`.
+        Ok(DirStat::from(unsafe { stat.assume_init() }))
+    }
+
+    /// The names of the entries in this directory, sorted, without `.` and
+    /// `..`.
+    pub fn list(&self) -> Result<Vec<OsString>, Error> {
+        let fd = self
+            .fd
+            .try_clone()
+            .map_err(|error| self.error(Path::new(""), error))?;
+        // SAFETY: `fd` is an open directory descriptor whose ownership
+        // passes to the stream; closedir below closes it.
+        let stream = unsafe { libc::fdopendir(fd.as_raw_fd()) };
+        if stream.is_null() {
+            return Err(self.error(Path::new(""), Error::last_os_error()));
+        }
+        std::mem::forget(fd);
+
+        // The duplicate shares its read position with earlier listings.
+        // SAFETY: `stream` is a valid directory stream until closed.
+        unsafe { libc::rewinddir(stream) };
+        let mut names = Vec::new();
+        let result = loop {
+            // readdir signals errors only through errno.
+            set_errno(0);
+            // SAFETY: `stream` is a valid directory stream.
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                let error = Error::last_os_error();
+                break match error.raw_os_error() {
+                    Some(0) => Ok(()),
+                    _ => Err(error),
+                };
+            }
+            // SAFETY: readdir returned an entry whose name is NUL-terminated
+            // and valid until the next readdir call.
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) }.to_bytes();
+            if name != b"." && name != b".." {
+                names.push(OsString::from_vec(name.to_vec()));
+            }
+        };
+        // SAFETY: `stream` is valid and not used after this.
+        unsafe { libc::closedir(stream) };
+        result.map_err(|error| self.error(Path::new(""), error))?;
+        names.sort();
+        Ok(names)
+    }
+
+    fn open_at(
+        &self,
+        name: &Path,
+        flags: libc::c_int,
+        mode: libc::c_uint,
+    ) -> Result<OwnedFd, Error> {
+        let c_name = c_name(name)?;
+        // SAFETY: the descriptor is open and `c_name` is NUL-terminated.
+        let fd = unsafe {
+            libc::openat(
+                self.fd.as_raw_fd(),
+                c_name.as_ptr(),
+                flags | libc::O_CLOEXEC,
+                mode,
+            )
+        };
+        owned(fd).map_err(|error| self.error(name, error))
+    }
+
+    fn check(&self, result: libc::c_int, name: &Path) -> Result<(), Error> {
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(self.error(name, Error::last_os_error()))
+        }
+    }
+
+    /// `error`, with the path of `name` in this directory in front of it.
+    fn error(&self, name: &Path, error: Error) -> Error {
+        with_path(&self.path.join(name), error)
+    }
+}
+
+impl AsFd for Dir {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for Dir {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<libc::stat> for DirStat {
+    // Field widths differ between platforms; the casts only ever widen.
+    #[allow(clippy::unnecessary_cast, clippy::useless_conversion)]
+    fn from(stat: libc::stat) -> DirStat {
+        let kind = match stat.st_mode as u32 & libc::S_IFMT as u32 {
+            t if t == libc::S_IFREG as u32 => EntryType::File,
+            t if t == libc::S_IFDIR as u32 => EntryType::Dir,
+            t if t == libc::S_IFLNK as u32 => EntryType::Symlink,
+            _ => EntryType::Other,
+        };
+        let offset = Duration::new(
+            stat.st_mtime.unsigned_abs() as u64,
+            stat.st_mtime_nsec as u32,
+        );
+        let modified = if stat.st_mtime >= 0 {
+            UNIX_EPOCH + offset
+        } else {
+            UNIX_EPOCH - offset
+        };
+        DirStat {
+            kind,
+            mode: Mode::from_bits(stat.st_mode as u32),
+            size: stat.st_size as u64,
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            dev: stat.st_dev as u64,
+            ino: stat.st_ino as u64,
+            nlink: stat.st_nlink as u64,
+            modified,
+        }
+    }
+}
+
+fn c_name(path: &Path) -> Result<CString, Error> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))
+}
+
+fn owned(fd: RawFd) -> Result<OwnedFd, Error> {
+    if fd < 0 {
+        Err(Error::last_os_error())
+    } else {
+        // SAFETY: `fd` was just returned by a successful open and nothing
+        // else owns it.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+fn with_path(path: &Path, error: Error) -> Error {
+    Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+fn set_errno(value: libc::c_int) {
+    // SAFETY: the errno location is valid for the current thread.
+    unsafe {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            *libc::__errno_location() = value;
+        }
+        #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+        {
+            *libc::__error() = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{read_text, write_file};
+    use std::io::Write;
+
+    #[test]
+    fn test_operations_follow_renamed_directory() {
+        let guard = setup_test();
+        let original = format!("{}/work", guard.test_root);
+        let moved = format!("{}/moved", guard.test_root);
+        std::fs::create_dir(&original).unwrap();
+        let dir = Dir::open(&original).unwrap();
+
+        std::fs::rename(&original, &moved).unwrap();
+        std::fs::create_dir(&original).unwrap();
+        dir.create_file("data")
+            .unwrap()
+            .write_all(b"anchored")
+            .unwrap();
+        assert_eq!(read_text(format!("{}/data", moved)).unwrap(), "anchored");
+        assert!(Dir::open(&original).unwrap().list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_twice_and_remove() {
+        let guard = setup_test();
+        let dir = Dir::open(&guard.test_root).unwrap();
+        write_file(format!("{}/b", guard.test_root), "").unwrap();
+        dir.create_dir("a").unwrap();
+        assert_eq!(dir.list().unwrap(), ["a", "b"]);
+        assert_eq!(dir.list().unwrap(), ["a", "b"]);
+
+        dir.remove("a").unwrap();
+        dir.remove("b").unwrap();
+        assert!(dir.list().unwrap().is_empty());
+        let error = dir.remove("b").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(
+            error
+                .to_string()
+                .starts_with(&format!("{}/b: ", guard.test_root))
+        );
+    }
+
+    #[test]
+    fn test_remove_non_empty_dir_fails() {
+        let guard = setup_test();
+        let dir = Dir::open(&guard.test_root).unwrap();
+        dir.create_dir("full").unwrap();
+        dir.open_dir("full").unwrap().create_file("x").unwrap();
+        assert_eq!(
+            dir.remove("full").unwrap_err().kind(),
+            ErrorKind::DirectoryNotEmpty
+        );
+    }
+
+    #[test]
+    fn test_stat_and_symlink() {
+        let guard = setup_test();
+        let dir = Dir::open(&guard.test_root).unwrap();
+        write_file(format!("{}/file", guard.test_root), "12345").unwrap();
+        dir.symlink("file", "link").unwrap();
+
+        let file = dir.stat("file").unwrap();
+        assert_eq!(file.kind, EntryType::File);
+        assert_eq!(file.size, 5);
+        assert_eq!(file.nlink, 1);
+        assert_eq!(dir.stat("link").unwrap().kind, EntryType::Symlink);
+        assert!(dir.open_dir("link").is_err());
+        assert_eq!(dir.stat(".").unwrap().kind, EntryType::Dir);
+    }
+}
@@ -0,0 +1,192 @@
+use std::fmt::Write;
+
+/// Bytes per line of [`hex_dump`].
+const HEX_LINE: usize = 16;
+
+/// Bytes per line of [`c_array`].
+const C_LINE: usize = 12;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Render `bytes` as a hex dump in the style of `xxd`: per 16 bytes, the
+/// offset, the bytes in groups of two, and the printable ASCII characters
+/// with `.` for the rest.
+///
+/// `offset` is the position of `bytes[0]` in its file, so a file can be
+/// dumped a block at a time as long as each block but the last is a
+/// multiple of 16 bytes.
+///
+/// Examples
+/// ```
+/// use soil::hex_dump;
+/// assert_eq!(
+///     hex_dump(b"hello, world\n", 0),
+///     "00000000: 6865 6c6c 6f2c 2077 6f72 6c64 0a         hello, world.\n"
+/// );
+/// ```
+pub fn hex_dump(bytes: &[u8], offset: u64) -> String {
+    let mut out = String::new();
+    for (index, line) in bytes.chunks(HEX_LINE).enumerate() {
+        let _ = write!(out, "{:08x}:", offset + (index * HEX_LINE) as u64);
+        let mut hex = String::with_capacity(40);
+        for (at, byte) in line.iter().enumerate() {
+            if at % 2 == 0 {
+                hex.push(' ');
+            }
+            let _ = write!(hex, "{:02x}", byte);
+        }
+        let ascii: String = line
+            .iter()
+            .map(|&byte| match byte {
+                b' '..=b'~' => byte as char,
+                _ => '.',
+            })
+            .collect();
+        let _ = writeln!(out, "{:<40}  {}", hex, ascii);
+    }
+    out
+}
+
+/// Encode `bytes` as standard base64 (RFC 4648), padded with `=`, on one
+/// line.
+///
+/// Examples
+/// ```
+/// use soil::base64_encode;
+/// assert_eq!(base64_encode(b"soil"), "c29pbA==");
+/// assert_eq!(base64_encode(b""), "");
+/// ```
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let mut block = [0; 3];
+        block[..group.len()].copy_from_slice(group);
+        let bits = u32::from_be_bytes([0, block[0], block[1], block[2]]);
+        for index in 0..4 {
+            if index <= group.len() {
+                out.push(BASE64[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Render `bytes` as a C array named `name` with its length, like
+/// `xxd -i`.
+///
+/// `name` is used as it is; make it a valid C identifier first.
+///
+/// Examples
+/// ```
+/// use soil::c_array;
+/// assert_eq!(
+///     c_array(b"hi\n", "greeting_txt"),
+///     "unsigned char greeting_txt[] = {\n  0x68, 0x69, 0x0a\n};\n\
+///      unsigned int greeting_txt_len = 3;\n"
+/// );
+/// ```
+pub fn c_array(bytes: &[u8], name: &str) -> String {
+    let mut out = format!("unsigned char {}[] = {{\n", name);
+    let lines: Vec<String> = bytes
+        .chunks(C_LINE)
+        .map(|line| {
+            let items: Vec<String> = line.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+            format!("  {}", items.join(", "))
+        })
+        .collect();
+    if !lines.is_empty() {
+        out.push_str(&lines.join(",\n"));
+        out.push('\n');
+    }
+    let _ = writeln!(out, "}};\nunsigned int {}_len = {};", name, bytes.len());
+    out
+}
+
+/// Render `bytes` as text, escaping what would not print: `\t`, `\r`,
+/// `\0` and `\\` as in Rust, other control characters as `\xNN` or
+/// `\u{NNNN}`, and bytes that are not UTF-8 as `\xNN`. Newlines are kept.
+///
+/// Examples
+/// ```
+/// use soil::escape_text;
+/// assert_eq!(escape_text(b"tab\there\n\x1b[0m\xff"), "tab\\there\n\\x1b[0m\\xff");
+/// assert_eq!(escape_text("héllo".as_bytes()), "héllo");
+/// ```
+pub fn escape_text(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for character in chunk.valid().chars() {
+            match character {
+                '\n' => out.push('\n'),
+                '\t' => out.push_str("\\t"),
+                '\r' => out.push_str("\\r"),
+                '\0' => out.push_str("\\0"),
+                '\\' => out.push_str("\\\\"),
+                _ if character.is_ascii_control() => {
+                    let _ = write!(out, "\\x{:02x}", character as u32);
+                }
+                _ if character.is_control() => {
+                    let _ = write!(out, "\\u{{{:04x}}}", character as u32);
+                }
+                _ => out.push(character),
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(out, "\\x{:02x}", byte);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump_lines_and_offsets() {
+        let bytes: Vec<u8> = (0..20).collect();
+        let dump = hex_dump(&bytes, 0x1000);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "00001000: 0001 0203 0405 0607 0809 0a0b 0c0d 0e0f  ................",
+                "00001010: 1011 1213                                ....",
+            ]
+        );
+        assert_eq!(hex_dump(b"", 0), "");
+    }
+
+    #[test]
+    fn test_base64_padding() {
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_encode(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+
+    #[test]
+    fn test_c_array_wraps_lines() {
+        let array = c_array(&[7; 13], "data");
+        assert_eq!(
+            array,
+            "unsigned char data[] = {\n  \
+             0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07,\n  \
+             0x07\n};\nunsigned int data_len = 13;\n"
+        );
+        assert_eq!(
+            c_array(b"", "empty"),
+            "unsigned char empty[] = {\n};\nunsigned int empty_len = 0;\n"
+        );
+    }
+
+    #[test]
+    fn test_escape_text_controls() {
+        assert_eq!(escape_text(b"a\\b\r\n\0"), "a\\\\b\\r\n\\0");
+        assert_eq!(escape_text("\u{85}".as_bytes()), "\\u{0085}");
+        assert_eq!(escape_text(b"\xe2\x82"), "\\xe2\\x82");
+    }
+}
@@ -0,0 +1,640 @@
+use serde_json::json;
+use soil::{
+    BulkReport, CancelToken, CopyOptions, Predicate, Preserve, Timestamp, Walker, exists, expand,
+    find, glob, is_glob, metadata, move_path_with_progress, normalize, parse_datetime, relative_to,
+    set_times, symlink_metadata, touch,
+};
+#[cfg(unix)]
+use soil::{Owner, set_symlink_times};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::output::{
+    Exit, ProgressBar, Status, error_record, fail, format_bytes, metadata_record, output,
+    print_metadata, reader_gone, type_name,
+};
+use crate::{BackupArgs, CopyArgs, FindArgs, PathCommand, TouchArgs};
+
+pub(crate) fn walker(path: &str, threads: Option<usize>, ignore: bool) -> Walker {
+    let walker = Walker::new(path).ignore(ignore);
+    match threads {
+        Some(threads) => walker.threads(threads),
+        None => walker,
+    }
+}
+
+/// Print a bulk operation's summary, or its failures and end non-zero.
+pub(crate) fn finish_bulk<T>(
+    report: io::Result<BulkReport<T>>,
+    on_success: impl FnOnce(&BulkReport<T>),
+) -> Status {
+    match report {
+        Ok(report) if report.is_success() => {
+            on_success(&report);
+            Ok(())
+        }
+        Ok(report) => {
+            report_failures(&report);
+            if output().is_text() {
+                eprintln!(
+                    "{} entries done, {} failed",
+                    report.done.len(),
+                    report.failed.len()
+                );
+            }
+            Err(fail())
+        }
+        Err(error) => {
+            output().error("", None, &error);
+            Err(fail())
+        }
+    }
+}
+
+/// Report each entry a bulk operation failed on.
+pub(crate) fn report_failures<T>(report: &BulkReport<T>) {
+    for (path, error) in &report.failed {
+        let path = path.to_string_lossy();
+        output().error(&format!("at '{}'", path), Some(&path), error);
+    }
+}
+
+/// Build the search described by `soil find` flags and stream its results.
+pub(crate) fn run_find(args: FindArgs) -> Status {
+    let FindArgs {
+        root,
+        name,
+        iname,
+        regex,
+        entry_type,
+        size,
+        older,
+        newer,
+        accessed_older,
+        accessed_newer,
+        newer_than,
+        empty,
+        #[cfg(unix)]
+        perm,
+        #[cfg(unix)]
+        user,
+        #[cfg(unix)]
+        group,
+        min_depth,
+        max_depth,
+        respect_ignore,
+        print0,
+        exec,
+    } = args;
+    let out = output();
+    let abort = |what: &str, error: io::Error| {
+        out.error(&format!("in {}", what), None, &error);
+        fail()
+    };
+
+    let mut search = find(&root).ignore(respect_ignore);
+    let names = name
+        .iter()
+        .map(|glob| Predicate::name(glob))
+        .chain(iname.iter().map(|glob| Predicate::iname(glob)))
+        .reduce(Predicate::or);
+    if let Some(names) = names {
+        search = search.filter(names);
+    }
+    if let Some(regex) = regex {
+        search = search.filter(Predicate::regex(&regex).map_err(|error| abort("--regex", error))?);
+    }
+    if let Some(entry_type) = entry_type {
+        search = search.file_type(entry_type);
+    }
+    for range in size {
+        search = search.size(range);
+    }
+    if let Some(age) = older {
+        search = search.filter(Predicate::older_than(age));
+    }
+    if let Some(age) = newer {
+        search = search.filter(Predicate::newer_than_age(age));
+    }
+    if let Some(age) = accessed_older {
+        search = search.filter(Predicate::accessed_older_than(age));
+    }
+    if let Some(age) = accessed_newer {
+        search = search.filter(Predicate::accessed_newer_than_age(age));
+    }
+    if let Some(reference) = newer_than {
+        search = search.filter(
+            Predicate::newer_than(&reference).map_err(|error| abort("--newer-than", error))?,
+        );
+    }
+    if empty {
+        search = search.filter(Predicate::empty());
+    }
+    #[cfg(unix)]
+    if let Some(perm) = perm {
+        let predicate = match perm.split_at_checked(1) {
+            Some(("-", bits)) => bits.parse().map(Predicate::perm_all),
+            Some(("/", bits)) => bits.parse().map(Predicate::perm_any),
+            _ => perm.parse().map(Predicate::perm_exact),
+        };
+        search = search.filter(predicate.map_err(|error| abort("--perm", error))?);
+    }
+    #[cfg(unix)]
+    if let Some(user) = user {
+        match user.parse::<Owner>() {
+            Ok(Owner { uid: Some(uid), .. }) if !user.contains(':') => {
+                search = search.filter(Predicate::user(uid));
+            }
+            Ok(_) => {
+                return Err(abort(
+                    "--user",
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid user '{}'", user),
+                    ),
+                ));
+            }
+            Err(error) => return Err(abort("--user", error)),
+        }
+    }
+    #[cfg(unix)]
+    if let Some(group) = group {
+        match format!(":{}", group).parse::<Owner>() {
+            Ok(Owner { gid: Some(gid), .. }) => {
+                search = search.filter(Predicate::group(gid));
+            }
+            Ok(_) => {
+                return Err(abort(
+                    "--group",
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid group '{}'", group),
+                    ),
+                ));
+            }
+            Err(error) => return Err(abort("--group", error)),
+        }
+    }
+    if let Some(depth) = min_depth {
+        search = search.min_depth(depth);
+    }
+    if let Some(depth) = max_depth {
+        search = search.max_depth(depth);
+    }
+
+    // A missing root is the one error that names the path given.
+    if let Err(error) = fs::symlink_metadata(&root) {
+        out.error(&format!("reading '{}'", root), Some(&root), &error);
+        return Err(fail());
+    }
+
+    let mut failed = false;
+    let mut stdout = io::stdout().lock();
+    for entry in search {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                out.error("", None, &error);
+                failed = true;
+                continue;
+            }
+        };
+        if exec.is_empty() && !out.is_text() {
+            out.record(json!({
+                "path": entry.path.to_string_lossy(),
+                "type": type_name(entry.metadata.file_type()),
+                "depth": entry.depth,
+            }));
+        } else if exec.is_empty() {
+            let separator = if print0 { "\0" } else { "\n" };
+            let written = write!(stdout, "{}{}", entry.path.display(), separator);
+            if written.is_err() {
+                // The reader went away (e.g. `| head`); stop quietly.
+                return Err(reader_gone(if failed { 1 } else { 0 }));
+            }
+        } else if !run_exec(&exec, &entry.path) {
+            failed = true;
+        }
+    }
+    if failed {
+        return Err(fail());
+    }
+    Ok(())
+}
+
+/// Run a `find --exec` command for one path, replacing `{}` (or appending
+/// the path when there is none). Returns whether it exited successfully.
+fn run_exec(command: &[String], path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    let mut args: Vec<String> = command.iter().map(|arg| arg.replace("{}", &path)).collect();
+    if !command.iter().any(|arg| arg.contains("{}")) {
+        args.push(path.to_string());
+    }
+    match process::Command::new(&args[0]).args(&args[1..]).status() {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            let message = format!("'{}' failed for '{}': {}", args[0], path, status);
+            if output().is_text() {
+                eprintln!("{}", message);
+            } else {
+                eprintln!("{}", error_record(&message, "command_failed", Some(&path)));
+            }
+            false
+        }
+        Err(error) => {
+            output().error(&format!("running '{}'", args[0]), Some(&path), &error);
+            false
+        }
+    }
+}
+
+/// `soil copy` of a single file, with a progress bar. Returns whether it worked.
+fn copy_one(src: &str, dst: &str, options: &CopyOptions) -> bool {
+    let out = output();
+    let mut bar = ProgressBar::new();
+    let mut bytes = 0;
+    let result = options.copy_with_progress(src, dst, &CancelToken::new(), |p| {
+        bytes = p.bytes_done;
+        bar.update(p)
+    });
+    bar.finish();
+    match result {
+        Ok(_) => {
+            out.status(format_args!("Copied '{}' to '{}'", src, dst));
+            out.record(json!({ "source": src, "destination": dst, "files": 1, "bytes": bytes }));
+            true
+        }
+        Err(error) => {
+            out.error(
+                &format!("copying '{}' to '{}'", src, dst),
+                Some(src),
+                &error,
+            );
+            false
+        }
+    }
+}
+
+/// `soil copy -r` of one tree. Returns whether every entry was copied.
+fn copy_tree(
+    src: &str,
+    dst: &str,
+    threads: Option<usize>,
+    ignore: bool,
+    options: &CopyOptions,
+) -> bool {
+    let out = output();
+    match walker(src, threads, ignore).copy_to_with(dst, options) {
+        Ok(report) => {
+            report_failures(&report);
+            let bytes: u64 = report.done.iter().map(|(_, bytes)| bytes).sum();
+            out.status(format_args!(
+                "Copied '{}' to '{}' ({} files, {})",
+                src,
+                dst,
+                report.done.len(),
+                format_bytes(bytes)
+            ));
+            out.record(json!({
+                "source": src,
+                "destination": dst,
+                "files": report.done.len(),
+                "bytes": bytes,
+            }));
+            report.is_success()
+        }
+        Err(error) => {
+            out.error(
+                &format!("copying '{}' to '{}'", src, dst),
+                Some(src),
+                &error,
+            );
+            false
+        }
+    }
+}
+
+/// Expand path arguments: glob patterns are replaced by their sorted matches
+/// and anything else is kept as given. A file by the exact name given wins
+/// over the pattern, so `'[ab]'` names that file even when `a` exists. A
+/// pattern matching nothing is an error.
+pub(crate) fn expand_paths(args: &[String]) -> Result<Vec<String>, Exit> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if !is_glob(arg) || symlink_metadata(arg).is_ok() {
+            paths.push(arg.clone());
+            continue;
+        }
+        match glob(arg) {
+            Ok(matches) if !matches.is_empty() => {
+                paths.extend(
+                    matches
+                        .iter()
+                        .map(|path| path.to_string_lossy().into_owned()),
+                );
+            }
+            Ok(_) => {
+                let message = format!("no matches for '{}'", arg);
+                let error = io::Error::new(io::ErrorKind::NotFound, message);
+                output().error("", Some(arg), &error);
+                return Err(fail());
+            }
+            Err(error) => {
+                output().error(&format!("expanding '{}'", arg), Some(arg), &error);
+                return Err(fail());
+            }
+        }
+    }
+    Ok(paths)
+}
+
+pub(crate) fn run_path(command: PathCommand) -> Status {
+    let out = output();
+    match command {
+        PathCommand::Normalize { paths } => {
+            for path in paths {
+                let normalized = normalize(&path);
+                out.text(normalized.display());
+                out.record(json!({ "path": path, "normalized": normalized.to_string_lossy() }));
+            }
+        }
+        PathCommand::Relative {
+            path: path_arg,
+            base: base_arg,
+        } => {
+            let (mut path, mut base) = (PathBuf::from(&path_arg), PathBuf::from(&base_arg));
+            if path.is_absolute() != base.is_absolute() {
+                let current = std::env::current_dir().map_err(|error| {
+                    out.error("reading the current directory", None, &error);
+                    fail()
+                })?;
+                if path.is_relative() {
+                    path = current.join(path);
+                } else {
+                    base = current.join(base);
+                }
+            }
+            match relative_to(&path, &base) {
+                Ok(relative) => {
+                    out.text(relative.display());
+                    out.record(json!({ "path": path_arg, "base": base_arg, "relative": relative.to_string_lossy() }));
+                }
+                Err(error) => {
+                    out.error("", Some(&path_arg), &error);
+                    return Err(fail());
+                }
+            }
+        }
+        PathCommand::Expand { texts } => {
+            for text in texts {
+                match expand(&text) {
+                    Ok(expanded) => {
+                        out.text(expanded.display());
+                        out.record(json!({ "text": text, "expanded": expanded.to_string_lossy() }));
+                    }
+                    Err(error) => {
+                        out.error("", None, &error);
+                        return Err(fail());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `soil copy`: copy each source to `dst`, into it when it is a directory.
+pub(crate) fn run_copy(args: CopyArgs) -> Status {
+    let out = output();
+    let CopyArgs {
+        sources,
+        dst,
+        strategy,
+        recursive,
+        threads,
+        respect_ignore,
+        archive,
+        preserve,
+        backup,
+    } = args;
+    let mut options = CopyOptions::new()
+        .strategy(strategy)
+        .backup(backup.policy());
+    if let Some(preserve) = preserve {
+        options = options.preserve(preserve);
+    } else if archive {
+        options = options.preserve(Preserve::all());
+    }
+    let sources = expand_paths(&sources)?;
+    let into_dir = Path::new(&dst).is_dir();
+    if sources.len() > 1 && !into_dir {
+        let message = format!(
+            "'{}' must be an existing directory to copy several sources",
+            dst
+        );
+        out.error(
+            "",
+            Some(&dst),
+            &io::Error::new(io::ErrorKind::NotADirectory, message),
+        );
+        return Err(fail());
+    }
+
+    let mut failed = false;
+    for src in &sources {
+        let target = match Path::new(src).file_name() {
+            Some(name) if into_dir => Path::new(&dst).join(name).to_string_lossy().into_owned(),
+            _ => dst.clone(),
+        };
+        let copied = if recursive {
+            copy_tree(src, &target, threads, respect_ignore, &options)
+        } else {
+            copy_one(src, &target, &options)
+        };
+        failed |= !copied;
+    }
+    if failed {
+        return Err(fail());
+    }
+    Ok(())
+}
+
+/// `soil mv`: move `from` to `to`, backing up what it replaces.
+pub(crate) fn run_mv(from: String, to: String, backup: BackupArgs) -> Status {
+    let out = output();
+    // Only back up `to` once there is something to move over it.
+    if let Err(error) = symlink_metadata(&from) {
+        out.error(
+            &format!("moving '{}' to '{}'", from, to),
+            Some(&from),
+            &error,
+        );
+        return Err(fail());
+    }
+    let backed_up = match backup.policy().backup(&to) {
+        Ok(backed_up) => backed_up,
+        Err(error) => {
+            out.error(&format!("backing up '{}'", to), Some(&to), &error);
+            return Err(fail());
+        }
+    };
+    let mut bar = ProgressBar::new();
+    let result = move_path_with_progress(&from, &to, &CancelToken::new(), |p| bar.update(p));
+    bar.finish();
+    match result {
+        Ok(_) => {
+            out.status(format_args!("Moved '{}' to '{}'", from, to));
+            out.record(json!({ "source": from, "destination": to, "backup": backed_up.as_deref().map(Path::to_string_lossy) }));
+        }
+        Err(error) => {
+            out.error(
+                &format!("moving '{}' to '{}'", from, to),
+                Some(&from),
+                &error,
+            );
+            return Err(fail());
+        }
+    }
+    Ok(())
+}
+
+/// `soil stat`: show the metadata of every path that `paths` expand to.
+pub(crate) fn run_stat(paths: Vec<String>) -> Status {
+    let out = output();
+    let mut failed = false;
+    for path in expand_paths(&paths)? {
+        match metadata(&path) {
+            Ok(metadata) => {
+                let kind = if metadata.is_file() {
+                    "File"
+                } else if metadata.is_dir() {
+                    "Directory"
+                } else {
+                    "Other"
+                };
+                out.text(format_args!("Metadata for '{}':", path));
+                print_metadata(kind, &metadata);
+                out.record(metadata_record(&path, &metadata));
+            }
+            Err(error) => {
+                out.error(
+                    &format!("reading metadata for '{}'", path),
+                    Some(&path),
+                    &error,
+                );
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err(fail());
+    }
+    Ok(())
+}
+
+/// `soil touch`: set the times of `path`, creating it unless `no_create`.
+pub(crate) fn run_touch(args: TouchArgs) -> Status {
+    let out = output();
+    let TouchArgs {
+        path,
+        date,
+        reference,
+        access_only,
+        modification_only,
+        no_create,
+        #[cfg(unix)]
+        no_dereference,
+    } = args;
+    let (atime, mtime) = if let Some(date) = &date {
+        match parse_datetime(date) {
+            Ok(time) => (Timestamp::At(time), Timestamp::At(time)),
+            Err(error) => {
+                out.error("parsing date", None, &error);
+                return Err(fail());
+            }
+        }
+    } else if let Some(reference) = &reference {
+        match metadata(reference).and_then(|md| Ok((md.accessed()?, md.modified()?))) {
+            Ok((atime, mtime)) => (Timestamp::At(atime), Timestamp::At(mtime)),
+            Err(error) => {
+                let context = format!("reading times of '{}'", reference);
+                out.error(&context, Some(reference), &error);
+                return Err(fail());
+            }
+        }
+    } else {
+        (Timestamp::Now, Timestamp::Now)
+    };
+    let atime = if modification_only {
+        Timestamp::Unchanged
+    } else {
+        atime
+    };
+    let mtime = if access_only {
+        Timestamp::Unchanged
+    } else {
+        mtime
+    };
+
+    let created = !exists(&path);
+    if created {
+        if no_create {
+            return Ok(());
+        }
+        if let Err(error) = touch(&path) {
+            out.error(&format!("creating '{}'", path), Some(&path), &error);
+            return Err(fail());
+        }
+    }
+
+    #[cfg(unix)]
+    let result = if no_dereference {
+        set_symlink_times(&path, atime, mtime)
+    } else {
+        set_times(&path, atime, mtime)
+    };
+    #[cfg(not(unix))]
+    let result = set_times(&path, atime, mtime);
+
+    match result {
+        Ok(_) => {
+            out.status(format_args!("Touched '{}'", path));
+            out.record(json!({ "path": path, "created": created }));
+        }
+        Err(error) => {
+            out.error(
+                &format!("updating times of '{}'", path),
+                Some(&path),
+                &error,
+            );
+            return Err(fail());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_paths_prefers_literal_names() {
+        let root = std::env::temp_dir().join(format!("soil-expand-{}", process::id()));
+        soil::ensure_dir(&root).unwrap();
+        for name in ["a", "b", "[ab]"] {
+            soil::write_file(root.join(name), "").unwrap();
+        }
+        let path = |name: &str| root.join(name).to_string_lossy().into_owned();
+
+        assert_eq!(expand_paths(&[path("[ab]")]).unwrap(), [path("[ab]")]);
+        assert_eq!(expand_paths(&[path("?")]).unwrap(), [path("a"), path("b")]);
+        soil::remove_file(root.join("[ab]")).unwrap();
+        assert_eq!(
+            expand_paths(&[path("[ab]")]).unwrap(),
+            [path("a"), path("b")]
+        );
+        soil::remove_dir_all(&root).unwrap();
+    }
+}
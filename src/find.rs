@@ -0,0 +1,891 @@
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::ops::Not;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use regex::Regex;
+
+use crate::glob::Pattern;
+use crate::ignore::IgnoreRules;
+#[cfg(unix)]
+use crate::mode::Mode;
+use crate::walk::WalkEntry;
+
+/// The kind of entry a [`Predicate::file_type`] test looks for.
+///
+/// Parses `f`/`file`, `d`/`dir`, `l`/`symlink` and `o`/`other`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    /// A regular file.
+    File,
+    /// A directory.
+    Dir,
+    /// A symbolic link (never followed).
+    Symlink,
+    /// Anything else: sockets, FIFOs, devices.
+    Other,
+}
+
+impl EntryType {
+    fn of(metadata: &fs::Metadata) -> EntryType {
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            EntryType::Symlink
+        } else if file_type.is_dir() {
+            EntryType::Dir
+        } else if file_type.is_file() {
+            EntryType::File
+        } else {
+            EntryType::Other
+        }
+    }
+}
+
+impl FromStr for EntryType {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<EntryType, Error> {
+        match spec {
+            "f" | "file" => Ok(EntryType::File),
+            "d" | "dir" | "directory" => Ok(EntryType::Dir),
+            "l" | "symlink" | "link" => Ok(EntryType::Symlink),
+            "o" | "other" => Ok(EntryType::Other),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown entry type '{}' (expected f, d, l or o)", spec),
+            )),
+        }
+    }
+}
+
+/// An inclusive range of file sizes in bytes.
+///
+/// Parses find-style specs: `+10M` (more than 10 MiB), `-4k` (less than
+/// 4 KiB) and `512` (exactly 512 bytes). Suffixes `k`, `M`, `G` and `T` are
+/// binary multiples; `c` or no suffix means bytes.
+///
+/// Examples
+/// ```
+/// use soil::SizeRange;
+/// let range: SizeRange = "+10M".parse().unwrap();
+/// assert!(range.contains(10 * 1024 * 1024 + 1));
+/// assert!(!range.contains(10 * 1024 * 1024));
+///
+/// let range: SizeRange = "-1k".parse().unwrap();
+/// assert!(range.contains(1023));
+/// assert!(!range.contains(1024));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct SizeRange {
+    /// Smallest accepted size, if bounded below.
+    pub min: Option<u64>,
+    /// Largest accepted size, if bounded above.
+    pub max: Option<u64>,
+}
+
+impl SizeRange {
+    /// Whether `size` lies within the range.
+    pub fn contains(&self, size: u64) -> bool {
+        self.min.is_none_or(|min| size >= min) && self.max.is_none_or(|max| size <= max)
+    }
+}
+
+impl FromStr for SizeRange {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<SizeRange, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid size '{}'", spec));
+        let (sign, rest) = match spec.as_bytes().first() {
+            Some(b'+') => (Some('+'), &spec[1..]),
+            Some(b'-') => (Some('-'), &spec[1..]),
+            _ => (None, spec),
+        };
+        let split = rest
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (digits, suffix) = rest.split_at(split);
+        let unit: u64 = match suffix {
+            "" | "c" | "b" | "B" => 1,
+            "k" | "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            "T" => 1 << 40,
+            _ => return Err(invalid()),
+        };
+        let size = digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|count| count.checked_mul(unit))
+            .ok_or_else(invalid)?;
+
+        Ok(match sign {
+            Some('+') => SizeRange {
+                min: Some(size.saturating_add(1)),
+                max: None,
+            },
+            Some(_) if size == 0 => return Err(invalid()),
+            Some(_) => SizeRange {
+                min: None,
+                max: Some(size - 1),
+            },
+            None => SizeRange {
+                min: Some(size),
+                max: Some(size),
+            },
+        })
+    }
+}
+
+impl fmt::Display for SizeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if min == max => write!(f, "{}", min),
+            (Some(min), None) => write!(f, "+{}", min.saturating_sub(1)),
+            (None, Some(max)) => write!(f, "-{}", max + 1),
+            (min, max) => write!(
+                f,
+                "{}..={}",
+                min.unwrap_or(0),
+                max.map_or(String::new(), |max| max.to_string())
+            ),
+        }
+    }
+}
+
+/// Parse an age such as `30s`, `15m`, `12h`, `7d` or `2w`.
+///
+/// A bare number is taken as seconds. Ages that reach back further than
+/// the clock can represent are rejected.
+///
+/// Examples
+/// ```
+/// use soil::parse_age;
+/// use std::time::Duration;
+/// assert_eq!(parse_age("7d").unwrap(), Duration::from_secs(7 * 24 * 3600));
+/// assert_eq!(parse_age("90").unwrap(), Duration::from_secs(90));
+/// assert!(parse_age("soon").is_err());
+/// assert!(parse_age("18446744073709551615s").is_err());
+/// ```
+pub fn parse_age(spec: &str) -> Result<Duration, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid age '{}'", spec));
+    let split = spec
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(spec.len());
+    let (digits, suffix) = spec.split_at(split);
+    let unit: u64 = match suffix {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        _ => return Err(invalid()),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(unit))
+        .map(Duration::from_secs)
+        .filter(|&age| SystemTime::now().checked_sub(age).is_some())
+        .ok_or_else(invalid)
+}
+
+/// The time `age` before now, or the epoch if that is out of range.
+fn age_ago(age: Duration) -> SystemTime {
+    SystemTime::now()
+        .checked_sub(age)
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// A test applied to each entry by [`find`].
+///
+/// Build leaf tests with the constructors below and combine them with
+/// [`Predicate::and`], [`Predicate::or`] and `!`.
+///
+/// Examples
+/// ```
+/// use soil::{EntryType, Predicate};
+/// // Log files that are not compressed, or any core dump.
+/// let predicate = Predicate::name("*.log")
+///     .and(!Predicate::name("*.gz"))
+///     .and(Predicate::file_type(EntryType::File))
+///     .or(Predicate::name("core.[0-9]*"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Predicate(Node);
+
+#[derive(Clone, Debug)]
+enum Node {
+    Always,
+    Name(Pattern),
+    Regex(Regex),
+    Type(EntryType),
+    Size(SizeRange),
+    Modified(Bound),
+    Accessed(Bound),
+    Empty,
+    #[cfg(unix)]
+    Perm(PermTest, u32),
+    #[cfg(unix)]
+    User(u32),
+    #[cfg(unix)]
+    Group(u32),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Bound {
+    Before(SystemTime),
+    After(SystemTime),
+}
+
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug)]
+enum PermTest {
+    Exact,
+    All,
+    Any,
+}
+
+impl Predicate {
+    /// Matches everything.
+    pub fn always() -> Predicate {
+        Predicate(Node::Always)
+    }
+
+    /// The entry's file name matches a wildcard pattern such as `*.log`.
+    pub fn name(glob: &str) -> Predicate {
+        Predicate(Node::Name(Pattern::new(glob)))
+    }
+
+    /// Like [`Predicate::name`], ignoring case.
+    pub fn iname(glob: &str) -> Predicate {
+        Predicate(Node::Name(Pattern::new(glob).case_insensitive(true)))
+    }
+
+    /// The entry's whole path matches a regular expression somewhere.
+    ///
+    /// Anchor with `^` and `$` to match the full path, as `find -regex` does.
+    pub fn regex(pattern: &str) -> Result<Predicate, Error> {
+        Regex::new(pattern)
+            .map(|regex| Predicate(Node::Regex(regex)))
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error))
+    }
+
+    /// The entry is of the given kind.
+    pub fn file_type(entry_type: EntryType) -> Predicate {
+        Predicate(Node::Type(entry_type))
+    }
+
+    /// The entry's size lies within `range`.
+    pub fn size(range: SizeRange) -> Predicate {
+        Predicate(Node::Size(range))
+    }
+
+    /// Last modified strictly before `time`.
+    pub fn modified_before(time: SystemTime) -> Predicate {
+        Predicate(Node::Modified(Bound::Before(time)))
+    }
+
+    /// Last modified strictly after `time`.
+    pub fn modified_after(time: SystemTime) -> Predicate {
+        Predicate(Node::Modified(Bound::After(time)))
+    }
+
+    /// Last accessed strictly before `time`.
+    pub fn accessed_before(time: SystemTime) -> Predicate {
+        Predicate(Node::Accessed(Bound::Before(time)))
+    }
+
+    /// Last accessed strictly after `time`.
+    pub fn accessed_after(time: SystemTime) -> Predicate {
+        Predicate(Node::Accessed(Bound::After(time)))
+    }
+
+    /// Not modified within the last `age`.
+    ///
+    /// An age further back than the clock reaches counts from the epoch.
+    pub fn older_than(age: Duration) -> Predicate {
+        Predicate::modified_before(age_ago(age))
+    }
+
+    /// Modified within the last `age`.
+    ///
+    /// An age further back than the clock reaches counts from the epoch.
+    pub fn newer_than_age(age: Duration) -> Predicate {
+        Predicate::modified_after(age_ago(age))
+    }
+
+    /// Not accessed within the last `age`.
+    ///
+    /// An age further back than the clock reaches counts from the epoch.
+    pub fn accessed_older_than(age: Duration) -> Predicate {
+        Predicate::accessed_before(age_ago(age))
+    }
+
+    /// Accessed within the last `age`.
+    ///
+    /// An age further back than the clock reaches counts from the epoch.
+    pub fn accessed_newer_than_age(age: Duration) -> Predicate {
+        Predicate::accessed_after(age_ago(age))
+    }
+
+    /// Modified more recently than `reference` was.
+    ///
+    /// The reference is read once, when the predicate is built.
+    pub fn newer_than<P: AsRef<Path>>(reference: P) -> Result<Predicate, Error> {
+        let modified = fs::metadata(reference)?.modified()?;
+        Ok(Predicate::modified_after(modified))
+    }
+
+    /// An empty regular file or a directory with no entries.
+    pub fn empty() -> Predicate {
+        Predicate(Node::Empty)
+    }
+
+    /// Permission bits are exactly `mode` (including setuid, setgid, sticky).
+    #[cfg(unix)]
+    pub fn perm_exact(mode: Mode) -> Predicate {
+        Predicate(Node::Perm(PermTest::Exact, mode.bits()))
+    }
+
+    /// Every bit of `mode` is set.
+    #[cfg(unix)]
+    pub fn perm_all(mode: Mode) -> Predicate {
+        Predicate(Node::Perm(PermTest::All, mode.bits()))
+    }
+
+    /// At least one bit of `mode` is set.
+    #[cfg(unix)]
+    pub fn perm_any(mode: Mode) -> Predicate {
+        Predicate(Node::Perm(PermTest::Any, mode.bits()))
+    }
+
+    /// Owned by the user with this id (see [`crate::user_id`]).
+    #[cfg(unix)]
+    pub fn user(uid: u32) -> Predicate {
+        Predicate(Node::User(uid))
+    }
+
+    /// Owned by the group with this id (see [`crate::group_id`]).
+    #[cfg(unix)]
+    pub fn group(gid: u32) -> Predicate {
+        Predicate(Node::Group(gid))
+    }
+
+    /// Both predicates match. The right side is skipped if the left fails.
+    pub fn and(self, other: Predicate) -> Predicate {
+        match (self.0, other.0) {
+            (Node::Always, node) | (node, Node::Always) => Predicate(node),
+            (left, right) => Predicate(Node::And(Box::new(left), Box::new(right))),
+        }
+    }
+
+    /// Either predicate matches. The right side is skipped if the left matches.
+    pub fn or(self, other: Predicate) -> Predicate {
+        Predicate(Node::Or(Box::new(self.0), Box::new(other.0)))
+    }
+
+    /// Whether `entry` passes the test.
+    pub fn matches(&self, entry: &WalkEntry) -> bool {
+        self.0.matches(entry)
+    }
+}
+
+impl Default for Predicate {
+    fn default() -> Predicate {
+        Predicate::always()
+    }
+}
+
+impl Not for Predicate {
+    type Output = Predicate;
+
+    fn not(self) -> Predicate {
+        Predicate(Node::Not(Box::new(self.0)))
+    }
+}
+
+impl Node {
+    fn matches(&self, entry: &WalkEntry) -> bool {
+        let metadata = &entry.metadata;
+        match self {
+            Node::Always => true,
+            Node::Name(pattern) => entry
+                .path
+                .file_name()
+                .is_some_and(|name| pattern.matches(&name.to_string_lossy())),
+            Node::Regex(regex) => regex.is_match(&entry.path.to_string_lossy()),
+            Node::Type(entry_type) => EntryType::of(metadata) == *entry_type,
+            Node::Size(range) => range.contains(metadata.len()),
+            Node::Modified(bound) => metadata.modified().is_ok_and(|time| bound.holds(time)),
+            Node::Accessed(bound) => metadata.accessed().is_ok_and(|time| bound.holds(time)),
+            Node::Empty => match EntryType::of(metadata) {
+                EntryType::File => metadata.len() == 0,
+                EntryType::Dir => {
+                    fs::read_dir(&entry.path).is_ok_and(|mut children| children.next().is_none())
+                }
+                _ => false,
+            },
+            #[cfg(unix)]
+            Node::Perm(test, bits) => {
+                let mode = metadata.mode() & 0o7777;
+                match test {
+                    PermTest::Exact => mode == *bits,
+                    PermTest::All => mode & bits == *bits,
+                    PermTest::Any => *bits == 0 || mode & bits != 0,
+                }
+            }
+            #[cfg(unix)]
+            Node::User(uid) => metadata.uid() == *uid,
+            #[cfg(unix)]
+            Node::Group(gid) => metadata.gid() == *gid,
+            Node::And(left, right) => left.matches(entry) && right.matches(entry),
+            Node::Or(left, right) => left.matches(entry) || right.matches(entry),
+            Node::Not(inner) => !inner.matches(entry),
+        }
+    }
+}
+
+impl Bound {
+    fn holds(&self, time: SystemTime) -> bool {
+        match *self {
+            Bound::Before(limit) => time < limit,
+            Bound::After(limit) => time > limit,
+        }
+    }
+}
+
+/// Search a directory tree for entries matching a [`Predicate`].
+///
+/// The search is lazy: iterating the returned [`Find`] reads one directory
+/// at a time and yields matches as soon as they are seen, in depth-first
+/// order with each directory's children sorted by name. The root itself is
+/// tested too. Symbolic links are reported but never followed. A directory
+/// that cannot be read yields an `Err` and the search carries on.
+///
+/// Builder methods AND their test onto what is already there; use
+/// [`Find::filter`] for anything involving `or` or `!`.
+///
+/// Examples
+/// ```
+/// use soil::{find, write_file, ensure_dir, remove_dir_all, EntryType};
+/// ensure_dir("find_example/logs").unwrap();
+/// write_file("find_example/logs/app.log", "started").unwrap();
+/// write_file("find_example/logs/app.txt", "").unwrap();
+///
+/// let logs: Vec<_> = find("find_example")
+///     .name("*.log")
+///     .file_type(EntryType::File)
+///     .into_iter()
+///     .map(|entry| entry.unwrap().path)
+///     .collect();
+/// assert_eq!(logs.len(), 1);
+/// assert!(logs[0].ends_with("logs/app.log"));
+/// remove_dir_all("find_example").unwrap();
+/// ```
+pub fn find<P: AsRef<Path>>(root: P) -> Find {
+    Find {
+        root: root.as_ref().to_path_buf(),
+        predicate: Predicate::always(),
+        min_depth: 0,
+        max_depth: None,
+        ignore: false,
+    }
+}
+
+/// A pending search built by [`find`]. Iterate it to run the search.
+#[derive(Clone, Debug)]
+pub struct Find {
+    root: PathBuf,
+    predicate: Predicate,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    ignore: bool,
+}
+
+impl Find {
+    /// Only report entries that also match `predicate`.
+    pub fn filter(mut self, predicate: Predicate) -> Find {
+        self.predicate = self.predicate.and(predicate);
+        self
+    }
+
+    /// Shorthand for `filter(Predicate::name(glob))`.
+    pub fn name(self, glob: &str) -> Find {
+        self.filter(Predicate::name(glob))
+    }
+
+    /// Shorthand for `filter(Predicate::file_type(entry_type))`.
+    pub fn file_type(self, entry_type: EntryType) -> Find {
+        self.filter(Predicate::file_type(entry_type))
+    }
+
+    /// Shorthand for `filter(Predicate::size(range))`.
+    pub fn size(self, range: SizeRange) -> Find {
+        self.filter(Predicate::size(range))
+    }
+
+    /// Do not report entries shallower than `depth` (the root is depth 0).
+    pub fn min_depth(mut self, depth: usize) -> Find {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Do not descend below `depth`.
+    pub fn max_depth(mut self, depth: usize) -> Find {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Skip entries excluded by ignore files (see [`IgnoreRules`]) and never
+    /// descend into ignored directories. Off by default.
+    pub fn ignore(mut self, ignore: bool) -> Find {
+        self.ignore = ignore;
+        self
+    }
+}
+
+impl IntoIterator for Find {
+    type Item = Result<WalkEntry, Error>;
+    type IntoIter = FindIter;
+
+    fn into_iter(self) -> FindIter {
+        let pending = match fs::symlink_metadata(&self.root) {
+            Ok(metadata) => vec![Ok(WalkEntry {
+                path: self.root.clone(),
+                depth: 0,
+                metadata,
+            })],
+            Err(error) => vec![Err(Error::new(
+                error.kind(),
+                format!("{}: {}", self.root.display(), error),
+            ))],
+        };
+        FindIter {
+            rules: self.ignore.then(|| IgnoreRules::new(&self.root)),
+            find: self,
+            pending,
+        }
+    }
+}
+
+/// Iterator over the matches of a [`Find`].
+pub struct FindIter {
+    find: Find,
+    // Entries still to visit, popped from the back.
+    pending: Vec<Result<WalkEntry, Error>>,
+    rules: Option<IgnoreRules>,
+}
+
+impl FindIter {
+    fn descend(&mut self, dir: &WalkEntry) -> Result<(), Error> {
+        let mut children = Vec::new();
+        for child in fs::read_dir(&dir.path)? {
+            let child = child.and_then(|child| {
+                Ok(WalkEntry {
+                    metadata: child.metadata()?,
+                    path: child.path(),
+                    depth: dir.depth + 1,
+                })
+            });
+            if let (Ok(child), Some(rules)) = (&child, &self.rules)
+                && rules.ignores_entry(&child.path, child.metadata.is_dir())
+            {
+                continue;
+            }
+            children.push(child);
+        }
+        // Sort descending so popping from the back visits names in order.
+        children.sort_by(|a, b| match (a, b) {
+            (Ok(a), Ok(b)) => b.path.cmp(&a.path),
+            (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+            (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+            (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+        });
+        self.pending.extend(children);
+        Ok(())
+    }
+}
+
+impl Iterator for FindIter {
+    type Item = Result<WalkEntry, Error>;
+
+    fn next(&mut self) -> Option<Result<WalkEntry, Error>> {
+        loop {
+            let entry = match self.pending.pop()? {
+                Ok(entry) => entry,
+                Err(error) => return Some(Err(error)),
+            };
+
+            let within_depth = self.find.max_depth.is_none_or(|max| entry.depth < max);
+            if entry.metadata.is_dir()
+                && within_depth
+                && let Err(error) = self.descend(&entry)
+            {
+                self.pending.push(Err(Error::new(
+                    error.kind(),
+                    format!("{}: {}", entry.path.display(), error),
+                )));
+            }
+
+            if entry.depth >= self.find.min_depth && self.find.predicate.matches(&entry) {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{Timestamp, ensure_dir, set_times, write_file};
+
+    fn names(search: Find) -> Vec<String> {
+        search
+            .into_iter()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                entry
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    fn build_tree(root: &str) {
+        ensure_dir(format!("{}/tree/logs/old", root)).unwrap();
+        ensure_dir(format!("{}/tree/empty", root)).unwrap();
+        write_file(format!("{}/tree/logs/app.log", root), "x".repeat(2048)).unwrap();
+        write_file(format!("{}/tree/logs/APP.LOG", root), "tiny").unwrap();
+        write_file(format!("{}/tree/logs/old/app.log.gz", root), "").unwrap();
+        write_file(format!("{}/tree/readme.md", root), "# hi").unwrap();
+    }
+
+    #[test]
+    fn test_size_range() {
+        let range: SizeRange = "+1k".parse().unwrap();
+        assert_eq!(range.min, Some(1025));
+        assert_eq!(range.to_string(), "+1024");
+        let range: SizeRange = "-2M".parse().unwrap();
+        assert_eq!(range.max, Some(2 * 1024 * 1024 - 1));
+        let range: SizeRange = "10c".parse().unwrap();
+        assert!(range.contains(10) && !range.contains(11));
+        assert!("-0".parse::<SizeRange>().is_err());
+        assert!("10Q".parse::<SizeRange>().is_err());
+        assert!("+".parse::<SizeRange>().is_err());
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(
+            parse_age("2w").unwrap(),
+            Duration::from_secs(14 * 24 * 3600)
+        );
+        assert_eq!(parse_age("15m").unwrap(), Duration::from_secs(900));
+        assert!(parse_age("").is_err());
+        assert!(parse_age("3y").is_err());
+        assert!(parse_age(&format!("{}s", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_find_order_and_depth() {
+        let guard = setup_test();
+        build_tree(&guard.test_root);
+        let root = format!("{}/tree", guard.test_root);
+
+        assert_eq!(
+            names(find(&root)),
+            [
+                "tree",
+                "empty",
+                "logs",
+                "APP.LOG",
+                "app.log",
+                "old",
+                "app.log.gz",
+                "readme.md"
+            ]
+        );
+        assert_eq!(
+            names(find(&root).max_depth(1)),
+            ["tree", "empty", "logs", "readme.md"]
+        );
+        assert_eq!(names(find(&root).min_depth(3)), ["app.log.gz"]);
+    }
+
+    #[test]
+    fn test_find_predicates() {
+        let guard = setup_test();
+        build_tree(&guard.test_root);
+        let root = format!("{}/tree", guard.test_root);
+
+        assert_eq!(names(find(&root).name("*.log")), ["app.log"]);
+        assert_eq!(
+            names(find(&root).filter(Predicate::iname("*.log"))),
+            ["APP.LOG", "app.log"]
+        );
+        assert_eq!(
+            names(find(&root).filter(Predicate::regex(r"old/.*\.gz$").unwrap())),
+            ["app.log.gz"]
+        );
+        assert_eq!(
+            names(find(&root).file_type(EntryType::Dir)),
+            ["tree", "empty", "logs", "old"]
+        );
+        assert_eq!(
+            names(
+                find(&root)
+                    .file_type(EntryType::File)
+                    .size("+1k".parse().unwrap())
+            ),
+            ["app.log"]
+        );
+        assert_eq!(
+            names(find(&root).filter(Predicate::empty())),
+            ["empty", "app.log.gz"]
+        );
+        assert!(Predicate::regex("(").is_err());
+    }
+
+    #[test]
+    fn test_find_combinators() {
+        let guard = setup_test();
+        build_tree(&guard.test_root);
+        let root = format!("{}/tree", guard.test_root);
+
+        let files = Predicate::file_type(EntryType::File);
+        assert_eq!(
+            names(find(&root).filter(files.clone().and(!Predicate::iname("*.log")))),
+            ["app.log.gz", "readme.md"]
+        );
+        assert_eq!(
+            names(find(&root).filter(Predicate::name("*.md").or(Predicate::name("*.gz")))),
+            ["app.log.gz", "readme.md"]
+        );
+        assert_eq!(names(find(&root).filter(!Predicate::always())).len(), 0);
+    }
+
+    #[test]
+    fn test_find_times() {
+        let guard = setup_test();
+        build_tree(&guard.test_root);
+        let root = format!("{}/tree", guard.test_root);
+        let week_ago = SystemTime::now() - Duration::from_secs(8 * 24 * 3600);
+        let old = format!("{}/logs/app.log", root);
+        set_times(&old, Timestamp::At(week_ago), Timestamp::At(week_ago)).unwrap();
+
+        let files = find(&root).file_type(EntryType::File);
+        assert_eq!(
+            names(
+                files
+                    .clone()
+                    .filter(Predicate::older_than(parse_age("7d").unwrap()))
+            ),
+            ["app.log"]
+        );
+        assert_eq!(
+            names(files.clone().filter(Predicate::accessed_before(
+                week_ago + Duration::from_secs(1)
+            ))),
+            ["app.log"]
+        );
+        assert_eq!(
+            names(files.clone().filter(Predicate::newer_than(&old).unwrap())),
+            ["APP.LOG", "app.log.gz", "readme.md"]
+        );
+        assert_eq!(
+            names(
+                files
+                    .clone()
+                    .filter(Predicate::accessed_older_than(parse_age("7d").unwrap()))
+            ),
+            ["app.log"]
+        );
+        assert_eq!(
+            names(files.clone().filter(Predicate::older_than(Duration::MAX))).len(),
+            0
+        );
+        assert_eq!(
+            names(
+                files
+                    .clone()
+                    .filter(Predicate::accessed_older_than(Duration::MAX))
+            )
+            .len(),
+            0
+        );
+        assert_eq!(
+            names(files.filter(Predicate::accessed_newer_than_age(Duration::MAX))).len(),
+            4
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_perm_and_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let guard = setup_test();
+        build_tree(&guard.test_root);
+        let root = format!("{}/tree", guard.test_root);
+        let script = format!("{}/readme.md", root);
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let files = find(&root).file_type(EntryType::File);
+        let exec = Mode::from_bits(0o111);
+        assert_eq!(
+            names(files.clone().filter(Predicate::perm_any(exec))),
+            ["readme.md"]
+        );
+        assert_eq!(
+            names(files.clone().filter(Predicate::perm_all(exec))),
+            ["readme.md"]
+        );
+        assert_eq!(
+            names(
+                files
+                    .clone()
+                    .filter(Predicate::perm_exact(Mode::from_bits(0o755)))
+            ),
+            ["readme.md"]
+        );
+
+        let uid = fs::metadata(&script).unwrap().uid();
+        assert_eq!(names(files.clone().filter(Predicate::user(uid))).len(), 4);
+        assert_eq!(names(files.filter(!Predicate::user(uid))).len(), 0);
+    }
+
+    #[test]
+    fn test_find_ignore() {
+        let guard = setup_test();
+        let root = guard.test_root.clone();
+        build_tree(&root);
+        write_file(format!("{}/tree/.ignore", root), "logs/\n").unwrap();
+
+        let search = find(format!("{}/tree", root)).min_depth(1).max_depth(1);
+        assert!(names(search.clone()).contains(&"logs".to_string()));
+        let kept = names(search.ignore(true));
+        assert!(!kept.contains(&"logs".to_string()));
+        assert!(kept.contains(&".ignore".to_string()));
+    }
+
+    #[test]
+    fn test_find_missing_root() {
+        let mut results = find("/definitely/not/here").into_iter();
+        let error = results.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().starts_with("/definitely/not/here: "));
+        assert!(results.next().is_none());
+    }
+}
@@ -0,0 +1,655 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// A shell-style wildcard pattern.
+///
+/// Supports `*` (any run of characters), `?` (one character), `**` (any run
+/// including `/`), character classes such as `[abc]`, `[a-z]` and `[!0-9]`
+/// (or `[^0-9]`), brace alternatives such as `*.{rs,toml}`, and `\` to
+/// escape the next character. An unterminated `[` matches itself.
+///
+/// [`Pattern::matches`] compares a single name, where `*` and `**` are the
+/// same. [`Pattern::matches_path`] compares a `/`-separated path, where only
+/// `**` crosses separators and `**/` also matches no directory at all.
+///
+/// Examples
+/// ```
+/// use soil::Pattern;
+/// let pattern = Pattern::new("*.log");
+/// assert!(pattern.matches("server.log"));
+/// assert!(!pattern.matches("server.log.gz"));
+///
+/// let pattern = Pattern::new("report-[0-9][0-9].{CSV,TSV}").case_insensitive(true);
+/// assert!(pattern.matches("Report-07.csv"));
+///
+/// let pattern = Pattern::new("src/**/*.rs");
+/// assert!(pattern.matches_path("src/main.rs"));
+/// assert!(pattern.matches_path("src/a/b/lib.rs"));
+/// assert!(!Pattern::new("src/*.rs").matches_path("src/a/lib.rs"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    alternatives: Vec<Vec<Token>>,
+    case_insensitive: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnyRun,
+    /// `**`: anything, separators included.
+    AnyPath,
+    /// `**/`: nothing, or anything ending in a separator.
+    AnyDirs,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    /// Compile a pattern. Matching is case-sensitive by default.
+    pub fn new(pattern: &str) -> Pattern {
+        Pattern {
+            source: pattern.to_string(),
+            alternatives: expand_braces(pattern)
+                .iter()
+                .map(|alternative| tokenize(alternative))
+                .collect(),
+            case_insensitive: false,
+        }
+    }
+
+    /// Ignore case when matching.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Pattern {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// The pattern as written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern contains no wildcard or brace at all.
+    pub fn is_literal(&self) -> bool {
+        self.literal_text().is_some()
+    }
+
+    /// Whether `text` matches the whole pattern, treating `/` like any other
+    /// character.
+    pub fn matches(&self, text: &str) -> bool {
+        self.matches_with(text, false)
+    }
+
+    /// Whether a `/`-separated `path` matches the whole pattern.
+    pub fn matches_path(&self, path: &str) -> bool {
+        self.matches_with(path, true)
+    }
+
+    fn matches_with(&self, text: &str, path_mode: bool) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.alternatives.iter().any(|tokens| {
+            let mut matcher = Matcher {
+                pattern: self,
+                tokens,
+                text: &text,
+                path_mode,
+                failed: vec![false; (tokens.len() + 1) * (text.len() + 1)],
+            };
+            matcher.matches(0, 0)
+        })
+    }
+
+    /// The text the pattern stands for, if it has no wildcards or braces.
+    fn literal_text(&self) -> Option<String> {
+        match self.alternatives.as_slice() {
+            [tokens] => tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Literal(ch) => Some(*ch),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    fn token_matches(&self, token: &Token, ch: char) -> bool {
+        match token {
+            Token::Literal(expected) => self.chars_equal(*expected, ch),
+            Token::AnyChar | Token::AnyRun | Token::AnyPath | Token::AnyDirs => true,
+            Token::Class { negated, ranges } => {
+                let hit = ranges.iter().any(|&(low, high)| {
+                    (low..=high).contains(&ch)
+                        || (self.case_insensitive
+                            && (fold_contains(low, high, ch.to_lowercase())
+                                || fold_contains(low, high, ch.to_uppercase())))
+                });
+                hit != *negated
+            }
+        }
+    }
+
+    fn chars_equal(&self, a: char, b: char) -> bool {
+        a == b || (self.case_insensitive && a.to_lowercase().eq(b.to_lowercase()))
+    }
+}
+
+/// Backtracking matcher for one brace alternative. `failed` remembers
+/// `(token, position)` pairs already known not to match, so patterns with
+/// many stars stay polynomial.
+struct Matcher<'a> {
+    pattern: &'a Pattern,
+    tokens: &'a [Token],
+    text: &'a [char],
+    path_mode: bool,
+    failed: Vec<bool>,
+}
+
+impl Matcher<'_> {
+    fn matches(&mut self, t: usize, i: usize) -> bool {
+        if t == self.tokens.len() {
+            return i == self.text.len();
+        }
+        let key = t * (self.text.len() + 1) + i;
+        if self.failed[key] {
+            return false;
+        }
+
+        let found = match &self.tokens[t] {
+            Token::AnyRun => {
+                let mut end = i;
+                loop {
+                    if self.matches(t + 1, end) {
+                        break true;
+                    }
+                    if end == self.text.len() || self.is_separator(end) {
+                        break false;
+                    }
+                    end += 1;
+                }
+            }
+            Token::AnyPath => (i..=self.text.len()).any(|end| self.matches(t + 1, end)),
+            Token::AnyDirs => {
+                self.matches(t + 1, i)
+                    || (i..self.text.len())
+                        .filter(|&end| self.text[end] == '/')
+                        .any(|end| self.matches(t + 1, end + 1))
+            }
+            token => {
+                i < self.text.len()
+                    && (matches!(token, Token::Literal(_)) || !self.is_separator(i))
+                    && self.pattern.token_matches(token, self.text[i])
+                    && self.matches(t + 1, i + 1)
+            }
+        };
+        if !found {
+            self.failed[key] = true;
+        }
+        found
+    }
+
+    fn is_separator(&self, i: usize) -> bool {
+        self.path_mode && self.text[i] == '/'
+    }
+}
+
+fn fold_contains(low: char, high: char, mut folded: impl Iterator<Item = char>) -> bool {
+    folded.any(|ch| (low..=high).contains(&ch))
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        match chars[index] {
+            '*' if chars.get(index + 1) == Some(&'*') => {
+                let at_component_start = index == 0 || chars[index - 1] == '/';
+                while chars.get(index + 1) == Some(&'*') {
+                    index += 1;
+                }
+                if at_component_start && chars.get(index + 1) == Some(&'/') {
+                    index += 1;
+                    tokens.push(Token::AnyDirs);
+                } else {
+                    tokens.push(Token::AnyPath);
+                }
+            }
+            '*' => {
+                // Collapse runs of stars; they mean the same thing.
+                if tokens.last() != Some(&Token::AnyRun) {
+                    tokens.push(Token::AnyRun);
+                }
+            }
+            '?' => tokens.push(Token::AnyChar),
+            '\\' if index + 1 < chars.len() => {
+                index += 1;
+                tokens.push(Token::Literal(chars[index]));
+            }
+            '[' => match parse_class(&chars, index) {
+                Some((token, end)) => {
+                    tokens.push(token);
+                    index = end;
+                }
+                None => tokens.push(Token::Literal('[')),
+            },
+            ch => tokens.push(Token::Literal(ch)),
+        }
+        index += 1;
+    }
+    tokens
+}
+
+/// Parse a `[...]` class starting at `start`. Returns the token and the index
+/// of the closing bracket.
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut index = start + 1;
+    let negated = matches!(chars.get(index), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    while index < chars.len() {
+        let ch = chars[index];
+        // A `]` right after the opening bracket is a literal.
+        if ch == ']' && !first {
+            return Some((Token::Class { negated, ranges }, index));
+        }
+        first = false;
+
+        let low = if ch == '\\' && index + 1 < chars.len() {
+            index += 1;
+            chars[index]
+        } else {
+            ch
+        };
+        if chars.get(index + 1) == Some(&'-') && chars.get(index + 2).is_some_and(|&c| c != ']') {
+            let high = chars[index + 2];
+            ranges.push((low, high));
+            index += 3;
+        } else {
+            ranges.push((low, low));
+            index += 1;
+        }
+    }
+    None
+}
+
+/// Expand `{a,b}` alternatives, nested groups included, into plain
+/// patterns. Braces without a top-level comma are left as they are.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 1,
+            '{' => {
+                if let Some((end, commas)) = find_brace_group(&chars, index) {
+                    let prefix: String = chars[..index].iter().collect();
+                    let suffix: String = chars[end + 1..].iter().collect();
+                    let mut bounds = vec![index];
+                    bounds.extend(commas);
+                    bounds.push(end);
+                    return bounds
+                        .windows(2)
+                        .flat_map(|pair| {
+                            let choice: String = chars[pair[0] + 1..pair[1]].iter().collect();
+                            expand_braces(&format!("{}{}{}", prefix, choice, suffix))
+                        })
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    vec![pattern.to_string()]
+}
+
+/// Find the `}` closing the group opened at `start` and the positions of its
+/// top-level commas. `None` when unclosed or when there is no comma.
+fn find_brace_group(chars: &[char], start: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut index = start + 1;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 1,
+            '{' => depth += 1,
+            '}' if depth == 0 => {
+                return (!commas.is_empty()).then_some((index, commas));
+            }
+            '}' => depth -= 1,
+            ',' if depth == 0 => commas.push(index),
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Settings for [`glob_with`].
+///
+/// Examples
+/// ```
+/// use soil::GlobOptions;
+/// let options = GlobOptions::new().case_insensitive(true).hidden(true);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlobOptions {
+    case_insensitive: bool,
+    hidden: bool,
+}
+
+impl GlobOptions {
+    /// Case-sensitive; hidden entries only matched by an explicit leading dot.
+    pub fn new() -> GlobOptions {
+        GlobOptions::default()
+    }
+
+    /// Ignore case when matching names.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> GlobOptions {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Let wildcards (and `**`) match names starting with a dot.
+    pub fn hidden(mut self, hidden: bool) -> GlobOptions {
+        self.hidden = hidden;
+        self
+    }
+}
+
+/// Return the paths matching a wildcard pattern, sorted and without
+/// duplicates.
+///
+/// Patterns are matched one path component at a time: `*`, `?` and classes
+/// stay within a component, `**` as a whole component matches any number of
+/// directories (including none, never following symbolic links), and braces
+/// expand first. As in shells, wildcards do not match a leading `.` unless
+/// the pattern spells it out. Directories that do not exist simply match
+/// nothing; other errors reading a directory are returned.
+///
+/// Examples
+/// ```
+/// use soil::{glob, ensure_dir, write_file, remove_dir_all};
+/// use std::path::PathBuf;
+/// ensure_dir("glob_example/src/bin").unwrap();
+/// write_file("glob_example/src/lib.rs", "").unwrap();
+/// write_file("glob_example/src/bin/tool.rs", "").unwrap();
+/// write_file("glob_example/Cargo.toml", "").unwrap();
+///
+/// assert_eq!(
+///     glob("glob_example/**/*.{rs,toml}").unwrap(),
+///     [
+///         PathBuf::from("glob_example/Cargo.toml"),
+///         PathBuf::from("glob_example/src/bin/tool.rs"),
+///         PathBuf::from("glob_example/src/lib.rs"),
+///     ]
+/// );
+/// remove_dir_all("glob_example").unwrap();
+/// ```
+pub fn glob(pattern: &str) -> Result<Vec<PathBuf>, Error> {
+    glob_with(pattern, GlobOptions::new())
+}
+
+/// Like [`glob`], with [`GlobOptions`].
+pub fn glob_with(pattern: &str, options: GlobOptions) -> Result<Vec<PathBuf>, Error> {
+    let mut found = Vec::new();
+    for alternative in expand_braces(pattern) {
+        let (base, rest) = match alternative.strip_prefix('/') {
+            Some(rest) => (PathBuf::from("/"), rest.to_string()),
+            None => (PathBuf::new(), alternative),
+        };
+        let components: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+        expand(&base, &components, options, &mut found)?;
+    }
+    found.sort();
+    found.dedup();
+    Ok(found)
+}
+
+/// Whether `text` contains glob syntax (`*`, `?`, `[` or `{`).
+///
+/// Examples
+/// ```
+/// assert!(soil::is_glob("*.tmp"));
+/// assert!(!soil::is_glob("notes.txt"));
+/// ```
+pub fn is_glob(text: &str) -> bool {
+    text.contains(['*', '?', '[', '{'])
+}
+
+fn expand(
+    dir: &Path,
+    components: &[&str],
+    options: GlobOptions,
+    found: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let Some((&component, rest)) = components.split_first() else {
+        if !dir.as_os_str().is_empty() {
+            found.push(dir.to_path_buf());
+        }
+        return Ok(());
+    };
+
+    if component == "**" {
+        expand(dir, rest, options, found)?;
+        for (child, is_dir) in list(dir)? {
+            if is_dir
+                && (options.hidden || !is_hidden(&child))
+                && !fs::symlink_metadata(&child)?.file_type().is_symlink()
+            {
+                expand(&child, components, options, found)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if component == "." || component == ".." {
+        return expand(&dir.join(component), rest, options, found);
+    }
+
+    let pattern = Pattern::new(component).case_insensitive(options.case_insensitive);
+    if !options.case_insensitive
+        && let Some(name) = pattern.literal_text()
+    {
+        let path = dir.join(name);
+        let exists = if rest.is_empty() {
+            fs::symlink_metadata(&path).is_ok()
+        } else {
+            path.is_dir()
+        };
+        if exists {
+            expand(&path, rest, options, found)?;
+        }
+        return Ok(());
+    }
+
+    let explicit_dot = component.starts_with('.');
+    for (child, is_dir) in list(dir)? {
+        if is_hidden(&child) && !explicit_dot && !options.hidden {
+            continue;
+        }
+        let name = child.file_name().unwrap_or_default().to_string_lossy();
+        if pattern.matches(&name) && (rest.is_empty() || is_dir) {
+            expand(&child, rest, options, found)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Sorted children of `dir` (the current directory when empty) with whether
+/// each is a directory, following symlinks. A missing directory has none.
+fn list(dir: &Path) -> Result<Vec<(PathBuf, bool)>, Error> {
+    let read_from = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let entries = match fs::read_dir(read_from) {
+        Ok(entries) => entries,
+        Err(error) if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+            return Ok(Vec::new());
+        }
+        Err(error) => return Err(error),
+    };
+
+    let mut children = Vec::new();
+    for entry in entries {
+        let child = dir.join(entry?.file_name());
+        let is_dir = child.is_dir();
+        children.push((child, is_dir));
+    }
+    children.sort();
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{ensure_dir, write_file};
+
+    #[test]
+    fn test_wildcards() {
+        assert!(Pattern::new("*").matches(""));
+        assert!(Pattern::new("*").matches("anything"));
+        assert!(Pattern::new("a*b*c").matches("aXXbYYc"));
+        assert!(!Pattern::new("a*b*c").matches("aXXbYY"));
+        assert!(Pattern::new("?.rs").matches("a.rs"));
+        assert!(!Pattern::new("?.rs").matches("ab.rs"));
+        assert!(Pattern::new("**x").matches("yyx"));
+        assert!(Pattern::new("*aab").matches("aaab"));
+        assert!(!Pattern::new("*a*a*a*a*a*a*a*b").matches(&"a".repeat(200)));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(Pattern::new("[abc].txt").matches("b.txt"));
+        assert!(!Pattern::new("[abc].txt").matches("d.txt"));
+        assert!(Pattern::new("[a-c]x").matches("cx"));
+        assert!(Pattern::new("[!a-c]x").matches("dx"));
+        assert!(!Pattern::new("[^a-c]x").matches("ax"));
+        assert!(Pattern::new("[]]").matches("]"));
+        assert!(Pattern::new("[a-]").matches("-"));
+        assert!(Pattern::new("[").matches("["));
+        assert!(Pattern::new("x[").matches("x["));
+    }
+
+    #[test]
+    fn test_escape_and_literal() {
+        assert!(Pattern::new("\\*.txt").matches("*.txt"));
+        assert!(!Pattern::new("\\*.txt").matches("a.txt"));
+        assert!(Pattern::new("plain.txt").is_literal());
+        assert!(!Pattern::new("*.txt").is_literal());
+        assert!(!Pattern::new("{a,b}").is_literal());
+        assert!(Pattern::new("\\{a,b}").matches("{a,b}"));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let pattern = Pattern::new("*.JPG").case_insensitive(true);
+        assert!(pattern.matches("photo.jpg"));
+        assert!(pattern.matches("photo.JpG"));
+        assert!(!Pattern::new("*.JPG").matches("photo.jpg"));
+        assert!(Pattern::new("[A-Z]").case_insensitive(true).matches("q"));
+    }
+
+    #[test]
+    fn test_unicode() {
+        assert!(Pattern::new("?ber").matches("über"));
+        assert!(Pattern::new("*é").matches("café"));
+    }
+
+    #[test]
+    fn test_braces() {
+        assert_eq!(expand_braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand_braces("{x,y{1,2}}"), ["x", "y1", "y2"]);
+        assert_eq!(expand_braces("{a,b}{c,d}"), ["ac", "ad", "bc", "bd"]);
+        assert_eq!(expand_braces("{solo}"), ["{solo}"]);
+        assert_eq!(expand_braces("{open,"), ["{open,"]);
+        assert!(Pattern::new("*.{rs,toml}").matches("Cargo.toml"));
+        assert!(!Pattern::new("*.{rs,toml}").matches("README.md"));
+    }
+
+    #[test]
+    fn test_path_matching() {
+        assert!(Pattern::new("src/*.rs").matches_path("src/lib.rs"));
+        assert!(!Pattern::new("src/*.rs").matches_path("src/a/lib.rs"));
+        assert!(Pattern::new("src/*.rs").matches("src/a/lib.rs"));
+        assert!(Pattern::new("**/*.rs").matches_path("lib.rs"));
+        assert!(Pattern::new("**/*.rs").matches_path("a/b/lib.rs"));
+        assert!(Pattern::new("a/**/b").matches_path("a/b"));
+        assert!(Pattern::new("a/**/b").matches_path("a/x/y/b"));
+        assert!(!Pattern::new("a/**/b").matches_path("a/xb"));
+        assert!(Pattern::new("target/**").matches_path("target/debug/soil"));
+        assert!(!Pattern::new("?").matches_path("/"));
+        assert!(!Pattern::new("[/]").matches_path("/"));
+    }
+
+    #[test]
+    fn test_glob_paths() {
+        let guard = setup_test();
+        let root = guard.test_root.clone();
+        ensure_dir(format!("{}/src/nested/deep", root)).unwrap();
+        ensure_dir(format!("{}/.hidden", root)).unwrap();
+        write_file(format!("{}/src/main.rs", root), "").unwrap();
+        write_file(format!("{}/src/nested/deep/mod.rs", root), "").unwrap();
+        write_file(format!("{}/src/README.MD", root), "").unwrap();
+        write_file(format!("{}/.hidden/secret.rs", root), "").unwrap();
+        write_file(format!("{}/.env", root), "").unwrap();
+
+        let names = |pattern: &str, options: GlobOptions| -> Vec<String> {
+            glob_with(&format!("{}/{}", root, pattern), options)
+                .unwrap()
+                .iter()
+                .map(|path| {
+                    let relative = path.strip_prefix(&root).unwrap();
+                    relative.to_string_lossy().into_owned()
+                })
+                .collect()
+        };
+        let plain = GlobOptions::new();
+
+        assert_eq!(names("src/*.rs", plain), ["src/main.rs"]);
+        assert_eq!(
+            names("**/*.rs", plain),
+            ["src/main.rs", "src/nested/deep/mod.rs"]
+        );
+        assert_eq!(
+            names("**/*.rs", plain.hidden(true)),
+            [".hidden/secret.rs", "src/main.rs", "src/nested/deep/mod.rs"]
+        );
+        assert_eq!(names(".*", plain), [".env", ".hidden"]);
+        assert!(names("src/*.md", plain).is_empty());
+        assert_eq!(
+            names("src/*.md", plain.case_insensitive(true)),
+            ["src/README.MD"]
+        );
+        assert_eq!(
+            names("SRC/main.rs", plain.case_insensitive(true)),
+            ["src/main.rs"]
+        );
+        assert_eq!(
+            names("src/{main.rs,nested}", plain),
+            ["src/main.rs", "src/nested"]
+        );
+        assert_eq!(names("src/*/deep", plain), ["src/nested/deep"]);
+        assert!(names("missing/*", plain).is_empty());
+        assert!(names("src/main.rs/*", plain).is_empty());
+    }
+}
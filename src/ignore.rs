@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::glob::Pattern;
+use crate::path::normalize;
+
+/// Per-directory ignore files, from lowest to highest precedence.
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".soilignore"];
+
+/// Ignore rules for a tree, in the style of `.gitignore`.
+///
+/// Rules come from, in increasing precedence: the global git excludes file
+/// (`core.excludesFile`, else `~/.config/git/ignore`), the repository's
+/// `.git/info/exclude`, then every `.gitignore`, `.ignore` and `.soilignore`
+/// between the repository top (or the root when it is not in a repository)
+/// and the entry. A `.soilignore` rule beats an `.ignore` rule, which beats a
+/// `.gitignore` rule; among files of one kind the deeper one wins, and within
+/// a file the last matching line wins. `!pattern` re-includes, `pattern/`
+/// only matches directories, and a pattern containing a `/` is anchored to
+/// the directory of its file. The `.git` directory itself is always ignored.
+///
+/// Ignore files are read lazily and cached, so one instance can be shared
+/// by all threads of a walk.
+///
+/// Examples
+/// ```
+/// use soil::{ensure_dir, write_file, remove_dir_all, IgnoreRules};
+/// ensure_dir("ignore_example/target").unwrap();
+/// write_file("ignore_example/.gitignore", "target/\n*.log\n").unwrap();
+/// write_file("ignore_example/.soilignore", "!keep.log\n").unwrap();
+///
+/// let rules = IgnoreRules::new("ignore_example");
+/// assert!(rules.is_ignored("ignore_example/target", true));
+/// assert!(rules.is_ignored("ignore_example/target/debug/soil", false));
+/// assert!(rules.is_ignored("ignore_example/app.log", false));
+/// assert!(!rules.is_ignored("ignore_example/keep.log", false));
+/// assert!(!rules.is_ignored("ignore_example/src/main.rs", false));
+/// remove_dir_all("ignore_example").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    top: PathBuf,
+    /// Global excludes followed by `.git/info/exclude`.
+    base: Vec<Rule>,
+    cache: Mutex<HashMap<PathBuf, Arc<[Vec<Rule>; 3]>>>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    base: PathBuf,
+    pattern: Pattern,
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl IgnoreRules {
+    /// Load the rules that apply below `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> IgnoreRules {
+        IgnoreRules::load(root.as_ref(), global_excludes_file())
+    }
+
+    fn load(root: &Path, global: Option<PathBuf>) -> IgnoreRules {
+        let root = absolute(root);
+        let top = root
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .unwrap_or(&root)
+            .to_path_buf();
+
+        let mut base = Vec::new();
+        if let Some(global) = global {
+            base.extend(read_rules(&global, &top));
+        }
+        base.extend(read_rules(&top.join(".git/info/exclude"), &top));
+
+        IgnoreRules {
+            root,
+            top,
+            base,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `path` is ignored, either itself or because a directory
+    /// between the root and it is.
+    pub fn is_ignored<P: AsRef<Path>>(&self, path: P, is_dir: bool) -> bool {
+        let path = absolute(path.as_ref());
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let mut dir = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            dir.push(component);
+            let last = components.peek().is_none();
+            if self.decide(&dir, if last { is_dir } else { true }) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether `path` itself is ignored, assuming its parent directory was
+    /// not. This is what walkers need, since they never enter ignored
+    /// directories.
+    pub(crate) fn ignores_entry(&self, path: &Path, is_dir: bool) -> bool {
+        self.decide(&absolute(path), is_dir)
+    }
+
+    fn decide(&self, path: &Path, is_dir: bool) -> bool {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        let Some(parent) = path.parent() else {
+            return false;
+        };
+        let Ok(below_top) = parent.strip_prefix(&self.top) else {
+            return false;
+        };
+
+        let mut dirs = vec![self.top.clone()];
+        for component in below_top.components() {
+            let next = dirs[dirs.len() - 1].join(component);
+            dirs.push(next);
+        }
+        let dir_rules: Vec<_> = dirs.iter().map(|dir| self.rules_in(dir)).collect();
+
+        let mut ignored = false;
+        let ranked = (0..IGNORE_FILES.len())
+            .flat_map(|kind| dir_rules.iter().flat_map(move |rules| rules[kind].iter()));
+        for rule in self.base.iter().chain(ranked) {
+            if rule.matches(path, is_dir) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+
+    fn rules_in(&self, dir: &Path) -> Arc<[Vec<Rule>; 3]> {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| Arc::new(IGNORE_FILES.map(|name| read_rules(&dir.join(name), dir))))
+            .clone()
+    }
+}
+
+impl Rule {
+    /// Parse one line of an ignore file; `None` for blanks and comments.
+    fn parse(line: &str, base: &Path) -> Option<Rule> {
+        let mut line = line.trim_end_matches('\r');
+        // Trailing spaces are dropped unless escaped with a backslash.
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let negated = line.starts_with('!');
+        if negated {
+            line = &line[1..];
+        }
+        let dir_only = line.ends_with('/');
+        if dir_only {
+            line = &line[..line.len() - 1];
+        }
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Rule {
+            base: base.to_path_buf(),
+            pattern: Pattern::new(line),
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anchored {
+            let relative: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            self.pattern.matches_path(&relative.join("/"))
+        } else {
+            path.file_name()
+                .is_some_and(|name| self.pattern.matches(&name.to_string_lossy()))
+        }
+    }
+}
+
+fn read_rules(file: &Path, base: &Path) -> Vec<Rule> {
+    fs::read_to_string(file)
+        .map(|contents| {
+            contents
+                .lines()
+                .filter_map(|line| Rule::parse(line, base))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Absolute form of `path` with `.` and `..` resolved lexically, so rule
+/// bases and walked paths compare component by component.
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        normalize(path)
+    } else {
+        normalize(env::current_dir().unwrap_or_default().join(path))
+    }
+}
+
+/// The user's global git excludes file: `core.excludesFile` from the git
+/// config if set, else `$XDG_CONFIG_HOME/git/ignore` (or
+/// `~/.config/git/ignore`).
+fn global_excludes_file() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+
+    // ~/.gitconfig is read last so that it wins, as it does for git.
+    let configs = [
+        config_home.as_ref().map(|dir| dir.join("git/config")),
+        home.as_ref().map(|home| home.join(".gitconfig")),
+    ];
+    let mut configured = None;
+    for config in configs.iter().flatten() {
+        if let Ok(contents) = fs::read_to_string(config)
+            && let Some(value) = core_excludes_file(&contents)
+        {
+            configured = Some(value);
+        }
+    }
+
+    match configured {
+        Some(value) => match (value.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(value)),
+        },
+        None => config_home.map(|dir| dir.join("git/ignore")),
+    }
+}
+
+/// The `excludesFile` key of the `[core]` section of a git config file.
+fn core_excludes_file(config: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut found = None;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_core = line
+                .trim_matches(['[', ']'])
+                .trim()
+                .eq_ignore_ascii_case("core");
+        } else if in_core
+            && let Some((key, value)) = line.split_once('=')
+            && key.trim().eq_ignore_ascii_case("excludesfile")
+        {
+            found = Some(value.trim().trim_matches('"').to_string());
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{ensure_dir, write_file};
+
+    #[test]
+    fn test_parse_rules() {
+        let base = Path::new("/repo");
+        assert!(Rule::parse("", base).is_none());
+        assert!(Rule::parse("# comment", base).is_none());
+        assert!(Rule::parse("/", base).is_none());
+
+        let rule = Rule::parse("!/build/  ", base).unwrap();
+        assert!(rule.negated && rule.dir_only && rule.anchored);
+        assert_eq!(rule.pattern.as_str(), "build");
+
+        let rule = Rule::parse("\\#notes\\ ", base).unwrap();
+        assert!(!rule.negated && !rule.anchored);
+        assert!(rule.pattern.matches("#notes "));
+    }
+
+    #[test]
+    fn test_core_excludes_file() {
+        let config = "[user]\n\tname = x\n[core]\n\texcludesFile = \"~/.gitignore_global\"\n";
+        assert_eq!(core_excludes_file(config).unwrap(), "~/.gitignore_global");
+        assert!(core_excludes_file("[user]\nexcludesfile = nope\n").is_none());
+    }
+
+    #[test]
+    fn test_precedence_and_negation() {
+        let guard = setup_test();
+        let root = guard.test_root.clone();
+        ensure_dir(format!("{}/src/gen", root)).unwrap();
+        write_file(format!("{}/.gitignore", root), "*.log\n/build\ngen/\n").unwrap();
+        write_file(format!("{}/.ignore", root), "!important.log\n").unwrap();
+        write_file(format!("{}/src/.gitignore", root), "!debug.log\n").unwrap();
+        write_file(format!("{}/.soilignore", root), "secret.*\n").unwrap();
+        write_file(format!("{}/src/.ignore", root), "!secret.txt\n").unwrap();
+
+        let rules = IgnoreRules::load(Path::new(&root), None);
+        let at = |path: &str| format!("{}/{}", root, path);
+        assert!(rules.is_ignored(at("app.log"), false));
+        assert!(rules.is_ignored(at("src/app.log"), false));
+        assert!(!rules.is_ignored(at("important.log"), false));
+        // A deeper .gitignore overrides a shallower one.
+        assert!(!rules.is_ignored(at("src/debug.log"), false));
+        // Anchored patterns only match next to their file.
+        assert!(rules.is_ignored(at("build"), true));
+        assert!(!rules.is_ignored(at("src/build"), true));
+        // Directory-only patterns skip files and cover everything inside.
+        assert!(rules.is_ignored(at("src/gen/out.rs"), false));
+        assert!(!rules.is_ignored(at("gen"), false));
+        // .soilignore wins over a deeper .ignore.
+        assert!(rules.is_ignored(at("src/secret.txt"), false));
+        assert!(rules.is_ignored(at(".git"), true));
+        assert!(!rules.is_ignored("/elsewhere/app.log", false));
+    }
+
+    #[test]
+    fn test_global_and_repository_excludes() {
+        let guard = setup_test();
+        let root = guard.test_root.clone();
+        ensure_dir(format!("{}/.git/info", root)).unwrap();
+        ensure_dir(format!("{}/sub", root)).unwrap();
+        write_file(format!("{}/.git/info/exclude", root), "*.local\n").unwrap();
+        write_file(format!("{}/global", root), ".DS_Store\n*.swp\n").unwrap();
+        write_file(format!("{}/.gitignore", root), "!keep.swp\nsub/skip.txt\n").unwrap();
+
+        let global = PathBuf::from(format!("{}/global", root));
+        // Rooted below the repository top, rules from the top still apply.
+        let rules = IgnoreRules::load(Path::new(&format!("{}/sub", root)), Some(global));
+        let at = |path: &str| format!("{}/{}", root, path);
+        assert!(rules.is_ignored(at("sub/.DS_Store"), false));
+        assert!(rules.is_ignored(at("sub/x.local"), false));
+        assert!(rules.is_ignored(at("sub/a.swp"), false));
+        assert!(!rules.is_ignored(at("sub/keep.swp"), false));
+        assert!(rules.is_ignored(at("sub/skip.txt"), false));
+    }
+}
@@ -1,5 +1,56 @@
 use std::fs;
 use std::path::Path;
+use trace::TraceLevel::{All, Changes};
+use trace::traced;
+
+mod backup;
+mod copy;
+mod diff;
+#[cfg(unix)]
+mod dir;
+mod dump;
+mod find;
+mod glob;
+mod ignore;
+mod mode;
+mod owner;
+mod path;
+mod progress;
+mod read;
+mod replace;
+mod search;
+mod soil_path;
+mod times;
+mod trace;
+mod walk;
+mod write;
+
+pub use backup::Backup;
+pub use copy::{CopyOptions, CopyStrategy, Preserve, copy_file_with};
+pub use diff::unified_diff;
+#[cfg(unix)]
+pub use dir::{Dir, DirStat};
+pub use dump::{base64_encode, c_array, escape_text, hex_dump};
+pub use find::{EntryType, Find, FindIter, Predicate, SizeRange, find, parse_age};
+pub use glob::{GlobOptions, Pattern, glob, glob_with, is_glob};
+pub use ignore::IgnoreRules;
+pub use mode::{Mode, ModeChange};
+pub use owner::{Owner, group_id, user_id};
+pub use path::{expand, expand_with, is_within, normalize, relative_to};
+pub use progress::{
+    CancelToken, Progress, copy_file_with_progress, move_path_with_progress,
+    remove_dir_all_with_progress,
+};
+pub use read::{Chunks, Lines, read_chunks, read_lines, read_range};
+pub use replace::{ReplaceOptions, Replacement, replace_in_files};
+pub use search::{Match, Search, SearchOptions, search};
+pub use soil_path::SoilPath;
+#[cfg(unix)]
+pub use times::set_symlink_times;
+pub use times::{Timestamp, copy_times, format_datetime, parse_datetime, set_times, touch};
+pub use trace::{TraceLevel, clear_trace, set_trace};
+pub use walk::{BulkReport, WalkEntry, Walker};
+pub use write::WriteOptions;
 
 pub const TEST_ROOT: &str = "./test_root";
 
@@ -29,7 +80,12 @@ pub const TEST_ROOT: &str = "./test_root";
 /// assert!(resolve_path("/non/existent/path").is_err());
 /// ```
 pub fn resolve_path<P: AsRef<Path>>(path: P) -> Result<String, std::io::Error> {
-    match fs::canonicalize(path.as_ref()) {
+    let path = path.as_ref();
+    match traced(
+        All,
+        format_args!("realpath({:?})", path),
+        fs::canonicalize(path),
+    ) {
         Ok(path) => Ok(path.to_string_lossy().into_owned()),
         Err(error) => Err(error),
     }
@@ -37,6 +93,9 @@ pub fn resolve_path<P: AsRef<Path>>(path: P) -> Result<String, std::io::Error> {
 
 /// Copy a file from `src` to `dst`.
 ///
+/// Uses the fastest method available ([`CopyStrategy::Auto`]); see
+/// [`copy_file_with`] to pick one explicitly.
+///
 /// Arguments
 /// - `src`: Source file path
 /// - `dst`: Destination file path
@@ -52,12 +111,42 @@ pub fn resolve_path<P: AsRef<Path>>(path: P) -> Result<String, std::io::Error> {
 /// remove_file("copy_dst.txt").unwrap();
 /// ```
 pub fn copy_file<P1: AsRef<Path>, P2: AsRef<Path>>(src: P1, dst: P2) -> Result<(), std::io::Error> {
-    match fs::copy(src.as_ref(), dst.as_ref()) {
+    match copy_file_with(src, dst, CopyStrategy::Auto) {
         Ok(_) => Ok(()),
         Err(error) => Err(error),
     }
 }
 
+/// Copy a file like [`copy_file`], first backing up `dst` if it exists.
+///
+/// Examples
+/// ```
+/// use soil::{copy_file_with_backup, write_file, read_text, remove_file, Backup};
+/// write_file("backup_copy_src.txt", "new").unwrap();
+/// write_file("backup_copy_dst.txt", "old").unwrap();
+/// copy_file_with_backup("backup_copy_src.txt", "backup_copy_dst.txt", &Backup::Simple).unwrap();
+/// assert_eq!(read_text("backup_copy_dst.txt").unwrap(), "new");
+/// assert_eq!(read_text("backup_copy_dst.txt~").unwrap(), "old");
+/// # for file in ["backup_copy_src.txt", "backup_copy_dst.txt", "backup_copy_dst.txt~"] {
+/// #     remove_file(file).unwrap();
+/// # }
+/// ```
+pub fn copy_file_with_backup<P1: AsRef<Path>, P2: AsRef<Path>>(
+    src: P1,
+    dst: P2,
+    backup: &Backup,
+) -> Result<(), std::io::Error> {
+    copy::copy_file_tracked(
+        src.as_ref(),
+        dst.as_ref(),
+        CopyStrategy::Auto,
+        true,
+        backup,
+        &mut progress::Tracker::silent(),
+    )?;
+    Ok(())
+}
+
 /// Create a directory and all missing parents (mkdir -p).
 ///
 /// Examples
@@ -68,7 +157,12 @@ pub fn copy_file<P1: AsRef<Path>, P2: AsRef<Path>>(src: P1, dst: P2) -> Result<(
 /// remove_dir_all("tmp_mkdirp").unwrap();
 /// ```
 pub fn ensure_dir<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
-    match fs::create_dir_all(path.as_ref()) {
+    let path = path.as_ref();
+    match traced(
+        Changes,
+        format_args!("mkdir -p {:?}", path),
+        fs::create_dir_all(path),
+    ) {
         Ok(_) => Ok(()),
         Err(error) => Err(error),
     }
@@ -112,7 +206,8 @@ pub fn exists<P: AsRef<Path>>(path: P) -> bool {
 /// remove_dir_all("test_list").unwrap();
 /// ```
 pub fn list_dir<P: AsRef<Path>>(path: P) -> Result<Vec<String>, std::io::Error> {
-    let entries = fs::read_dir(path.as_ref())?;
+    let path = path.as_ref();
+    let entries = traced(All, format_args!("opendir({:?})", path), fs::read_dir(path))?;
     let mut names = Vec::new();
 
     for entry in entries {
@@ -140,7 +235,12 @@ pub fn list_dir<P: AsRef<Path>>(path: P) -> Result<Vec<String>, std::io::Error>
 /// remove_dir_all("parent_dir").unwrap();
 /// ```
 pub fn create_dir<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
-    fs::create_dir(path.as_ref())
+    let path = path.as_ref();
+    traced(
+        Changes,
+        format_args!("mkdir({:?})", path),
+        fs::create_dir(path),
+    )
 }
 
 /// Remove a file.
@@ -153,7 +253,12 @@ pub fn create_dir<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
 /// assert!(!exists("temp_remove.txt"));
 /// ```
 pub fn remove_file<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
-    fs::remove_file(path.as_ref())
+    let path = path.as_ref();
+    traced(
+        Changes,
+        format_args!("unlink({:?})", path),
+        fs::remove_file(path),
+    )
 }
 
 /// Remove an empty directory.
@@ -167,7 +272,12 @@ pub fn remove_file<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
 /// assert!(!exists("empty_dir"));
 /// ```
 pub fn remove_empty_dir<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
-    fs::remove_dir(path.as_ref())
+    let path = path.as_ref();
+    traced(
+        Changes,
+        format_args!("rmdir({:?})", path),
+        fs::remove_dir(path),
+    )
 }
 
 /// Remove a directory and all of its contents (recursive delete).
@@ -182,7 +292,12 @@ pub fn remove_empty_dir<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
 /// assert!(!exists("tmp_dir"));
 /// ```
 pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
-    fs::remove_dir_all(path.as_ref())
+    let path = path.as_ref();
+    traced(
+        Changes,
+        format_args!("rm -r {:?}", path),
+        fs::remove_dir_all(path),
+    )
 }
 
 /// Move or rename a file or directory.
@@ -197,7 +312,37 @@ pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
 /// remove_file("moved.txt").unwrap();
 /// ```
 pub fn move_path<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(), std::io::Error> {
-    fs::rename(from.as_ref(), to.as_ref())
+    let (from, to) = (from.as_ref(), to.as_ref());
+    traced(
+        Changes,
+        format_args!("rename({:?}, {:?})", from, to),
+        fs::rename(from, to),
+    )
+}
+
+/// Move a file or directory like [`move_path`], first backing up `to` if it
+/// is an existing file.
+///
+/// Examples
+/// ```
+/// use soil::{move_path_with_backup, write_file, read_text, exists, remove_file, Backup};
+/// write_file("backup_move_src.txt", "new").unwrap();
+/// write_file("backup_move_dst.txt", "old").unwrap();
+/// move_path_with_backup("backup_move_src.txt", "backup_move_dst.txt", &Backup::Simple).unwrap();
+/// assert!(!exists("backup_move_src.txt"));
+/// assert_eq!(read_text("backup_move_dst.txt~").unwrap(), "old");
+/// # for file in ["backup_move_dst.txt", "backup_move_dst.txt~"] {
+/// #     remove_file(file).unwrap();
+/// # }
+/// ```
+pub fn move_path_with_backup<P1: AsRef<Path>, P2: AsRef<Path>>(
+    from: P1,
+    to: P2,
+    backup: &Backup,
+) -> Result<(), std::io::Error> {
+    symlink_metadata(from.as_ref())?;
+    backup.backup(to.as_ref())?;
+    move_path(from, to)
 }
 
 /// Get metadata for a file or directory.
@@ -212,7 +357,8 @@ pub fn move_path<P1: AsRef<Path>, P2: AsRef<Path>>(from: P1, to: P2) -> Result<(
 /// remove_file("file.txt").unwrap();
 /// ```
 pub fn metadata<P: AsRef<Path>>(path: P) -> Result<fs::Metadata, std::io::Error> {
-    fs::metadata(path.as_ref())
+    let path = path.as_ref();
+    traced(All, format_args!("stat({:?})", path), fs::metadata(path))
 }
 
 /// Read entire file contents as bytes.
@@ -226,7 +372,8 @@ pub fn metadata<P: AsRef<Path>>(path: P) -> Result<fs::Metadata, std::io::Error>
 /// remove_file("bytes.txt").unwrap();
 /// ```
 pub fn read_bytes<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, std::io::Error> {
-    fs::read(path.as_ref())
+    let path = path.as_ref();
+    traced(All, format_args!("read({:?})", path), fs::read(path))
 }
 
 /// Read entire file contents as UTF-8 text.
@@ -240,7 +387,12 @@ pub fn read_bytes<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, std::io::Error> {
 /// remove_file("read_text_example.txt").unwrap();
 /// ```
 pub fn read_text<P: AsRef<Path>>(path: P) -> Result<String, std::io::Error> {
-    fs::read_to_string(path.as_ref())
+    let path = path.as_ref();
+    traced(
+        All,
+        format_args!("read({:?})", path),
+        fs::read_to_string(path),
+    )
 }
 
 /// Write data to a file (creates or overwrites).
@@ -263,7 +415,12 @@ pub fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(
     path: P,
     contents: C,
 ) -> Result<(), std::io::Error> {
-    fs::write(path.as_ref(), contents)
+    let (path, contents) = (path.as_ref(), contents.as_ref());
+    traced(
+        Changes,
+        format_args!("write({:?}, {} bytes)", path, contents.len()),
+        fs::write(path, contents),
+    )
 }
 
 /// Create a hard link.
@@ -283,12 +440,22 @@ pub fn create_hard_link<P1: AsRef<Path>, P2: AsRef<Path>>(
     original: P1,
     link: P2,
 ) -> Result<(), std::io::Error> {
-    fs::hard_link(original.as_ref(), link.as_ref())
+    let (original, link) = (original.as_ref(), link.as_ref());
+    traced(
+        Changes,
+        format_args!("link({:?}, {:?})", original, link),
+        fs::hard_link(original, link),
+    )
 }
 
 /// Read the target of a symbolic link (like `readlink`).
 pub fn read_symlink<P: AsRef<Path>>(path: P) -> Result<String, std::io::Error> {
-    match fs::read_link(path.as_ref()) {
+    let path = path.as_ref();
+    match traced(
+        All,
+        format_args!("readlink({:?})", path),
+        fs::read_link(path),
+    ) {
         Ok(path_buf) => Ok(path_buf.to_string_lossy().into_owned()),
         Err(error) => Err(error),
     }
@@ -299,12 +466,246 @@ pub fn set_permissions<P: AsRef<Path>>(
     path: P,
     permissions: fs::Permissions,
 ) -> Result<(), std::io::Error> {
-    fs::set_permissions(path.as_ref(), permissions)
+    let path = path.as_ref();
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Mode::from_bits(permissions.mode()).to_octal()
+    };
+    #[cfg(not(unix))]
+    let mode = if permissions.readonly() {
+        "readonly"
+    } else {
+        "writable"
+    };
+    traced(
+        Changes,
+        format_args!("chmod({:?}, {})", path, mode),
+        fs::set_permissions(path, permissions),
+    )
+}
+
+/// Which entries [`chmod_recursive`] should touch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ChmodTarget {
+    /// Files and directories alike.
+    #[default]
+    All,
+    /// Only regular files (and other non-directories).
+    Files,
+    /// Only directories.
+    Dirs,
+}
+
+/// Apply an octal or symbolic mode change to a single path (like `chmod`).
+///
+/// Returns the mode that was set.
+///
+/// Examples
+/// ```
+/// use soil::{chmod, write_file, remove_file, ModeChange};
+/// write_file("chmod_example.txt", "content").unwrap();
+/// let mode = chmod("chmod_example.txt", &"0640".parse::<ModeChange>().unwrap()).unwrap();
+/// assert_eq!(mode.to_string(), "rw-r-----");
+/// let mode = chmod("chmod_example.txt", &"g+w,o+r".parse::<ModeChange>().unwrap()).unwrap();
+/// assert_eq!(mode.to_string(), "rw-rw-r--");
+/// remove_file("chmod_example.txt").unwrap();
+/// ```
+#[cfg(unix)]
+pub fn chmod<P: AsRef<Path>>(path: P, change: &ModeChange) -> Result<Mode, std::io::Error> {
+    let metadata = fs::metadata(path.as_ref())?;
+    apply_mode_change(path.as_ref(), &metadata, change)
+}
+
+/// Apply a mode change to a path and everything below it (like `chmod -R`).
+///
+/// Symbolic links inside the tree are never followed or changed. `target`
+/// restricts the change to files or directories, so e.g. `a+X` can be applied
+/// to directories only.
+///
+/// Examples
+/// ```
+/// use soil::{chmod_recursive, ensure_dir, metadata, write_file, remove_dir_all, ChmodTarget};
+/// use std::os::unix::fs::PermissionsExt;
+/// ensure_dir("chmod_r_example/sub").unwrap();
+/// write_file("chmod_r_example/sub/file.txt", "content").unwrap();
+/// chmod_recursive("chmod_r_example", &"0700".parse().unwrap(), ChmodTarget::Dirs).unwrap();
+/// chmod_recursive("chmod_r_example", &"0600".parse().unwrap(), ChmodTarget::Files).unwrap();
+/// assert_eq!(metadata("chmod_r_example/sub").unwrap().permissions().mode() & 0o777, 0o700);
+/// assert_eq!(metadata("chmod_r_example/sub/file.txt").unwrap().permissions().mode() & 0o777, 0o600);
+/// remove_dir_all("chmod_r_example").unwrap();
+/// ```
+#[cfg(unix)]
+pub fn chmod_recursive<P: AsRef<Path>>(
+    path: P,
+    change: &ModeChange,
+    target: ChmodTarget,
+) -> Result<(), std::io::Error> {
+    let metadata = fs::metadata(path.as_ref())?;
+    chmod_tree(path.as_ref(), &metadata, change, target)
+}
+
+#[cfg(unix)]
+fn chmod_tree(
+    path: &Path,
+    metadata: &fs::Metadata,
+    change: &ModeChange,
+    target: ChmodTarget,
+) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if !metadata.is_dir() {
+        if target != ChmodTarget::Dirs {
+            apply_mode_change(path, metadata, change)?;
+        }
+        return Ok(());
+    }
+
+    let touch_dir = target != ChmodTarget::Files;
+    // Open the directory up before descending when the change makes it
+    // traversable; otherwise lock it down only after its contents are done.
+    let current = Mode::from_bits(metadata.permissions().mode());
+    let before = touch_dir && change.apply(current, true).bits() & 0o500 == 0o500;
+    if before {
+        apply_mode_change(path, metadata, change)?;
+    }
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let entry_metadata = entry.metadata()?;
+        if entry_metadata.file_type().is_symlink() {
+            continue;
+        }
+        chmod_tree(&entry.path(), &entry_metadata, change, target)?;
+    }
+
+    if touch_dir && !before {
+        apply_mode_change(path, metadata, change)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn apply_mode_change(
+    path: &Path,
+    metadata: &fs::Metadata,
+    change: &ModeChange,
+) -> Result<Mode, std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let current = Mode::from_bits(metadata.permissions().mode());
+    let mode = change.apply(current, metadata.is_dir());
+    traced(
+        Changes,
+        format_args!("chmod({:?}, {})", path, mode.to_octal()),
+        fs::set_permissions(path, fs::Permissions::from_mode(mode.bits())),
+    )?;
+    Ok(mode)
+}
+
+/// Change the owning user and/or group of a path (like `chown`).
+///
+/// Follows symbolic links; use [`lchown`] to change the link itself.
+///
+/// Examples
+/// ```
+/// use soil::{chown, write_file, metadata, remove_file, Owner};
+/// use std::os::unix::fs::MetadataExt;
+/// write_file("chown_example.txt", "content").unwrap();
+/// let gid = metadata("chown_example.txt").unwrap().gid();
+/// chown("chown_example.txt", Owner::group(gid)).unwrap();
+/// assert_eq!(metadata("chown_example.txt").unwrap().gid(), gid);
+/// remove_file("chown_example.txt").unwrap();
+/// ```
+#[cfg(unix)]
+pub fn chown<P: AsRef<Path>>(path: P, owner: Owner) -> Result<(), std::io::Error> {
+    let path = path.as_ref();
+    traced(
+        Changes,
+        format_args!("chown({:?}, {:?}, {:?})", path, owner.uid, owner.gid),
+        std::os::unix::fs::chown(path, owner.uid, owner.gid),
+    )
+}
+
+/// Change the owning user and/or group of a symbolic link itself.
+#[cfg(unix)]
+pub fn lchown<P: AsRef<Path>>(path: P, owner: Owner) -> Result<(), std::io::Error> {
+    let path = path.as_ref();
+    traced(
+        Changes,
+        format_args!("lchown({:?}, {:?}, {:?})", path, owner.uid, owner.gid),
+        std::os::unix::fs::lchown(path, owner.uid, owner.gid),
+    )
+}
+
+/// Change ownership of a path and everything below it (like `chown -R`).
+///
+/// Symbolic links inside the tree are never followed. They get their own
+/// ownership changed with [`lchown`], unless `skip_symlinks` is set, in which
+/// case they are left alone.
+#[cfg(unix)]
+pub fn chown_recursive<P: AsRef<Path>>(
+    path: P,
+    owner: Owner,
+    skip_symlinks: bool,
+) -> Result<(), std::io::Error> {
+    let path = path.as_ref();
+    chown(path, owner)?;
+    if fs::metadata(path)?.is_dir() {
+        chown_tree(path, owner, skip_symlinks)?;
+    }
+    Ok(())
+}
+
+/// [`chown_recursive`] without following `path` itself if it is a
+/// symbolic link (like `chown -R -h`): such a link gets its own ownership
+/// changed with [`lchown`] and nothing behind it is touched.
+#[cfg(unix)]
+pub fn lchown_recursive<P: AsRef<Path>>(
+    path: P,
+    owner: Owner,
+    skip_symlinks: bool,
+) -> Result<(), std::io::Error> {
+    let path = path.as_ref();
+    let metadata = symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return lchown(path, owner);
+    }
+    chown(path, owner)?;
+    if metadata.is_dir() {
+        chown_tree(path, owner, skip_symlinks)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn chown_tree(dir: &Path, owner: Owner, skip_symlinks: bool) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            if !skip_symlinks {
+                lchown(&path, owner)?;
+            }
+            continue;
+        }
+        chown(&path, owner)?;
+        if file_type.is_dir() {
+            chown_tree(&path, owner, skip_symlinks)?;
+        }
+    }
+    Ok(())
 }
 
 /// Get metadata for a path without following symlinks (like `lstat`).
 pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> Result<fs::Metadata, std::io::Error> {
-    fs::symlink_metadata(path.as_ref())
+    let path = path.as_ref();
+    traced(
+        All,
+        format_args!("lstat({:?})", path),
+        fs::symlink_metadata(path),
+    )
 }
 
 /// Create a symbolic link.
@@ -313,7 +714,12 @@ pub fn create_symlink<P1: AsRef<Path>, P2: AsRef<Path>>(
     original: P1,
     link: P2,
 ) -> Result<(), std::io::Error> {
-    std::os::unix::fs::symlink(original.as_ref(), link.as_ref())
+    let (original, link) = (original.as_ref(), link.as_ref());
+    traced(
+        Changes,
+        format_args!("symlink({:?}, {:?})", original, link),
+        std::os::unix::fs::symlink(original, link),
+    )
 }
 
 /// Create a symbolic link (Windows: supports files and directories).
@@ -344,8 +750,8 @@ mod tests {
         format!("{}_test_{}", TEST_ROOT, id)
     }
 
-    struct TestGuard {
-        test_root: String,
+    pub(crate) struct TestGuard {
+        pub(crate) test_root: String,
     }
 
     impl Drop for TestGuard {
@@ -354,7 +760,7 @@ mod tests {
         }
     }
 
-    fn setup_test() -> TestGuard {
+    pub(crate) fn setup_test() -> TestGuard {
         let test_root = get_unique_test_root();
         let _ = ensure_dir(&test_root);
         TestGuard { test_root }
@@ -595,6 +1001,205 @@ mod tests {
         assert_eq!(new_md.permissions().readonly(), !original_readonly);
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn test_chmod() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let guard = setup_test();
+        let test_file = &*format!("{}/chmod_test.txt", guard.test_root);
+        write_file(test_file, "content").unwrap();
+
+        let mode = chmod(test_file, &"0600".parse().unwrap()).unwrap();
+        assert_eq!(mode.bits(), 0o600);
+
+        let mode = chmod(test_file, &"u+x,g+r".parse().unwrap()).unwrap();
+        assert_eq!(mode.bits(), 0o740);
+        assert_eq!(
+            metadata(test_file).unwrap().permissions().mode() & 0o7777,
+            0o740
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_chmod_recursive() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let guard = setup_test();
+        let root = &*format!("{}/chmod_tree", guard.test_root);
+        ensure_dir(format!("{}/a/b", root)).unwrap();
+        write_file(format!("{}/top.txt", root), "top").unwrap();
+        write_file(format!("{}/a/b/deep.txt", root), "deep").unwrap();
+        create_symlink("top.txt", format!("{}/link", root)).unwrap();
+
+        let mode_of = |path: String| metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        chmod_recursive(root, &"0640".parse().unwrap(), ChmodTarget::Files).unwrap();
+        assert_eq!(mode_of(format!("{}/top.txt", root)), 0o640);
+        assert_eq!(mode_of(format!("{}/a/b/deep.txt", root)), 0o640);
+
+        chmod_recursive(root, &"go-rwx".parse().unwrap(), ChmodTarget::Dirs).unwrap();
+        assert_eq!(mode_of(format!("{}/a/b", root)) & 0o077, 0);
+        assert_eq!(mode_of(format!("{}/top.txt", root)), 0o640);
+
+        chmod_recursive(root, &"a=rX,u+w".parse().unwrap(), ChmodTarget::All).unwrap();
+        assert_eq!(mode_of(format!("{}/a", root)), 0o755);
+        assert_eq!(mode_of(format!("{}/a/b/deep.txt", root)), 0o644);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_chmod_recursive_locks_directories_last() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let guard = setup_test();
+        let root = &*format!("{}/chmod_lock", guard.test_root);
+        ensure_dir(format!("{}/inner", root)).unwrap();
+        write_file(format!("{}/inner/file.txt", root), "content").unwrap();
+        let inner = format!("{}/inner", root);
+        let file = format!("{}/inner/file.txt", root);
+        for (path, mode) in [(root, 0o755), (&*inner, 0o755), (&*file, 0o644)] {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let mode_of = |path: &str| metadata(path).unwrap().permissions().mode() & 0o7777;
+        let modes = || [mode_of(root), mode_of(&inner), mode_of(&file)];
+
+        chmod_recursive(root, &"a-x".parse().unwrap(), ChmodTarget::All).unwrap();
+        assert_eq!(modes(), [0o644, 0o644, 0o644]);
+        chmod_recursive(root, &"u+x".parse().unwrap(), ChmodTarget::Dirs).unwrap();
+        assert_eq!(modes(), [0o744, 0o744, 0o644]);
+        chmod_recursive(root, &"u+rwx".parse().unwrap(), ChmodTarget::All).unwrap();
+        assert_eq!(modes(), [0o744, 0o744, 0o744]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_chown_recursive() {
+        use std::os::unix::fs::MetadataExt;
+
+        let guard = setup_test();
+        let root = &*format!("{}/chown_tree", guard.test_root);
+        ensure_dir(format!("{}/sub", root)).unwrap();
+        write_file(format!("{}/sub/file.txt", root), "content").unwrap();
+        create_symlink("sub/file.txt", format!("{}/link", root)).unwrap();
+
+        // Without privileges we can only hand files to ourselves, which still
+        // exercises the traversal.
+        let md = metadata(root).unwrap();
+        let owner = Owner {
+            uid: Some(md.uid()),
+            gid: Some(md.gid()),
+        };
+        assert!(chown_recursive(root, owner, false).is_ok());
+        assert!(chown_recursive(root, owner, true).is_ok());
+        assert!(lchown(format!("{}/link", root), owner).is_ok());
+        assert_eq!(
+            symlink_metadata(format!("{}/link", root)).unwrap().uid(),
+            md.uid()
+        );
+        assert!(chown(format!("{}/missing", root), owner).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_lchown_recursive_keeps_to_a_root_link() {
+        use std::os::unix::fs::MetadataExt;
+        use std::sync::{Arc, Mutex};
+
+        let guard = setup_test();
+        let target = &*format!("{}/lchown_target", guard.test_root);
+        let link = &*format!("{}/lchown_link", guard.test_root);
+        ensure_dir(target).unwrap();
+        write_file(format!("{}/file.txt", target), "").unwrap();
+        create_symlink("lchown_target", link).unwrap();
+        let md = metadata(target).unwrap();
+        let owner = Owner {
+            uid: Some(md.uid()),
+            gid: Some(md.gid()),
+        };
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&lines);
+        set_trace(TraceLevel::Changes, move |line| {
+            sink.lock().unwrap().push(line.to_string())
+        });
+        lchown_recursive(link, owner, false).unwrap();
+        chown_recursive(target, owner, false).unwrap();
+        clear_trace();
+
+        let lines = lines.lock().unwrap();
+        let ours: Vec<_> = lines
+            .iter()
+            .filter(|line| line.contains(&format!("{}/lchown_", guard.test_root)))
+            .collect();
+        assert!(
+            ours[0].starts_with(&format!("lchown({:?}", link)),
+            "{:?}",
+            ours
+        );
+        assert!(
+            ours[1].starts_with(&format!("chown({:?}", target)),
+            "{:?}",
+            ours
+        );
+        assert_eq!(ours.len(), 3, "{:?}", ours);
+    }
+
+    #[test]
+    fn test_set_times() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let guard = setup_test();
+        let test_file = &*format!("{}/times.txt", guard.test_root);
+        write_file(test_file, "content").unwrap();
+
+        let atime = UNIX_EPOCH + Duration::new(1_600_000_000, 1);
+        let mtime = UNIX_EPOCH + Duration::new(1_500_000_000, 999_999_999);
+        set_times(test_file, atime, mtime).unwrap();
+        let md = metadata(test_file).unwrap();
+        assert_eq!(md.accessed().unwrap(), atime);
+        assert_eq!(md.modified().unwrap(), mtime);
+
+        set_times(test_file, Timestamp::Unchanged, Timestamp::Now).unwrap();
+        let md = metadata(test_file).unwrap();
+        assert_eq!(md.accessed().unwrap(), atime);
+        assert!(md.modified().unwrap() > mtime);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_set_symlink_times() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let guard = setup_test();
+        let target = &*format!("{}/times_target.txt", guard.test_root);
+        let link = &*format!("{}/times_link", guard.test_root);
+        write_file(target, "content").unwrap();
+        create_symlink("times_target.txt", link).unwrap();
+
+        let target_mtime = metadata(target).unwrap().modified().unwrap();
+        let old = UNIX_EPOCH + Duration::from_secs(1_234_567_890);
+        set_symlink_times(link, old, old).unwrap();
+
+        assert_eq!(symlink_metadata(link).unwrap().modified().unwrap(), old);
+        assert_eq!(metadata(target).unwrap().modified().unwrap(), target_mtime);
+    }
+
+    #[test]
+    fn test_touch() {
+        let guard = setup_test();
+        let test_file = &*format!("{}/touched.txt", guard.test_root);
+
+        touch(test_file).unwrap();
+        assert!(exists(test_file));
+
+        write_file(test_file, "keep me").unwrap();
+        touch(test_file).unwrap();
+        assert_eq!(read_text(test_file).unwrap(), "keep me");
+    }
+
     #[test]
     #[cfg(unix)]
     fn test_symlink_metadata() {
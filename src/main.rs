@@ -1,10 +1,33 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
+use clap_complete::env::{CompleteEnv, Shells};
+use serde_json::json;
 use soil::{
-    copy_file, create_dir, create_hard_link, create_symlink, ensure_dir, exists, list_dir,
-    metadata, move_path, read_bytes, read_symlink, read_text, remove_dir_all, remove_empty_dir,
-    remove_file, resolve_path, set_permissions, symlink_metadata, write_file,
+    Backup, CancelToken, CopyStrategy, EntryType, Mode, Preserve, SizeRange, TraceLevel,
+    create_dir, create_hard_link, create_symlink, ensure_dir, exists, list_dir, parse_age,
+    read_symlink, remove_dir_all_with_progress, remove_empty_dir, remove_file, resolve_path,
+    set_trace, symlink_metadata,
 };
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
+
+use output::{
+    EX_USAGE, Exit, Format, OUTPUT, Output, ProgressBar, Status, error_record, fail,
+    metadata_record, output, print_metadata, type_name,
+};
+
+use files::{expand_paths, finish_bulk, walker};
+use stream::{BytesView, parse_byte_range, parse_offset};
+
+mod batch;
+mod files;
+mod output;
+mod perms;
+mod shell;
+mod stream;
+mod text;
+mod undo;
 
 /// A CLI for soil
 #[derive(Parser)]
@@ -12,6 +35,18 @@ use std::process;
 #[command(about = "A CLI for soil")]
 #[command(long_about = None)]
 struct Cli {
+    /// Print results as text, one JSON document (json) or one JSON object
+    /// per line (ndjson); errors on stderr follow the same format
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Print only the results asked for, not messages such as "Copied 'a'
+    /// to 'b'" or progress bars
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Trace on stderr the filesystem calls that change something (-v), or
+    /// every call (-vv)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
     #[command(subcommand)]
     command: Commands,
 }
@@ -27,22 +62,37 @@ enum Commands {
     /// ```
     Resolve {
         /// The path to canonicalize
+        #[arg(value_hint = ValueHint::AnyPath)]
         path: String,
     },
 
+    /// Work with paths as text, without touching the filesystem
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil path normalize ./build/../src/
+    /// soil path relative /srv/app/logs /srv/data
+    /// soil path expand '~/$PROJECT/dir'
+    /// ```
+    Path {
+        #[command(subcommand)]
+        command: PathCommand,
+    },
+
     /// Copy a file from source to destination
     ///
     /// # Examples
     ///
     /// ```
     /// soil copy source.txt destination.txt
+    /// soil copy --strategy reflink big.img clone.img
+    /// soil copy '*.conf' notes.txt ./backup/
+    /// soil copy --backup=numbered new.conf app.conf
+    /// soil copy -a -r ./cache ./cache.bak
+    /// soil copy --preserve mode,timestamps build.tar /mnt/backup/
     /// ```
-    Copy {
-        /// The source file to copy
-        src: String,
-        /// The destination path
-        dst: String,
-    },
+    Copy(Box<CopyArgs>),
 
     /// Create a directory and all parent directories
     ///
@@ -53,6 +103,7 @@ enum Commands {
     /// ```
     Mkdirp {
         /// The directory path to create
+        #[arg(value_hint = ValueHint::DirPath)]
         path: String,
     },
 
@@ -65,6 +116,7 @@ enum Commands {
     /// ```
     Mkdir {
         /// The directory path to create
+        #[arg(value_hint = ValueHint::DirPath)]
         path: String,
     },
 
@@ -77,6 +129,7 @@ enum Commands {
     /// ```
     Ls {
         /// The directory path to list
+        #[arg(value_hint = ValueHint::DirPath)]
         path: String,
     },
 
@@ -86,10 +139,12 @@ enum Commands {
     ///
     /// ```
     /// soil rm ./file.txt
+    /// soil rm '*.tmp' 'cache/**/*.bak'
     /// ```
     Rm {
-        /// The file path to remove
-        path: String,
+        /// The files or glob patterns to remove
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        paths: Vec<String>,
     },
 
     /// Remove an empty directory
@@ -101,6 +156,7 @@ enum Commands {
     /// ```
     Rmdir {
         /// The directory path to remove
+        #[arg(value_hint = ValueHint::DirPath)]
         path: String,
     },
 
@@ -110,10 +166,15 @@ enum Commands {
     ///
     /// ```
     /// soil rmrf ./directory
+    /// soil rmrf -j 16 ./node_modules
     /// ```
     Rmrf {
         /// The directory path to remove recursively
+        #[arg(value_hint = ValueHint::AnyPath)]
         path: String,
+        /// Remove with this many worker threads instead of one
+        #[arg(short = 'j', long)]
+        threads: Option<usize>,
     },
 
     /// Move or rename a file or directory
@@ -125,9 +186,13 @@ enum Commands {
     /// ```
     Mv {
         /// The current path
+        #[arg(value_hint = ValueHint::AnyPath)]
         from: String,
         /// The destination path
+        #[arg(value_hint = ValueHint::AnyPath)]
         to: String,
+        #[command(flatten)]
+        backup: BackupArgs,
     },
 
     /// Get metadata information about a file or directory
@@ -136,22 +201,54 @@ enum Commands {
     ///
     /// ```
     /// soil stat ./file.txt
+    /// soil stat 'src/*.rs' Cargo.toml
     /// ```
     Stat {
-        /// The path to examine
-        path: String,
+        /// The paths or glob patterns to examine
+        #[arg(required = true, value_hint = ValueHint::AnyPath)]
+        paths: Vec<String>,
     },
 
     /// Read file content as bytes
     ///
+    /// Without a format flag, prints the content if it is UTF-8. `--hex`
+    /// dumps it like `xxd`, `--base64` and `--c-array` encode it, and
+    /// `--text` prints it with non-printable characters escaped.
+    ///
     /// # Examples
     ///
     /// ```
     /// soil read-bytes ./file.txt
+    /// soil read-bytes ./image.png --raw > copy.png
+    /// soil read-bytes ./firmware.bin --hex --offset 0x200 --length 64
+    /// soil read-bytes ./icon.png --c-array > icon.h
+    /// soil read-bytes ./output.log --text
     /// ```
     ReadBytes {
         /// The file path to read
+        #[arg(value_hint = ValueHint::FilePath)]
         path: String,
+        /// Start this many bytes into the file (decimal, or hex with 0x)
+        #[arg(short = 's', long, default_value = "0", value_parser = parse_offset)]
+        offset: u64,
+        /// Read at most this many bytes (decimal, or hex with 0x)
+        #[arg(short = 'l', long, value_parser = parse_offset)]
+        length: Option<u64>,
+        /// Write the bytes to stdout as they are, without a banner
+        #[arg(long, group = "view")]
+        raw: bool,
+        /// Print a hex dump in the style of xxd
+        #[arg(long, group = "view")]
+        hex: bool,
+        /// Print the bytes as base64
+        #[arg(long, group = "view")]
+        base64: bool,
+        /// Print the bytes as a C array, like xxd -i
+        #[arg(long, group = "view")]
+        c_array: bool,
+        /// Print the bytes as text with non-printable characters escaped
+        #[arg(long, group = "view")]
+        text: bool,
     },
 
     /// Read file content as text
@@ -160,10 +257,64 @@ enum Commands {
     ///
     /// ```
     /// soil read-text ./file.txt
+    /// soil read-text ./huge.log --range 1048576..1049600
+    /// soil read-text ./notes.txt --raw | wc -l
     /// ```
     ReadText {
         /// The file path to read
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: String,
+        /// Only read this byte range: START..END, START.. or ..END (END exclusive)
+        #[arg(long, value_parser = parse_byte_range, value_name = "START..END")]
+        range: Option<(u64, Option<u64>)>,
+        /// Write the text to stdout as it is, without a banner
+        #[arg(long)]
+        raw: bool,
+    },
+
+    /// Print the first lines of a file without reading the rest
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil head ./huge.log
+    /// soil head -n 100 ./huge.log
+    /// soil head -c 512 ./data.bin
+    /// ```
+    Head {
+        /// The file to read
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: String,
+        /// Number of lines to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// Print this many bytes instead of lines
+        #[arg(short = 'c', long, conflicts_with = "lines")]
+        bytes: Option<u64>,
+    },
+
+    /// Print the last lines of a file, reading it from the end
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil tail ./huge.log
+    /// soil tail -n 50 -f ./app.log
+    /// soil tail -c 1024 ./data.bin
+    /// ```
+    Tail {
+        /// The file to read
+        #[arg(value_hint = ValueHint::FilePath)]
         path: String,
+        /// Number of lines to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// Print this many bytes instead of lines
+        #[arg(short = 'c', long, conflicts_with = "lines")]
+        bytes: Option<u64>,
+        /// Keep printing data as it is appended to the file
+        #[arg(short = 'f', long)]
+        follow: bool,
     },
 
     /// Write content to a file
@@ -172,12 +323,52 @@ enum Commands {
     ///
     /// ```
     /// soil write ./file.txt "content"
+    /// soil write ./app.log "started" --append
+    /// soil write ./token "secret" --no-clobber --mode 0600
+    /// soil write ./app.conf "debug = true" --backup-dir ./.backups --backup-keep 5
+    /// curl -s https://example.com/data.bin | soil write ./data.bin -
     /// ```
     Write {
         /// The file path to write to
+        #[arg(value_hint = ValueHint::FilePath)]
         path: String,
-        /// The content to write
+        /// The content to write, or `-` to read it from stdin
+        #[arg(allow_hyphen_values = true)]
         content: String,
+        /// Add to the end of the file instead of replacing it
+        #[arg(short = 'a', long)]
+        append: bool,
+        /// Fail if the file already exists
+        #[arg(short = 'n', long)]
+        no_clobber: bool,
+        /// Permissions for a newly created file, e.g. 0600 or u=rw
+        #[arg(long)]
+        mode: Option<Mode>,
+        /// Wait for the data to reach the disk (O_SYNC)
+        #[arg(long)]
+        sync: bool,
+        #[command(flatten)]
+        backup: BackupArgs,
+    },
+
+    /// Copy stdin to stdout and to each of several files
+    ///
+    /// With `--format json` or `ndjson`, the input is not echoed; a record
+    /// per file is printed instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// make 2>&1 | soil tee build.log
+    /// soil tee --append all.log today.log < events.txt
+    /// ```
+    Tee {
+        /// The files to write
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        paths: Vec<String>,
+        /// Add to the end of the files instead of replacing them
+        #[arg(short = 'a', long)]
+        append: bool,
     },
 
     /// Create a hard link
@@ -189,8 +380,10 @@ enum Commands {
     /// ```
     Hardlink {
         /// The original file path
+        #[arg(value_hint = ValueHint::FilePath)]
         original: String,
         /// The hard link path
+        #[arg(value_hint = ValueHint::AnyPath)]
         link: String,
     },
 
@@ -203,8 +396,10 @@ enum Commands {
     /// ```
     Symlink {
         /// The target path
+        #[arg(value_hint = ValueHint::AnyPath)]
         original: String,
         /// The symbolic link path
+        #[arg(value_hint = ValueHint::AnyPath)]
         link: String,
     },
 
@@ -217,6 +412,7 @@ enum Commands {
     /// ```
     Readlink {
         /// The symbolic link path
+        #[arg(value_hint = ValueHint::AnyPath)]
         path: String,
     },
 
@@ -225,15 +421,109 @@ enum Commands {
     /// # Examples
     ///
     /// ```
+    /// soil chmod file.txt 0644
+    /// soil chmod file.txt u+x,go-w
+    /// soil chmod -R ./dir a=rX --dirs-only
     /// soil chmod file.txt readonly|writable
+    /// soil chmod 'bin/*.sh' tools/run u+x
+    /// ```
+    Chmod(Box<ChmodArgs>),
+
+    /// Change the owning user and/or group of a file or directory
+    ///
+    /// # Examples
+    ///
     /// ```
-    Chmod {
+    /// soil chown www-data:www-data ./srv
+    /// soil chown 1000 file.txt
+    /// soil chown :staff -R ./shared
+    /// ```
+    #[cfg(unix)]
+    Chown {
+        /// New owner as user, user:group, :group or user: (names or numeric ids)
+        owner: String,
         /// The path to modify
+        #[arg(value_hint = ValueHint::AnyPath)]
+        path: String,
+        /// Change ownership of the directory and everything below it
+        #[arg(short = 'R', long)]
+        recursive: bool,
+        /// Change symbolic links themselves rather than what they point to;
+        /// with -R, a link given as PATH is not descended through
+        #[arg(long)]
+        no_dereference: bool,
+        /// With -R, leave symbolic links inside the tree untouched
+        #[arg(long, requires = "recursive")]
+        skip_symlinks: bool,
+    },
+
+    /// Update access and modification times, creating the file if needed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil touch ./file.txt
+    /// soil touch -d "2024-05-01 12:00" ./file.txt
+    /// soil touch -r reference.txt ./file.txt
+    /// ```
+    Touch(Box<TouchArgs>),
+
+    /// Print the SHA-256 of every file under a path, sorted by path
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil hash ./target
+    /// soil hash -j 8 ./cache
+    /// ```
+    Hash {
+        /// The file or directory to hash
+        #[arg(value_hint = ValueHint::AnyPath)]
         path: String,
-        /// Permission mode (readonly/writable)
-        mode: String,
+        /// Worker threads (defaults to the number of CPUs)
+        #[arg(short = 'j', long)]
+        threads: Option<usize>,
+        /// Skip files excluded by .gitignore, .ignore or .soilignore
+        #[arg(long)]
+        respect_ignore: bool,
     },
 
+    /// Search a directory tree, printing matching paths as they are found
+    ///
+    /// All tests must match; repeat --name or --iname to accept any of several patterns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil find ./logs --name '*.log' --older 7d --size +10M
+    /// soil find . --type f --empty --print0 | xargs -0 rm
+    /// soil find ./src --name '*.rs' --exec wc -l {} \;
+    /// ```
+    Find(Box<FindArgs>),
+
+    /// Search file contents under a path for a regular expression
+    ///
+    /// Exits with 0 when something matched, 1 when nothing did and 2 on errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil grep 'TODO|FIXME' ./src
+    /// soil grep -F -i 'error[' ./logs -C 2
+    /// soil grep --json 'fn main' .
+    /// ```
+    Grep(Box<GrepArgs>),
+
+    /// Replace a pattern in every text file under a path, atomically per file
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil replace 'version = "1.2.3"' 'version = "1.2.4"' . --include Cargo.toml -F
+    /// soil replace 'old_crate::(\w+)' 'new_crate::$1' ./src --include '*.rs' --dry-run
+    /// ```
+    Replace(Box<ReplaceArgs>),
+
     /// Get metadata of a symbolic link without following it
     ///
     /// # Examples
@@ -243,9 +533,88 @@ enum Commands {
     /// ```
     Lstat {
         /// The path to examine
+        #[arg(value_hint = ValueHint::AnyPath)]
         path: String,
     },
 
+    /// Run many soil commands in one process
+    ///
+    /// Each line holds the arguments that would follow `soil`, quoted as in
+    /// a shell; blank lines and `#` comments are skipped. A line may instead
+    /// be a JSON array of arguments, and the whole input may be one JSON
+    /// array of such arrays. Every line is checked before any runs. By
+    /// default the batch stops at the first failure; --keep-going runs the
+    /// rest. The exit status is that of the first failure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// printf 'mkdir out\ncopy "my notes.txt" out/notes.txt\n' | soil batch -
+    /// soil --format ndjson batch --keep-going cleanup.soil
+    /// ```
+    Batch {
+        /// File of commands, or `-` for stdin
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: String,
+        /// Run the remaining commands after one fails instead of stopping
+        #[arg(short = 'k', long)]
+        keep_going: bool,
+    },
+
+    /// Start an interactive soil shell
+    ///
+    /// Type soil commands without the leading `soil`, with history and tab
+    /// completion of commands and paths. The shell adds `cd` (with `cd -`),
+    /// `pwd`, `history`, `exit`, and `undo`, which takes back the last
+    /// command that changed something: created, wrote, copied, moved,
+    /// removed files, or changed modes, owners or times. Commands that change
+    /// whole trees, such as rmrf and replace, cannot be undone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil shell
+    /// soil shell --history ./cleanup.history
+    /// ```
+    Shell {
+        /// Keep history in this file instead of ~/.soil_history
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        history: Option<PathBuf>,
+    },
+
+    /// Print a shell script that sets up tab completion for soil
+    ///
+    /// Completions are computed by soil itself as you type, so path arguments
+    /// only offer what fits: `rmdir` offers directories, `read-text` files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// echo 'source <(soil completions bash)' >> ~/.bashrc
+    /// soil completions fish > ~/.config/fish/completions/soil.fish
+    /// ```
+    Completions {
+        /// The shell to complete in
+        shell: clap_complete::Shell,
+    },
+
+    /// Generate roff manual pages
+    ///
+    /// Prints the page for soil itself, or with --out-dir writes one page
+    /// per subcommand (soil-copy.1, soil-path-expand.1, ...).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// soil man | man -l -
+    /// soil man --out-dir /usr/local/share/man/man1
+    /// ```
+    Man {
+        /// Write soil.1 and a page for every subcommand into this directory
+        #[arg(short = 'o', long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        out_dir: Option<PathBuf>,
+    },
+
     /// Check if a path exists
     ///
     /// # Examples
@@ -255,294 +624,736 @@ enum Commands {
     /// ```
     Exists {
         /// The path to check
+        #[arg(value_hint = ValueHint::AnyPath)]
+        path: String,
+    },
+}
+
+/// Subcommands of `soil path`.
+#[derive(Subcommand)]
+enum PathCommand {
+    /// Resolve `.` and `..` lexically; the paths need not exist
+    Normalize {
+        /// The paths to normalize
+        #[arg(required = true, value_hint = ValueHint::AnyPath)]
+        paths: Vec<String>,
+    },
+    /// Print the path that leads from BASE to PATH, like ../../x
+    Relative {
+        /// The path to reach
+        #[arg(value_hint = ValueHint::AnyPath)]
         path: String,
+        /// The directory to start from (relative paths are taken from the
+        /// current directory if the other one is absolute)
+        #[arg(default_value = ".", value_hint = ValueHint::DirPath)]
+        base: String,
+    },
+    /// Expand a leading ~ and $NAME or ${NAME} environment variables
+    Expand {
+        /// The texts to expand (quote them to keep the shell out)
+        #[arg(required = true)]
+        texts: Vec<String>,
     },
 }
 
+/// Backup flags shared by the commands that overwrite files.
+#[derive(Args)]
+struct BackupArgs {
+    /// Back up files before overwriting them: simple (file~, the default),
+    /// numbered (file.~1~) or none
+    #[arg(
+        long,
+        value_name = "METHOD",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "simple"
+    )]
+    backup: Option<Backup>,
+    /// Put backups in this directory, named with a UTC timestamp (implies --backup)
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    backup_dir: Option<PathBuf>,
+    /// Keep at most this many numbered or directory backups per file
+    #[arg(long, value_name = "N")]
+    backup_keep: Option<usize>,
+}
+
+impl BackupArgs {
+    fn policy(&self) -> Backup {
+        match (&self.backup_dir, &self.backup) {
+            (Some(dir), _) => Backup::Directory {
+                dir: dir.clone(),
+                keep: self.backup_keep,
+            },
+            (None, Some(Backup::Numbered { .. })) => Backup::Numbered {
+                keep: self.backup_keep,
+            },
+            (None, Some(backup)) => backup.clone(),
+            (None, None) => Backup::None,
+        }
+    }
+}
+
+/// When `soil grep` colors its output.
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Arguments of `soil find`, boxed to keep `Commands` small.
+#[derive(Args)]
+struct FindArgs {
+    /// The directory to search
+    #[arg(value_hint = ValueHint::DirPath)]
+    root: String,
+    /// File name matches this wildcard pattern
+    #[arg(long)]
+    name: Vec<String>,
+    /// Like --name, ignoring case
+    #[arg(long)]
+    iname: Vec<String>,
+    /// Whole path matches this regular expression
+    #[arg(long)]
+    regex: Option<String>,
+    /// Entry type: f (file), d (directory), l (symlink) or o (other)
+    #[arg(long = "type", value_name = "TYPE")]
+    entry_type: Option<EntryType>,
+    /// Size in bytes: +N more than, -N less than, N exactly (suffixes k, M, G, T)
+    #[arg(long, allow_hyphen_values = true)]
+    size: Vec<SizeRange>,
+    /// Not modified within this age (e.g. 30m, 12h, 7d, 2w)
+    #[arg(long, value_parser = parse_age)]
+    older: Option<Duration>,
+    /// Modified within this age
+    #[arg(long, value_parser = parse_age)]
+    newer: Option<Duration>,
+    /// Not accessed within this age
+    #[arg(long, value_parser = parse_age)]
+    accessed_older: Option<Duration>,
+    /// Accessed within this age
+    #[arg(long, value_parser = parse_age)]
+    accessed_newer: Option<Duration>,
+    /// Modified more recently than this file
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::AnyPath)]
+    newer_than: Option<String>,
+    /// Empty files and directories
+    #[arg(long)]
+    empty: bool,
+    /// Permission bits: MODE exactly, -MODE all of them, /MODE any of them
+    #[cfg(unix)]
+    #[arg(long, allow_hyphen_values = true)]
+    perm: Option<String>,
+    /// Owned by this user (name or id)
+    #[cfg(unix)]
+    #[arg(long)]
+    user: Option<String>,
+    /// Owned by this group (name or id)
+    #[cfg(unix)]
+    #[arg(long)]
+    group: Option<String>,
+    /// Skip entries shallower than this (the root is depth 0)
+    #[arg(long)]
+    min_depth: Option<usize>,
+    /// Do not descend below this depth
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Skip entries excluded by .gitignore, .ignore or .soilignore
+    #[arg(long)]
+    respect_ignore: bool,
+    /// Separate output paths with NUL instead of newline
+    #[arg(short = '0', long)]
+    print0: bool,
+    /// Run a command for each match instead of printing it; {} is replaced
+    /// by the path (appended if absent) and the command ends at ';'
+    #[arg(long, num_args = 1.., value_terminator = ";", allow_hyphen_values = true, value_name = "CMD")]
+    exec: Vec<String>,
+}
+
+/// Arguments of `soil copy`.
+#[derive(Args)]
+struct CopyArgs {
+    /// The source files or glob patterns to copy
+    #[arg(required = true, value_hint = ValueHint::AnyPath)]
+    sources: Vec<String>,
+    /// The destination path; must be an existing directory for several sources
+    #[arg(value_hint = ValueHint::AnyPath)]
+    dst: String,
+    /// How to copy: auto, reflink, copy-file-range or userspace.
+    /// Anything but auto fails instead of falling back.
+    #[arg(long, default_value = "auto", conflicts_with = "recursive")]
+    strategy: CopyStrategy,
+    /// Copy a directory tree in parallel (the destination must not exist)
+    #[arg(short = 'r', long)]
+    recursive: bool,
+    /// Worker threads for -r (defaults to the number of CPUs)
+    #[arg(short = 'j', long, requires = "recursive")]
+    threads: Option<usize>,
+    #[command(flatten)]
+    backup: BackupArgs,
+    /// With -r, skip entries excluded by .gitignore, .ignore or .soilignore
+    #[arg(long, requires = "recursive")]
+    respect_ignore: bool,
+    /// Keep permissions, times, ownership, xattrs and links, like cp -a
+    #[arg(short = 'a', long)]
+    archive: bool,
+    /// Keep only these: a comma-separated list of mode, timestamps,
+    /// ownership, xattrs, links, or all
+    #[arg(long, value_name = "LIST", conflicts_with = "archive")]
+    preserve: Option<Preserve>,
+}
+
+/// Arguments of `soil chmod`.
+#[derive(Args)]
+struct ChmodArgs {
+    /// The paths or glob patterns to modify
+    #[arg(required = true, value_hint = ValueHint::AnyPath)]
+    paths: Vec<String>,
+    /// Permission mode: octal (0755), symbolic (u+x,go-w, a=rX) or readonly/writable
+    #[arg(allow_hyphen_values = true)]
+    mode: String,
+    /// Apply the mode to the directory and everything below it
+    #[arg(short = 'R', long)]
+    recursive: bool,
+    /// With -R, only change files
+    #[arg(long, requires = "recursive", conflicts_with = "dirs_only")]
+    files_only: bool,
+    /// With -R, only change directories
+    #[arg(long, requires = "recursive")]
+    dirs_only: bool,
+    /// With -R, walk the tree with this many worker threads
+    #[arg(short = 'j', long, requires = "recursive")]
+    threads: Option<usize>,
+}
+
+/// Arguments of `soil touch`.
+#[derive(Args)]
+struct TouchArgs {
+    /// The file to touch
+    #[arg(value_hint = ValueHint::AnyPath)]
+    path: String,
+    /// Use this time instead of now (e.g. 2024-05-01T12:00:00Z or @1714564800)
+    #[arg(short = 'd', long, conflicts_with = "reference")]
+    date: Option<String>,
+    /// Use the times of this file instead of now
+    #[arg(short = 'r', long, value_hint = ValueHint::AnyPath)]
+    reference: Option<String>,
+    /// Only change the access time
+    #[arg(short = 'a', conflicts_with = "modification_only")]
+    access_only: bool,
+    /// Only change the modification time
+    #[arg(short = 'm')]
+    modification_only: bool,
+    /// Do not create the file if it does not exist
+    #[arg(short = 'c', long)]
+    no_create: bool,
+    /// Change a symbolic link itself rather than what it points to
+    #[cfg(unix)]
+    #[arg(long)]
+    no_dereference: bool,
+}
+
+/// Arguments of `soil grep`.
+#[derive(Args)]
+struct GrepArgs {
+    /// The pattern to look for
+    pattern: String,
+    /// The file or directory to search
+    #[arg(default_value = ".", value_hint = ValueHint::AnyPath)]
+    path: String,
+    /// Treat the pattern as plain text
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+    /// Ignore case
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+    /// Search binary files too
+    #[arg(short = 'a', long)]
+    text: bool,
+    /// Lines of context after each match
+    #[arg(short = 'A', long, value_name = "N")]
+    after_context: Option<usize>,
+    /// Lines of context before each match
+    #[arg(short = 'B', long, value_name = "N")]
+    before_context: Option<usize>,
+    /// Lines of context before and after each match
+    #[arg(short = 'C', long, value_name = "N")]
+    context: Option<usize>,
+    /// Stop after this many matching lines per file
+    #[arg(short = 'm', long, value_name = "N")]
+    max_count: Option<usize>,
+    /// Print one JSON object per matching line; the same as
+    /// `--format ndjson`
+    #[arg(long, conflicts_with = "color")]
+    json: bool,
+    /// Highlight matches: auto (when stdout is a terminal), always or never
+    #[arg(long, value_enum, default_value = "auto")]
+    color: ColorChoice,
+    /// Skip files excluded by .gitignore, .ignore or .soilignore
+    #[arg(long)]
+    respect_ignore: bool,
+}
+
+/// Arguments of `soil replace`.
+#[derive(Args)]
+struct ReplaceArgs {
+    /// The pattern to replace (a regular expression unless -F)
+    pattern: String,
+    /// The replacement; $1 or ${name} insert capture groups
+    replacement: String,
+    /// The file or directory to rewrite
+    #[arg(default_value = ".", value_hint = ValueHint::AnyPath)]
+    path: String,
+    /// Treat the pattern and replacement as plain text
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+    /// Ignore case
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+    /// Only rewrite files matching this glob (name, or relative path if it has a '/')
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Never rewrite files matching this glob
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Print a unified diff of the changes instead of writing them
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+    #[command(flatten)]
+    backup: BackupArgs,
+    /// Skip files excluded by .gitignore, .ignore or .soilignore
+    #[arg(long)]
+    respect_ignore: bool,
+}
+
 fn main() {
-    let cli = Cli::parse();
+    CompleteEnv::with_factory(Cli::command).complete();
+    let mut cli = Cli::try_parse().unwrap_or_else(|error| exit_on_usage_error(error));
+    if let Commands::Grep(args) = &cli.command
+        && args.json
+    {
+        // `grep --json` is `--format ndjson`, which one document can't be too.
+        if cli.format == Format::Json {
+            let error = Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "the argument '--json' cannot be used with '--format json'",
+            );
+            exit_on_usage_error(error);
+        }
+        cli.format = Format::Ndjson;
+    }
+    let list = cli.command.lists();
+    let _ = OUTPUT.set(Output::new(cli.format, list, cli.quiet));
+    let trace = match cli.verbose {
+        0 => None,
+        1 => Some(TraceLevel::Changes),
+        _ => Some(TraceLevel::All),
+    };
+    if let Some(level) = trace {
+        set_trace(level, |line| output().trace(line));
+    }
+    let code = Exit::code(run(cli.command));
+    output().finish();
+    process::exit(code);
+}
 
-    match cli.command {
+/// Report a command line that does not parse, as JSON when `--format json`
+/// or `ndjson` was asked for, and exit.
+fn exit_on_usage_error(error: clap::Error) -> ! {
+    use clap::error::ErrorKind;
+
+    let args: Vec<String> = std::env::args().collect();
+    let json = args.iter().enumerate().any(|(index, arg)| {
+        let value = match arg.strip_prefix("--format=") {
+            Some(value) => Some(value),
+            None if arg == "--format" => args.get(index + 1).map(String::as_str),
+            None => None,
+        };
+        matches!(value, Some("json" | "ndjson"))
+    });
+    if matches!(
+        error.kind(),
+        ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+    ) {
+        error.exit();
+    }
+    if json {
+        let message = error.render().to_string();
+        let message = message.lines().next().unwrap_or_default();
+        let message = message.strip_prefix("error: ").unwrap_or(message);
+        eprintln!("{}", error_record(message, "usage", None));
+    } else {
+        let _ = error.print();
+    }
+    process::exit(EX_USAGE);
+}
+
+impl Commands {
+    /// Whether this command reports a list of records rather than one.
+    fn lists(&self) -> bool {
+        match self {
+            Commands::Path { command } => !matches!(command, PathCommand::Relative { .. }),
+            Commands::Copy(_)
+            | Commands::Ls { .. }
+            | Commands::Rm { .. }
+            | Commands::Stat { .. }
+            | Commands::Chmod(_)
+            | Commands::Hash { .. }
+            | Commands::Find(_)
+            | Commands::Batch { .. }
+            | Commands::Grep(_)
+            | Commands::Replace(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn run(command: Commands) -> Status {
+    let out = output();
+    match command {
         Commands::Resolve { path } => match resolve_path(&path) {
             Ok(canonical_path) => {
-                println!("Canonical path: {}", canonical_path);
+                out.text(format_args!("Canonical path: {}", canonical_path));
+                out.record(json!({ "path": path, "canonical": canonical_path }));
             }
             Err(error) => {
-                eprintln!("Error resolving path '{}': {}", path, error);
-                process::exit(1);
+                out.error(&format!("resolving path '{}'", path), Some(&path), &error);
+                return Err(fail());
             }
         },
 
-        Commands::Copy { src, dst } => match copy_file(&src, &dst) {
-            Ok(_) => {
-                println!("Copied '{}' to '{}'", src, dst);
-            }
-            Err(error) => {
-                eprintln!("Error copying '{}' to '{}': {}", src, dst, error);
-                process::exit(1);
-            }
-        },
+        Commands::Path { command } => return files::run_path(command),
+
+        Commands::Copy(args) => return files::run_copy(*args),
 
         Commands::Mkdirp { path } => match ensure_dir(&path) {
             Ok(_) => {
-                println!("Created directories '{}'", path);
+                out.status(format_args!("Created directories '{}'", path));
+                out.record(json!({ "path": path }));
             }
             Err(error) => {
-                eprintln!("Error creating directories '{}': {}", path, error);
-                process::exit(1);
+                out.error(
+                    &format!("creating directories '{}'", path),
+                    Some(&path),
+                    &error,
+                );
+                return Err(fail());
             }
         },
 
         Commands::Mkdir { path } => match create_dir(&path) {
             Ok(_) => {
-                println!("Created directory '{}'", path);
+                out.status(format_args!("Created directory '{}'", path));
+                out.record(json!({ "path": path }));
             }
             Err(error) => {
-                eprintln!("Error creating directory '{}': {}", path, error);
-                process::exit(1);
+                out.error(
+                    &format!("creating directory '{}'", path),
+                    Some(&path),
+                    &error,
+                );
+                return Err(fail());
             }
         },
 
-        Commands::Ls { path } => match list_dir(&path) {
-            Ok(contents) => {
-                println!("Contents of '{}':", path);
-                for item in contents {
-                    println!("  {}", item);
+        Commands::Ls { path } => {
+            match list_dir(&path) {
+                Ok(contents) => {
+                    out.status(format_args!("Contents of '{}':", path));
+                    for item in contents {
+                        out.text(format_args!("  {}", item));
+                        if !out.is_text() {
+                            let entry = Path::new(&path).join(&item);
+                            let kind = symlink_metadata(&entry)
+                                .map(|metadata| type_name(metadata.file_type()))
+                                .ok();
+                            out.record(json!({ "name": item, "path": entry.to_string_lossy(), "type": kind }));
+                        }
+                    }
+                }
+                Err(error) => {
+                    out.error(
+                        &format!("listing directory '{}'", path),
+                        Some(&path),
+                        &error,
+                    );
+                    return Err(fail());
                 }
             }
-            Err(error) => {
-                eprintln!("Error listing directory '{}': {}", path, error);
-                process::exit(1);
-            }
-        },
+        }
 
-        Commands::Rm { path } => match remove_file(&path) {
-            Ok(_) => {
-                println!("Removed file '{}'", path);
+        Commands::Rm { paths } => {
+            let mut failed = false;
+            for path in expand_paths(&paths)? {
+                match remove_file(&path) {
+                    Ok(_) => {
+                        out.status(format_args!("Removed file '{}'", path));
+                        out.record(json!({ "path": path }));
+                    }
+                    Err(error) => {
+                        out.error(&format!("removing file '{}'", path), Some(&path), &error);
+                        failed = true;
+                    }
+                }
             }
-            Err(error) => {
-                eprintln!("Error removing file '{}': {}", path, error);
-                process::exit(1);
+            if failed {
+                return Err(fail());
             }
-        },
+        }
 
         Commands::Rmdir { path } => match remove_empty_dir(&path) {
             Ok(_) => {
-                println!("Removed empty directory '{}'", path);
-            }
-            Err(error) => {
-                eprintln!("Error removing empty directory '{}': {}", path, error);
-                process::exit(1);
-            }
-        },
-
-        Commands::Rmrf { path } => match remove_dir_all(&path) {
-            Ok(_) => {
-                println!("Removed directory recursively '{}'", path);
+                out.status(format_args!("Removed empty directory '{}'", path));
+                out.record(json!({ "path": path }));
             }
             Err(error) => {
-                eprintln!("Error removing directory recursively '{}': {}", path, error);
-                process::exit(1);
+                out.error(
+                    &format!("removing empty directory '{}'", path),
+                    Some(&path),
+                    &error,
+                );
+                return Err(fail());
             }
         },
 
-        Commands::Mv { from, to } => match move_path(&from, &to) {
-            Ok(_) => {
-                println!("Moved '{}' to '{}'", from, to);
-            }
-            Err(error) => {
-                eprintln!("Error moving '{}' to '{}': {}", from, to, error);
-                process::exit(1);
-            }
-        },
+        Commands::Rmrf {
+            path,
+            threads: Some(threads),
+        } => {
+            let report = walker(&path, Some(threads), false).remove();
+            return finish_bulk(report, |report| {
+                out.status(format_args!(
+                    "Removed directory recursively '{}' ({} entries)",
+                    path,
+                    report.done.len()
+                ));
+                out.record(json!({ "path": path, "entries": report.done.len() }));
+            });
+        }
 
-        Commands::Stat { path } => match metadata(&path) {
-            Ok(metadata) => {
-                println!("Metadata for '{}':", path);
-                println!("  Size: {} bytes", metadata.len());
-                println!(
-                    "  Type: {}",
-                    if metadata.is_file() {
-                        "File"
-                    } else if metadata.is_dir() {
-                        "Directory"
-                    } else {
-                        "Other"
-                    }
-                );
-                println!("  Read-only: {}", metadata.permissions().readonly());
-                if let Ok(modified) = metadata.modified() {
-                    println!("  Modified: {:?}", modified);
+        Commands::Rmrf {
+            path,
+            threads: None,
+        } => {
+            let mut bar = ProgressBar::new();
+            let result =
+                remove_dir_all_with_progress(&path, &CancelToken::new(), |p| bar.update(p));
+            bar.finish();
+            match result {
+                Ok(_) => {
+                    out.status(format_args!("Removed directory recursively '{}'", path));
+                    out.record(json!({ "path": path, "entries": null }));
                 }
-            }
-            Err(error) => {
-                eprintln!("Error reading metadata for '{}': {}", path, error);
-                process::exit(1);
-            }
-        },
-
-        Commands::ReadBytes { path } => match read_bytes(&path) {
-            Ok(content) => {
-                println!("Read {} bytes from '{}'", content.len(), path);
-                match String::from_utf8(content) {
-                    Ok(text) => println!("Content:\n{}", text),
-                    Err(_) => println!("Content contains non-UTF8 data"),
+                Err(error) => {
+                    let context = format!("removing directory recursively '{}'", path);
+                    out.error(&context, Some(&path), &error);
+                    return Err(fail());
                 }
             }
-            Err(error) => {
-                eprintln!("Error reading bytes from '{}': {}", path, error);
-                process::exit(1);
-            }
-        },
+        }
 
-        Commands::ReadText { path } => match read_text(&path) {
-            Ok(content) => {
-                println!("Text from '{}':", path);
-                println!("{}", content);
-            }
-            Err(error) => {
-                eprintln!("Error reading text from '{}': {}", path, error);
-                process::exit(1);
-            }
-        },
+        Commands::Mv { from, to, backup } => return files::run_mv(from, to, backup),
 
-        Commands::Write { path, content } => match write_file(&path, &content) {
-            Ok(_) => {
-                println!("Wrote {} bytes to '{}'", content.len(), path);
-            }
-            Err(error) => {
-                eprintln!("Error writing to '{}': {}", path, error);
-                process::exit(1);
-            }
-        },
+        Commands::Stat { paths } => return files::run_stat(paths),
+
+        Commands::ReadBytes {
+            path,
+            offset,
+            length,
+            raw,
+            hex,
+            base64,
+            c_array,
+            text,
+        } => {
+            let view = [
+                (raw, BytesView::Raw),
+                (hex, BytesView::Hex),
+                (base64, BytesView::Base64),
+                (c_array, BytesView::CArray),
+                (text, BytesView::Text),
+            ]
+            .into_iter()
+            .find_map(|(chosen, view)| chosen.then_some(view));
+            return stream::run_read_bytes(&path, offset, length, view);
+        }
+
+        Commands::ReadText { path, range, raw } => return stream::run_read_text(path, range, raw),
+
+        Commands::Head { path, lines, bytes } => return stream::run_head(path, lines, bytes),
+
+        Commands::Tail {
+            path,
+            lines,
+            bytes,
+            follow,
+        } => return stream::run_tail(path, lines, bytes, follow),
+
+        Commands::Write {
+            path,
+            content,
+            append,
+            no_clobber,
+            mode,
+            sync,
+            backup,
+        } => return stream::run_write(path, content, append, no_clobber, mode, sync, backup),
+
+        Commands::Tee { paths, append } => return stream::run_tee(&paths, append),
 
         Commands::Hardlink { original, link } => match create_hard_link(&original, &link) {
             Ok(_) => {
-                println!("Created hard link '{}' -> '{}'", link, original);
+                out.status(format_args!(
+                    "Created hard link '{}' -> '{}'",
+                    link, original
+                ));
+                out.record(json!({ "link": link, "target": original }));
             }
             Err(error) => {
-                eprintln!(
-                    "Error creating hard link '{}' -> '{}': {}",
-                    link, original, error
-                );
-                process::exit(1);
+                let context = format!("creating hard link '{}' -> '{}'", link, original);
+                out.error(&context, Some(&link), &error);
+                return Err(fail());
             }
         },
 
         Commands::Symlink { original, link } => match create_symlink(&original, &link) {
             Ok(_) => {
-                println!("Created symlink '{}' -> '{}'", link, original);
+                out.status(format_args!("Created symlink '{}' -> '{}'", link, original));
+                out.record(json!({ "link": link, "target": original }));
             }
             Err(error) => {
-                eprintln!(
-                    "Error creating symlink '{}' -> '{}': {}",
-                    link, original, error
-                );
-                process::exit(1);
+                let context = format!("creating symlink '{}' -> '{}'", link, original);
+                out.error(&context, Some(&link), &error);
+                return Err(fail());
             }
         },
 
         Commands::Readlink { path } => match read_symlink(&path) {
             Ok(target) => {
-                println!("Symbolic link '{}' points to: {}", path, target);
+                out.text(format_args!(
+                    "Symbolic link '{}' points to: {}",
+                    path, target
+                ));
+                out.record(json!({ "path": path, "target": target }));
             }
             Err(error) => {
-                eprintln!("Error reading symlink '{}': {}", path, error);
-                process::exit(1);
+                out.error(&format!("reading symlink '{}'", path), Some(&path), &error);
+                return Err(fail());
             }
         },
 
-        Commands::Chmod { path, mode } => match metadata(&path) {
-            Ok(metadata) => {
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::{MetadataExt, PermissionsExt};
-
-                    let current_mode = metadata.mode();
-                    let new_mode = match mode.as_str() {
-                        // clear all write bits (owner/group/other)
-                        "readonly" => current_mode & !0o222,
-                        // ensure owner-writable; do not broaden group/other write bits
-                        "writable" => current_mode | 0o200,
-                        _ => {
-                            eprintln!("Invalid mode '{}'. Use 'readonly' or 'writable'", mode);
-                            process::exit(1);
-                        }
-                    };
-                    let perms = PermissionsExt::from_mode(new_mode);
-                    match set_permissions(&path, perms) {
-                        Ok(_) => {
-                            println!("Updated permissions of '{}' to {}", path, mode)
-                        }
-                        Err(error) => {
-                            eprintln!("Error updating permissions of '{}': {}", path, error);
-                            process::exit(1);
-                        }
-                    }
-                }
+        Commands::Chmod(args) => return perms::run_chmod(*args),
 
-                #[cfg(windows)]
-                {
-                    let mut perms = metadata.permissions();
-                    match mode.as_str() {
-                        "readonly" => perms.set_readonly(true),
-                        "writable" => perms.set_readonly(false),
-                        _ => {
-                            eprintln!("Invalid mode '{}'. Use 'readonly' or 'writable'", mode);
-                            process::exit(1);
-                        }
-                    }
-                    match set_permissions(&path, perms) {
-                        Ok(_) => {
-                            println!("Updated permissions of '{}' to {}", path, mode)
-                        }
-                        Err(error) => {
-                            eprintln!("Error updating permissions of '{}': {}", path, error);
-                            process::exit(1);
-                        }
-                    }
+        #[cfg(unix)]
+        Commands::Chown {
+            owner,
+            path,
+            recursive,
+            no_dereference,
+            skip_symlinks,
+        } => return perms::run_chown(owner, path, recursive, no_dereference, skip_symlinks),
+
+        Commands::Touch(args) => return files::run_touch(*args),
+
+        Commands::Hash {
+            path,
+            threads,
+            respect_ignore,
+        } => {
+            let report = walker(&path, threads, respect_ignore).hash();
+            return finish_bulk(report, |report| {
+                for (file, digest) in &report.done {
+                    out.text(format_args!("{}  {}", digest, file.display()));
+                    out.record(json!({ "path": file.to_string_lossy(), "sha256": digest }));
                 }
+            });
+        }
+
+        Commands::Find(args) => return files::run_find(*args),
+
+        Commands::Grep(args) => return text::run_grep(*args),
+
+        Commands::Replace(args) => return text::run_replace(*args),
+
+        Commands::Lstat { path } => match symlink_metadata(&path) {
+            Ok(metadata) => {
+                let kind = if metadata.file_type().is_symlink() {
+                    "Symbolic Link"
+                } else if metadata.is_file() {
+                    "File"
+                } else if metadata.is_dir() {
+                    "Directory"
+                } else {
+                    "Other"
+                };
+                out.text(format_args!("lstat for '{}':", path));
+                print_metadata(kind, &metadata);
+                out.record(metadata_record(&path, &metadata));
             }
             Err(error) => {
-                eprintln!("Error reading metadata for '{}': {}", path, error);
-                process::exit(1);
+                out.error(&format!("lstat '{}'", path), Some(&path), &error);
+                return Err(fail());
             }
         },
 
-        Commands::Lstat { path } => match symlink_metadata(&path) {
-            Ok(metadata) => {
-                println!("lstat for '{}':", path);
-                println!("  Size: {} bytes", metadata.len());
-                println!(
-                    "  Type: {}",
-                    if metadata.file_type().is_symlink() {
-                        "Symbolic Link"
-                    } else if metadata.is_file() {
-                        "File"
-                    } else if metadata.is_dir() {
-                        "Directory"
-                    } else {
-                        "Other"
+        Commands::Batch { file, keep_going } => return batch::run_batch(&file, keep_going),
+
+        Commands::Shell { history } => return shell::run_shell(history),
+
+        Commands::Completions { shell } => {
+            let shells = Shells::builtins();
+            let completer = shells
+                .completer(&shell.to_string())
+                .expect("every clap_complete shell has a dynamic completer");
+            let mut stdout = io::stdout();
+            if let Err(error) =
+                completer.write_registration("COMPLETE", "soil", "soil", "soil", &mut stdout)
+            {
+                out.error("writing completions", None, &error);
+                return Err(fail());
+            }
+        }
+
+        Commands::Man { out_dir } => {
+            let result = match &out_dir {
+                Some(dir) => {
+                    ensure_dir(dir).and_then(|_| clap_mangen::generate_to(Cli::command(), dir))
+                }
+                None => clap_mangen::Man::new(Cli::command()).render(&mut io::stdout()),
+            };
+            match result {
+                Ok(()) => {
+                    if let Some(dir) = out_dir {
+                        out.status(format_args!("Wrote manual pages to '{}'", dir.display()));
+                        out.record(json!({ "dir": dir.to_string_lossy() }));
                     }
-                );
-                println!("  Read-only: {}", metadata.permissions().readonly());
-                if let Ok(modified) = metadata.modified() {
-                    println!("  Modified: {:?}", modified);
+                }
+                Err(error) => {
+                    out.error("writing manual pages", None, &error);
+                    return Err(fail());
                 }
             }
-            Err(error) => {
-                eprintln!("Error lstat '{}': {}", path, error);
-                process::exit(1);
-            }
-        },
+        }
 
         Commands::Exists { path } => {
             let path_exists = exists(&path);
-            println!("Path '{}' exists: {}", path, path_exists);
+            out.status(format_args!("Path '{}' exists: {}", path, path_exists));
+            out.record(json!({ "path": path, "exists": path_exists }));
 
             if !path_exists {
-                process::exit(1);
+                return Err(Exit(1));
             }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
 }
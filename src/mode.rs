@@ -0,0 +1,434 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+const USER: u32 = 0o4700;
+const GROUP: u32 = 0o2070;
+const OTHER: u32 = 0o1007;
+const ALL: u32 = USER | GROUP | OTHER;
+
+/// Unix permission bits (including setuid, setgid and sticky).
+///
+/// Displays in `ls -l` style, e.g. `rwxr-xr-x`.
+///
+/// Examples
+/// ```
+/// use soil::Mode;
+/// let mode: Mode = "0755".parse().unwrap();
+/// assert_eq!(mode.bits(), 0o755);
+/// assert_eq!(mode.to_string(), "rwxr-xr-x");
+///
+/// let mode: Mode = "u=rw,go=r".parse().unwrap();
+/// assert_eq!(mode.to_string(), "rw-r--r--");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Mode(u32);
+
+impl Mode {
+    /// Build a mode from raw bits. Anything above `0o7777` is dropped.
+    pub fn from_bits(bits: u32) -> Mode {
+        Mode(bits & 0o7777)
+    }
+
+    /// The raw permission bits.
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Format the bits as an octal string like `0755`.
+    pub fn to_octal(self) -> String {
+        format!("{:04o}", self.0)
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bits = self.0;
+        let triplets = [
+            (bits >> 6, bits & 0o4000 != 0, 's'),
+            (bits >> 3, bits & 0o2000 != 0, 's'),
+            (bits, bits & 0o1000 != 0, 't'),
+        ];
+
+        let mut rendered = String::with_capacity(9);
+        for (triplet, special, special_char) in triplets {
+            rendered.push(if triplet & 0o4 != 0 { 'r' } else { '-' });
+            rendered.push(if triplet & 0o2 != 0 { 'w' } else { '-' });
+            let exec = triplet & 0o1 != 0;
+            rendered.push(match (exec, special) {
+                (true, true) => special_char,
+                (false, true) => special_char.to_ascii_uppercase(),
+                (true, false) => 'x',
+                (false, false) => '-',
+            });
+        }
+        f.write_str(&rendered)
+    }
+}
+
+impl fmt::Octal for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Octal::fmt(&self.0, f)
+    }
+}
+
+/// Parse an octal or symbolic mode.
+///
+/// Symbolic modes are applied to an empty mode, so `u+x` yields `--x------`.
+/// Use [`ModeChange`] to apply a symbolic mode on top of existing bits.
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Mode, Error> {
+        Ok(spec.parse::<ModeChange>()?.apply(Mode(0), false))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Add,
+    Remove,
+    Set,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Perms {
+    /// Literal `rwxst` bits, plus whether `X` was given.
+    Bits { bits: u32, conditional_exec: bool },
+    /// Copy the permissions of another class (`g=u`).
+    Copy(u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Clause {
+    who: u32,
+    actions: Vec<(Op, Perms)>,
+}
+
+/// A mode change as accepted by `chmod`: either an absolute octal mode
+/// (`0755`) or a comma-separated list of symbolic clauses (`u+x,go-w`,
+/// `a=rX`).
+///
+/// Symbolic clauses without a `who` part (`+x`) apply to everyone; the
+/// process umask is not consulted.
+///
+/// Examples
+/// ```
+/// use soil::{Mode, ModeChange};
+/// let change: ModeChange = "go-w,u+x".parse().unwrap();
+/// let mode = change.apply(Mode::from_bits(0o666), false);
+/// assert_eq!(mode.to_string(), "rwxr--r--");
+///
+/// // `X` only grants execute to directories and already-executable files.
+/// let change: ModeChange = "a=rX".parse().unwrap();
+/// assert_eq!(change.apply(Mode::from_bits(0o600), true).bits(), 0o555);
+/// assert_eq!(change.apply(Mode::from_bits(0o600), false).bits(), 0o444);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModeChange {
+    kind: ChangeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ChangeKind {
+    Absolute(Mode),
+    Symbolic(Vec<Clause>),
+}
+
+impl ModeChange {
+    /// Compute the resulting mode for a path whose current bits are `current`.
+    ///
+    /// `is_dir` decides whether `X` grants execute permission.
+    pub fn apply(&self, current: Mode, is_dir: bool) -> Mode {
+        let clauses = match &self.kind {
+            ChangeKind::Absolute(mode) => return *mode,
+            ChangeKind::Symbolic(clauses) => clauses,
+        };
+
+        let mut bits = current.0;
+        for clause in clauses {
+            for (op, perms) in &clause.actions {
+                let mask = match *perms {
+                    Perms::Bits {
+                        bits: perm_bits,
+                        conditional_exec,
+                    } => {
+                        let mut mask = perm_bits;
+                        if conditional_exec && (is_dir || bits & 0o111 != 0) {
+                            mask |= 0o111;
+                        }
+                        mask
+                    }
+                    Perms::Copy(class) => copy_class(bits, class),
+                };
+                let mask = mask & clause.who;
+                bits = match op {
+                    Op::Add => bits | mask,
+                    Op::Remove => bits & !mask,
+                    Op::Set => (bits & !clause.who) | mask,
+                };
+            }
+        }
+        Mode(bits)
+    }
+
+    /// Whether the change is an absolute octal mode.
+    pub fn is_absolute(&self) -> bool {
+        matches!(self.kind, ChangeKind::Absolute(_))
+    }
+}
+
+impl From<Mode> for ModeChange {
+    fn from(mode: Mode) -> ModeChange {
+        ModeChange {
+            kind: ChangeKind::Absolute(mode),
+        }
+    }
+}
+
+impl FromStr for ModeChange {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<ModeChange, Error> {
+        if spec.is_empty() {
+            return Err(invalid_mode(spec));
+        }
+
+        if spec.bytes().all(|byte| (b'0'..=b'7').contains(&byte)) {
+            let bits = u32::from_str_radix(spec, 8).map_err(|_| invalid_mode(spec))?;
+            if bits > 0o7777 {
+                return Err(invalid_mode(spec));
+            }
+            return Ok(Mode(bits).into());
+        }
+
+        let clauses = spec
+            .split(',')
+            .map(|clause| parse_clause(clause).ok_or_else(|| invalid_mode(spec)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ModeChange {
+            kind: ChangeKind::Symbolic(clauses),
+        })
+    }
+}
+
+impl fmt::Display for ModeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ChangeKind::Absolute(mode) => write!(f, "{}", mode.to_octal()),
+            ChangeKind::Symbolic(clauses) => {
+                for (index, clause) in clauses.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_clause(f, clause)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_clause(clause: &str) -> Option<Clause> {
+    let mut chars = clause.chars().peekable();
+
+    let mut who = 0;
+    while let Some(&ch) = chars.peek() {
+        who |= match ch {
+            'u' => USER,
+            'g' => GROUP,
+            'o' => OTHER,
+            'a' => ALL,
+            _ => break,
+        };
+        chars.next();
+    }
+    if who == 0 {
+        who = ALL;
+    }
+
+    let mut actions = Vec::new();
+    while let Some(ch) = chars.next() {
+        let op = match ch {
+            '+' => Op::Add,
+            '-' => Op::Remove,
+            '=' => Op::Set,
+            _ => return None,
+        };
+
+        let perms = match chars.peek() {
+            Some(&class @ ('u' | 'g' | 'o')) => {
+                chars.next();
+                Perms::Copy(match class {
+                    'u' => USER,
+                    'g' => GROUP,
+                    _ => OTHER,
+                })
+            }
+            _ => {
+                let mut bits = 0;
+                let mut conditional_exec = false;
+                while let Some(&ch) = chars.peek() {
+                    match ch {
+                        'r' => bits |= 0o444,
+                        'w' => bits |= 0o222,
+                        'x' => bits |= 0o111,
+                        'X' => conditional_exec = true,
+                        's' => bits |= 0o6000,
+                        't' => bits |= 0o1000,
+                        _ => break,
+                    }
+                    chars.next();
+                }
+                Perms::Bits {
+                    bits,
+                    conditional_exec,
+                }
+            }
+        };
+        actions.push((op, perms));
+    }
+
+    if actions.is_empty() {
+        return None;
+    }
+    Some(Clause { who, actions })
+}
+
+fn write_clause(f: &mut fmt::Formatter<'_>, clause: &Clause) -> fmt::Result {
+    if clause.who == ALL {
+        f.write_str("a")?;
+    } else {
+        for (class, letter) in [(USER, 'u'), (GROUP, 'g'), (OTHER, 'o')] {
+            if clause.who & class == class {
+                write!(f, "{}", letter)?;
+            }
+        }
+    }
+
+    for (op, perms) in &clause.actions {
+        f.write_str(match op {
+            Op::Add => "+",
+            Op::Remove => "-",
+            Op::Set => "=",
+        })?;
+        match *perms {
+            Perms::Copy(class) => f.write_str(match class {
+                USER => "u",
+                GROUP => "g",
+                _ => "o",
+            })?,
+            Perms::Bits {
+                bits,
+                conditional_exec,
+            } => {
+                for (bit, letter) in [(0o444, "r"), (0o222, "w"), (0o111, "x")] {
+                    if bits & bit != 0 {
+                        f.write_str(letter)?;
+                    }
+                }
+                if conditional_exec {
+                    f.write_str("X")?;
+                }
+                if bits & 0o6000 != 0 {
+                    f.write_str("s")?;
+                }
+                if bits & 0o1000 != 0 {
+                    f.write_str("t")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Spread the `rwx` bits of one class over all three classes.
+fn copy_class(bits: u32, class: u32) -> u32 {
+    let triplet = match class {
+        USER => (bits >> 6) & 0o7,
+        GROUP => (bits >> 3) & 0o7,
+        _ => bits & 0o7,
+    };
+    triplet * 0o111
+}
+
+fn invalid_mode(spec: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("invalid mode '{}'", spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(spec: &str) -> ModeChange {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Mode::from_bits(0o755).to_string(), "rwxr-xr-x");
+        assert_eq!(Mode::from_bits(0o640).to_string(), "rw-r-----");
+        assert_eq!(Mode::from_bits(0o4755).to_string(), "rwsr-xr-x");
+        assert_eq!(Mode::from_bits(0o2644).to_string(), "rw-r-Sr--");
+        assert_eq!(Mode::from_bits(0o1777).to_string(), "rwxrwxrwt");
+        assert_eq!(Mode::from_bits(0o1776).to_string(), "rwxrwxrwT");
+    }
+
+    #[test]
+    fn test_parse_octal() {
+        assert_eq!("0755".parse::<Mode>().unwrap().bits(), 0o755);
+        assert_eq!("644".parse::<Mode>().unwrap().bits(), 0o644);
+        assert_eq!("4755".parse::<Mode>().unwrap().bits(), 0o4755);
+        assert!("0789".parse::<Mode>().is_err());
+        assert!("17777".parse::<Mode>().is_err());
+        assert!("".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn test_symbolic_changes() {
+        let current = Mode::from_bits(0o644);
+        assert_eq!(change("u+x").apply(current, false).bits(), 0o744);
+        assert_eq!(change("go-r").apply(current, false).bits(), 0o600);
+        assert_eq!(change("a+x").apply(current, false).bits(), 0o755);
+        assert_eq!(change("+x").apply(current, false).bits(), 0o755);
+        assert_eq!(change("o=").apply(current, false).bits(), 0o640);
+        assert_eq!(change("u=rwx,g=rx,o=").apply(current, false).bits(), 0o750);
+        assert_eq!(change("g=u").apply(current, false).bits(), 0o664);
+        assert_eq!(change("u+s").apply(current, false).bits(), 0o4644);
+        assert_eq!(change("+t").apply(current, false).bits(), 0o1644);
+        assert_eq!(change("u-w+x").apply(current, false).bits(), 0o544);
+    }
+
+    #[test]
+    fn test_conditional_exec() {
+        assert_eq!(
+            change("a+X").apply(Mode::from_bits(0o644), false).bits(),
+            0o644
+        );
+        assert_eq!(
+            change("a+X").apply(Mode::from_bits(0o744), false).bits(),
+            0o755
+        );
+        assert_eq!(
+            change("a+X").apply(Mode::from_bits(0o700), true).bits(),
+            0o711
+        );
+    }
+
+    #[test]
+    fn test_invalid_symbolic() {
+        assert!("u+q".parse::<ModeChange>().is_err());
+        assert!("z+x".parse::<ModeChange>().is_err());
+        assert!("u".parse::<ModeChange>().is_err());
+        assert!("u+x,".parse::<ModeChange>().is_err());
+    }
+
+    #[test]
+    fn test_change_display_round_trip() {
+        for spec in ["0755", "u+x", "go-w", "a=rX", "u=rwx,g=rx,o=", "g=u"] {
+            let parsed = change(spec);
+            assert_eq!(parsed.to_string().parse::<ModeChange>().unwrap(), parsed);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use clap::ValueEnum;
+use serde_json::{Value, json};
+use soil::{Mode, Progress, format_datetime};
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// The output formats of `--format`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Format {
+    Text,
+    Json,
+    Ndjson,
+}
+
+/// Where `soil` reports results and errors, in the `--format` of this run.
+///
+/// Commands describe each result twice: as text and as a JSON record (the
+/// schemas are listed in the README). A command about a single thing prints
+/// one record; list commands print an array of records with `json`, and one
+/// record per line as they are produced with `ndjson`. With `json` or
+/// `ndjson`, errors go to stderr as `{"error", "kind", "path"}` objects.
+pub(crate) struct Output {
+    pub(crate) format: Format,
+    pub(crate) quiet: bool,
+    /// Records of a list command, held back until the array is closed.
+    records: Mutex<Option<Vec<Value>>>,
+    /// The exit status for the first error reported, or 0.
+    pub(crate) status: AtomicI32,
+    /// Whether a command is running under [`Output::capture`], in a batch
+    /// or the shell, which carry on after it.
+    pub(crate) capturing: AtomicBool,
+}
+
+pub(crate) static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+/// The output of this run, set up in `main` before any command runs.
+pub(crate) fn output() -> &'static Output {
+    OUTPUT.get().expect("output is set up in main")
+}
+
+impl Output {
+    pub(crate) fn new(format: Format, list: bool, quiet: bool) -> Output {
+        let records = (format == Format::Json && list).then(Vec::new);
+        Output {
+            format,
+            quiet,
+            records: Mutex::new(records),
+            status: AtomicI32::new(0),
+            capturing: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    /// Print a line of text, in text mode only.
+    pub(crate) fn text(&self, line: impl fmt::Display) {
+        if self.is_text() {
+            println!("{}", line);
+        }
+    }
+
+    /// Print a message about what was done, in text mode without `-q`.
+    pub(crate) fn status(&self, line: impl fmt::Display) {
+        if self.is_text() && !self.quiet {
+            println!("{}", line);
+        }
+    }
+
+    /// Print a line of the `-v` trace on stderr.
+    pub(crate) fn trace(&self, line: &str) {
+        if self.is_text() {
+            eprintln!("soil: {}", line);
+        } else {
+            eprintln!("{}", json!({ "trace": line }));
+        }
+    }
+
+    /// Print a JSON record, in the JSON modes only.
+    pub(crate) fn record(&self, record: Value) {
+        if self.is_text() {
+            return;
+        }
+        match &mut *self.records.lock().unwrap() {
+            Some(records) => records.push(record),
+            None if self.format == Format::Ndjson => println!("{}", record),
+            None => println!("{:#}", record),
+        }
+    }
+
+    /// Run one command of a batch or the shell, returning its exit status
+    /// and the records it produced rather than printing them.
+    pub(crate) fn capture(&self, command: impl FnOnce() -> Status) -> (i32, Vec<Value>) {
+        let saved = self.records.lock().unwrap().replace(Vec::new());
+        self.status.store(0, Ordering::Relaxed);
+        let nested = self.capturing.swap(true, Ordering::Relaxed);
+        let status = Exit::code(command());
+        self.capturing.store(nested, Ordering::Relaxed);
+        let records = mem::replace(&mut *self.records.lock().unwrap(), saved);
+        (status, records.unwrap_or_default())
+    }
+
+    /// Report a failure as `Error <context>: <error>`, or `Error: <error>`
+    /// without context.
+    pub(crate) fn error(&self, context: &str, path: Option<&str>, error: &io::Error) {
+        let code = exit_code(error.kind());
+        let _ = self
+            .status
+            .compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+        let message = if context.is_empty() {
+            error.to_string()
+        } else {
+            format!("{}: {}", context, error)
+        };
+        if self.is_text() {
+            match context {
+                "" => eprintln!("Error: {}", message),
+                _ => eprintln!("Error {}", message),
+            }
+        } else {
+            eprintln!("{}", error_record(&message, kind_name(error.kind()), path));
+        }
+    }
+
+    /// Close the array of a list command.
+    pub(crate) fn finish(&self) {
+        if let Some(records) = self.records.lock().unwrap().take() {
+            println!("{:#}", Value::Array(records));
+        }
+    }
+}
+
+/// The JSON object reporting an error on stderr.
+pub(crate) fn error_record(message: &str, kind: &str, path: Option<&str>) -> Value {
+    json!({ "error": message, "kind": kind, "path": path })
+}
+
+/// A command that ended before running to completion, with the exit status
+/// to end with: non-zero for a failure, 0 for a quiet early stop.
+#[derive(Debug)]
+pub(crate) struct Exit(pub(crate) i32);
+
+/// What a command returns: `Ok` when it ran to completion, which exits 0.
+pub(crate) type Status = Result<(), Exit>;
+
+impl Exit {
+    /// The exit status for the outcome of a command.
+    pub(crate) fn code(status: Status) -> i32 {
+        match status {
+            Ok(()) => 0,
+            Err(Exit(code)) => code,
+        }
+    }
+
+    /// End with `code`, or complete normally when it is 0.
+    pub(crate) fn with(code: i32) -> Status {
+        match code {
+            0 => Ok(()),
+            code => Err(Exit(code)),
+        }
+    }
+}
+
+/// The status of the first error reported, or 1 if none was.
+pub(crate) fn fail() -> Exit {
+    match output().status.load(Ordering::Relaxed) {
+        0 => Exit(1),
+        code => Exit(code),
+    }
+}
+
+/// How a command ends when the reader of its output went away (e.g.
+/// `| head`): quietly with `code` on its own, but as an I/O failure of
+/// that command in a batch or the shell, which go on to the next one.
+pub(crate) fn reader_gone(code: i32) -> Exit {
+    if output().capturing.load(Ordering::Relaxed) {
+        Exit(EX_IOERR)
+    } else {
+        Exit(code)
+    }
+}
+
+/// Exit status for a command line that does not parse (`EX_USAGE`).
+pub(crate) const EX_USAGE: i32 = 64;
+
+/// Exit status for an I/O error of no more specific class (`EX_IOERR`).
+pub(crate) const EX_IOERR: i32 = 74;
+
+/// The exit status for a failure of this kind, from `sysexits.h` where one
+/// fits. The table is in the README; scripts may rely on it.
+pub(crate) fn exit_code(kind: io::ErrorKind) -> i32 {
+    use io::ErrorKind::*;
+
+    match kind {
+        InvalidInput => EX_USAGE,
+        InvalidData => 65,                           // EX_DATAERR
+        NotFound => 66,                              // EX_NOINPUT
+        Unsupported => 69,                           // EX_UNAVAILABLE
+        AlreadyExists | DirectoryNotEmpty => 73,     // EX_CANTCREAT
+        Interrupted | TimedOut | WouldBlock => 75,   // EX_TEMPFAIL
+        PermissionDenied | ReadOnlyFilesystem => 77, // EX_NOPERM
+        _ => EX_IOERR,
+    }
+}
+
+/// The `kind` of a JSON error record. The names are listed in the README
+/// alongside the exit codes; kinds not named there report `other`.
+pub(crate) fn kind_name(kind: io::ErrorKind) -> &'static str {
+    use io::ErrorKind::*;
+
+    match kind {
+        InvalidInput => "invalid_input",
+        InvalidData => "invalid_data",
+        NotFound => "not_found",
+        Unsupported => "unsupported",
+        AlreadyExists => "already_exists",
+        DirectoryNotEmpty => "directory_not_empty",
+        IsADirectory => "is_a_directory",
+        NotADirectory => "not_a_directory",
+        Interrupted => "interrupted",
+        TimedOut => "timed_out",
+        WouldBlock => "would_block",
+        PermissionDenied => "permission_denied",
+        ReadOnlyFilesystem => "read_only_filesystem",
+        _ => "other",
+    }
+}
+
+/// A progress bar on stderr for `copy`, `rmrf` and `mv`.
+///
+/// Stays invisible unless stderr is a terminal and the operation has been
+/// running for a moment, so quick operations print nothing extra.
+pub(crate) struct ProgressBar {
+    enabled: bool,
+    started: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl ProgressBar {
+    const DELAY: Duration = Duration::from_millis(300);
+    const REFRESH: Duration = Duration::from_millis(100);
+    const WIDTH: usize = 30;
+
+    pub(crate) fn new() -> ProgressBar {
+        ProgressBar {
+            enabled: io::stderr().is_terminal() && !output().quiet,
+            started: Instant::now(),
+            last_draw: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, progress: &Progress) {
+        if !self.enabled || self.started.elapsed() < Self::DELAY {
+            return;
+        }
+        if self
+            .last_draw
+            .is_some_and(|last| last.elapsed() < Self::REFRESH)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        let fraction = progress.fraction().clamp(0.0, 1.0);
+        let filled = (fraction * Self::WIDTH as f64).round() as usize;
+        eprint!(
+            "\r\x1b[2K[{}{}] {:>3}%  {} / {}  {}/{} entries",
+            "#".repeat(filled),
+            "-".repeat(Self::WIDTH - filled),
+            (fraction * 100.0) as u32,
+            format_bytes(progress.bytes_done),
+            format_bytes(progress.bytes_total),
+            progress.files_done,
+            progress.files_total,
+        );
+        let _ = io::stderr().flush();
+    }
+
+    pub(crate) fn finish(&mut self) {
+        if self.last_draw.is_some() {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+/// Render a byte count with a binary unit, e.g. `12.5 MiB`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// The text lines shared by `soil stat` and `soil lstat`.
+pub(crate) fn print_metadata(kind: &str, metadata: &fs::Metadata) {
+    let out = output();
+    out.text(format_args!("  Size: {} bytes", metadata.len()));
+    out.text(format_args!("  Type: {}", kind));
+    out.text(format_args!(
+        "  Read-only: {}",
+        metadata.permissions().readonly()
+    ));
+    if let Ok(modified) = metadata.modified() {
+        out.text(format_args!("  Modified: {}", format_datetime(modified)));
+    }
+}
+
+/// The JSON record of `soil stat` and `soil lstat`.
+pub(crate) fn metadata_record(path: &str, metadata: &fs::Metadata) -> Value {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(Mode::from_bits(metadata.permissions().mode()).to_octal())
+    };
+    #[cfg(not(unix))]
+    let mode: Option<String> = None;
+    json!({
+        "path": path,
+        "type": type_name(metadata.file_type()),
+        "size": metadata.len(),
+        "readonly": metadata.permissions().readonly(),
+        "mode": mode,
+        "modified": metadata.modified().ok().map(format_datetime),
+        "accessed": metadata.accessed().ok().map(format_datetime),
+    })
+}
+
+/// The `type` field of JSON records: `file`, `dir`, `symlink` or `other`.
+pub(crate) fn type_name(file_type: fs::FileType) -> &'static str {
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "dir"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        use io::ErrorKind::*;
+        assert_eq!(exit_code(NotFound), 66);
+        assert_eq!(exit_code(PermissionDenied), 77);
+        assert_eq!(exit_code(AlreadyExists), 73);
+        assert_eq!(exit_code(InvalidInput), EX_USAGE);
+        assert_eq!(exit_code(Other), 74);
+        assert_eq!(kind_name(NotFound), "not_found");
+        assert_eq!(kind_name(ReadOnlyFilesystem), "read_only_filesystem");
+        assert_eq!(kind_name(OutOfMemory), "other");
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+const PASSWD: &str = "/etc/passwd";
+const GROUP: &str = "/etc/group";
+
+/// A user and/or group to hand a path to, as accepted by `chown`.
+///
+/// Parses `user`, `user:group`, `:group` and `user:` (the user's login
+/// group). Either side may be a name or a numeric id; names are resolved
+/// through `/etc/passwd` and `/etc/group`.
+///
+/// Examples
+/// ```
+/// use soil::Owner;
+/// let owner: Owner = "0:0".parse().unwrap();
+/// assert_eq!(owner, Owner { uid: Some(0), gid: Some(0) });
+///
+/// let owner: Owner = ":100".parse().unwrap();
+/// assert_eq!(owner, Owner { uid: None, gid: Some(100) });
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Owner {
+    /// New owning user, or `None` to leave it unchanged.
+    pub uid: Option<u32>,
+    /// New owning group, or `None` to leave it unchanged.
+    pub gid: Option<u32>,
+}
+
+impl Owner {
+    /// Change only the owning user.
+    pub fn user(uid: u32) -> Owner {
+        Owner {
+            uid: Some(uid),
+            gid: None,
+        }
+    }
+
+    /// Change only the owning group.
+    pub fn group(gid: u32) -> Owner {
+        Owner {
+            uid: None,
+            gid: Some(gid),
+        }
+    }
+}
+
+impl FromStr for Owner {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Owner, Error> {
+        parse_owner(spec, |path| fs::read_to_string(path))
+    }
+}
+
+/// Parse an owner spec, resolving names through the user and group
+/// databases that `read` returns the contents of.
+fn parse_owner<R>(spec: &str, read: R) -> Result<Owner, Error>
+where
+    R: Fn(&str) -> Result<String, Error>,
+{
+    let (user, group) = match spec.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    };
+
+    if user.is_empty() && group.is_none_or(str::is_empty) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid owner '{}'", spec),
+        ));
+    }
+
+    let mut owner = Owner::default();
+    if !user.is_empty() {
+        let (uid, login_gid) = match user.parse::<u32>() {
+            Ok(uid) => (uid, None),
+            Err(_) => {
+                let entry = user_entry(&read(PASSWD)?, user)?;
+                (entry.0, Some(entry.1))
+            }
+        };
+        owner.uid = Some(uid);
+
+        // `user:` means "and the user's login group".
+        if group == Some("") {
+            owner.gid = match login_gid {
+                Some(gid) => Some(gid),
+                None => Some(login_group_of(&read(PASSWD)?, uid)?),
+            };
+        }
+    }
+
+    if let Some(group) = group.filter(|group| !group.is_empty()) {
+        owner.gid = Some(match group.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => group_entry(&read(GROUP)?, group)?,
+        });
+    }
+
+    Ok(owner)
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(uid) = self.uid {
+            write!(f, "{}", uid)?;
+        }
+        if let Some(gid) = self.gid {
+            write!(f, ":{}", gid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Look up a user id by name in `/etc/passwd`.
+///
+/// Examples
+/// ```
+/// let passwd = std::fs::read_to_string("/etc/passwd").unwrap();
+/// let first = passwd.lines().find(|line| !line.starts_with('#')).unwrap();
+/// let fields: Vec<&str> = first.split(':').collect();
+/// assert_eq!(soil::user_id(fields[0]).unwrap(), fields[2].parse::<u32>().unwrap());
+/// assert!(soil::user_id("no-such-user-here").is_err());
+/// ```
+pub fn user_id(name: &str) -> Result<u32, Error> {
+    Ok(user_entry(&fs::read_to_string(PASSWD)?, name)?.0)
+}
+
+/// Look up a group id by name in `/etc/group`.
+///
+/// Examples
+/// ```
+/// let groups = std::fs::read_to_string("/etc/group").unwrap();
+/// let first = groups.lines().find(|line| !line.starts_with('#')).unwrap();
+/// let fields: Vec<&str> = first.split(':').collect();
+/// assert_eq!(soil::group_id(fields[0]).unwrap(), fields[2].parse::<u32>().unwrap());
+/// assert!(soil::group_id("no-such-group-here").is_err());
+/// ```
+pub fn group_id(name: &str) -> Result<u32, Error> {
+    group_entry(&fs::read_to_string(GROUP)?, name)
+}
+
+/// The gid of the named group in the group database `contents`.
+fn group_entry(contents: &str, name: &str) -> Result<u32, Error> {
+    find_by_name(contents, name)
+        .and_then(|fields| fields.get(2)?.parse().ok())
+        .ok_or_else(|| not_found("group", name))
+}
+
+/// `(uid, login gid)` of the named user in the user database `contents`.
+fn user_entry(contents: &str, name: &str) -> Result<(u32, u32), Error> {
+    find_by_name(contents, name)
+        .and_then(|fields| Some((fields.get(2)?.parse().ok()?, fields.get(3)?.parse().ok()?)))
+        .ok_or_else(|| not_found("user", name))
+}
+
+fn login_group_of(contents: &str, uid: u32) -> Result<u32, Error> {
+    contents
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.get(2).and_then(|id| id.parse().ok()) == Some(uid))
+        .and_then(|fields| fields.get(3)?.parse().ok())
+        .ok_or_else(|| not_found("user id", &uid.to_string()))
+}
+
+/// Find the colon-separated record whose first field is `name`.
+fn find_by_name<'a>(contents: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))
+}
+
+fn not_found(kind: &str, name: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("unknown {} '{}'", kind, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+# comment line
+root:x:0:0:root:/root:/bin/bash
+svc-web:x:998:997::/srv/web:/usr/sbin/nologin
+";
+
+    const GROUP_SAMPLE: &str = "\
+root:x:0:
+daemon:x:2:
+www-data:x:33:svc-web
+";
+
+    /// Parse `spec` against the sample databases instead of the host's.
+    fn parse_sample(spec: &str) -> Result<Owner, Error> {
+        parse_owner(spec, |path| {
+            Ok(match path {
+                PASSWD => SAMPLE.to_string(),
+                _ => GROUP_SAMPLE.to_string(),
+            })
+        })
+    }
+
+    #[test]
+    fn test_find_by_name() {
+        let fields = find_by_name(SAMPLE, "svc-web").unwrap();
+        assert_eq!(fields[2], "998");
+        assert_eq!(fields[3], "997");
+        assert!(find_by_name(SAMPLE, "svc").is_none());
+        assert!(find_by_name(SAMPLE, "# comment line").is_none());
+    }
+
+    #[test]
+    fn test_parse_numeric() {
+        assert_eq!(
+            "1000:1000".parse::<Owner>().unwrap(),
+            Owner {
+                uid: Some(1000),
+                gid: Some(1000)
+            }
+        );
+        assert_eq!("1000".parse::<Owner>().unwrap(), Owner::user(1000));
+        assert_eq!(":50".parse::<Owner>().unwrap(), Owner::group(50));
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(
+            parse_sample("root:daemon").unwrap(),
+            Owner {
+                uid: Some(0),
+                gid: Some(2)
+            }
+        );
+        assert_eq!(
+            parse_sample("svc-web:").unwrap(),
+            Owner {
+                uid: Some(998),
+                gid: Some(997)
+            }
+        );
+        assert_eq!(
+            parse_sample("998:").unwrap(),
+            Owner {
+                uid: Some(998),
+                gid: Some(997)
+            }
+        );
+        assert_eq!(parse_sample(":www-data").unwrap(), Owner::group(33));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_sample("").is_err());
+        assert!(parse_sample(":").is_err());
+        assert!(parse_sample("no-such-user-here").is_err());
+        assert!(parse_sample(":no-such-group-here").is_err());
+        assert!(parse_sample("4242:").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Owner::user(5).to_string(), "5");
+        assert_eq!(Owner::group(7).to_string(), ":7");
+        assert_eq!(
+            Owner {
+                uid: Some(5),
+                gid: Some(7)
+            }
+            .to_string(),
+            "5:7"
+        );
+    }
+}
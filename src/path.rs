@@ -0,0 +1,289 @@
+use std::env;
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+/// Resolve `.` and `..` in a path without touching the filesystem.
+///
+/// Unlike [`resolve_path`](crate::resolve_path), the path need not exist and
+/// symbolic links are not followed, so `link/..` becomes `.` even when
+/// `link` points elsewhere. Repeated and trailing separators are dropped.
+/// Leading `..` components of a relative path are kept, `..` at the root is
+/// dropped, and a path that cancels out entirely becomes `.`.
+///
+/// Examples
+/// ```
+/// use soil::normalize;
+/// use std::path::Path;
+/// assert_eq!(normalize("a/./b//../c/"), Path::new("a/c"));
+/// assert_eq!(normalize("../x/../../y"), Path::new("../../y"));
+/// assert_eq!(normalize("/../etc"), Path::new("/etc"));
+/// assert_eq!(normalize("a/.."), Path::new("."));
+/// ```
+pub fn normalize<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut normal = PathBuf::new();
+    // Number of trailing components of `normal` that a `..` may cancel.
+    let mut named = 0;
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if named > 0 => {
+                normal.pop();
+                named -= 1;
+            }
+            Component::ParentDir if normal.has_root() => {}
+            Component::ParentDir => normal.push(".."),
+            Component::Normal(name) => {
+                normal.push(name);
+                named += 1;
+            }
+            root => normal.push(root),
+        }
+    }
+    if normal.as_os_str().is_empty() {
+        normal.push(".");
+    }
+    normal
+}
+
+/// The path that leads from the directory `base` to `path`, such as
+/// `../../x`, worked out lexically (see [`normalize`]).
+///
+/// Both paths must be absolute or both relative; otherwise, or when `base`
+/// climbs above `path` with `..` so that the way back is unknown, this fails
+/// with `ErrorKind::InvalidInput`. Equal paths give `.`.
+///
+/// Examples
+/// ```
+/// use soil::relative_to;
+/// use std::path::Path;
+/// assert_eq!(relative_to("/srv/app/logs/today", "/srv/data").unwrap(), Path::new("../app/logs/today"));
+/// assert_eq!(relative_to("src/lib.rs", "src").unwrap(), Path::new("lib.rs"));
+/// assert_eq!(relative_to("/srv", "/srv/").unwrap(), Path::new("."));
+/// assert!(relative_to("/srv", "srv").is_err());
+/// ```
+pub fn relative_to<P: AsRef<Path>, B: AsRef<Path>>(path: P, base: B) -> Result<PathBuf, Error> {
+    let (path, base) = (normalize(path), normalize(base));
+    if path.has_root() != base.has_root() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "cannot relate {} to {}: one is absolute and the other relative",
+                path.display(),
+                base.display()
+            ),
+        ));
+    }
+    let path_parts: Vec<_> = path.components().filter(is_named).collect();
+    let base_parts: Vec<_> = base.components().filter(is_named).collect();
+    let common = path_parts
+        .iter()
+        .zip(&base_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if base_parts[common..].contains(&Component::ParentDir) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "cannot relate {} to {}: the base climbs out of the common directory",
+                path.display(),
+                base.display()
+            ),
+        ));
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_parts.len() {
+        relative.push("..");
+    }
+    relative.extend(&path_parts[common..]);
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    Ok(relative)
+}
+
+/// Whether `child` is `parent` or lies inside it, compared lexically (see
+/// [`normalize`]). An absolute path is never within a relative one or the
+/// other way round.
+///
+/// Examples
+/// ```
+/// use soil::is_within;
+/// assert!(is_within("/srv/app/../app/data", "/srv/app"));
+/// assert!(is_within("/srv/app", "/srv/app/"));
+/// assert!(!is_within("/srv/application", "/srv/app"));
+/// assert!(!is_within("/srv/app/../../etc/passwd", "/srv/app"));
+/// ```
+pub fn is_within<C: AsRef<Path>, P: AsRef<Path>>(child: C, parent: P) -> bool {
+    let (child, parent) = (normalize(child), normalize(parent));
+    if child.has_root() != parent.has_root() {
+        return false;
+    }
+    if parent == Path::new(".") {
+        return !child.starts_with("..");
+    }
+    child.starts_with(&parent)
+}
+
+/// Expand a leading `~` and `$NAME` or `${NAME}` variables from the process
+/// environment. See [`expand_with`] for the rules.
+///
+/// Examples
+/// ```
+/// use soil::expand;
+/// // SAFETY: nothing else in this example reads the environment.
+/// unsafe { std::env::set_var("SOIL_EXPAND_EXAMPLE", "demo") };
+/// assert_eq!(expand("/srv/$SOIL_EXPAND_EXAMPLE/logs").unwrap().to_str(), Some("/srv/demo/logs"));
+/// assert!(expand("$SOIL_EXPAND_EXAMPLE_UNSET").is_err());
+/// ```
+pub fn expand(text: &str) -> Result<PathBuf, Error> {
+    expand_with(text, |name| env::var(name).ok())
+}
+
+/// Expand a leading `~` and `$NAME` or `${NAME}` variables, looking names up
+/// with `lookup`.
+///
+/// `~` alone or followed by `/` becomes the value of `HOME`; `~user` is left
+/// as it is. Variable names are letters, digits and `_`, and `$$` stands for
+/// a literal `$`. An undefined variable fails with `ErrorKind::NotFound` and
+/// an unclosed `${` with `ErrorKind::InvalidInput`.
+///
+/// Examples
+/// ```
+/// use soil::expand_with;
+/// let lookup = |name: &str| match name {
+///     "HOME" => Some("/home/ada".to_string()),
+///     "PROJECT" => Some("engine".to_string()),
+///     _ => None,
+/// };
+/// let path = expand_with("~/${PROJECT}_v2/$PROJECT.toml", lookup).unwrap();
+/// assert_eq!(path.to_str(), Some("/home/ada/engine_v2/engine.toml"));
+/// assert_eq!(expand_with("cost$$", lookup).unwrap().to_str(), Some("cost$"));
+/// assert!(expand_with("$MISSING/dir", lookup).is_err());
+/// ```
+pub fn expand_with<F>(text: &str, lookup: F) -> Result<PathBuf, Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let value = |name: &str| {
+        lookup(name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("{}: undefined variable {}", text, name),
+            )
+        })
+    };
+
+    let mut expanded = String::new();
+    let mut rest = text;
+    if let Some(after) = rest.strip_prefix('~')
+        && (after.is_empty() || after.starts_with('/'))
+    {
+        expanded.push_str(&value("HOME")?);
+        rest = after;
+    }
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput, format!("{}: unclosed ${{", text))
+            })?;
+            expanded.push_str(&value(&braced[..end])?);
+            rest = &braced[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                // A lone `$` is not a variable.
+                expanded.push('$');
+            } else {
+                expanded.push_str(&value(&rest[..end])?);
+                rest = &rest[end..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+fn is_named(component: &Component) -> bool {
+    matches!(component, Component::Normal(_) | Component::ParentDir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        for (input, expected) in [
+            ("", "."),
+            (".", "."),
+            ("./", "."),
+            ("/", "/"),
+            ("/..", "/"),
+            ("..", ".."),
+            ("../..", "../.."),
+            ("a/../..", ".."),
+            ("a/b/../../c", "c"),
+            ("/a/./b/", "/a/b"),
+        ] {
+            assert_eq!(normalize(input), Path::new(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_relative_to() {
+        for (path, base, expected) in [
+            ("/a/b/c", "/a/d/e", "../../b/c"),
+            ("/a", "/a/b/c", "../.."),
+            ("/", "/a", ".."),
+            ("a/b", ".", "a/b"),
+            (".", "a/b", "../.."),
+            ("../x", "y", "../../x"),
+            ("../x", "../y", "../x"),
+        ] {
+            assert_eq!(
+                relative_to(path, base).unwrap(),
+                Path::new(expected),
+                "{} from {}",
+                path,
+                base
+            );
+        }
+        assert!(relative_to("x", "../y").is_err());
+    }
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within("a/b", "a"));
+        assert!(is_within("a", "."));
+        assert!(is_within("/", "/"));
+        assert!(is_within("/etc", "/"));
+        assert!(!is_within("../a", "."));
+        assert!(!is_within("a", "/a"));
+        assert!(!is_within("a", "a/b"));
+    }
+
+    #[test]
+    fn test_expand_edge_cases() {
+        let lookup = |name: &str| (name == "HOME" || name == "A").then(|| name.to_lowercase());
+        assert_eq!(expand_with("~", lookup).unwrap(), Path::new("home"));
+        assert_eq!(expand_with("~ada/x", lookup).unwrap(), Path::new("~ada/x"));
+        assert_eq!(expand_with("a/~/b", lookup).unwrap(), Path::new("a/~/b"));
+        assert_eq!(expand_with("$A-$", lookup).unwrap(), Path::new("a-$"));
+        assert_eq!(
+            expand_with("x$A_y", lookup).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            expand_with("${A", lookup).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use serde_json::json;
+#[cfg(unix)]
+use soil::{
+    ChmodTarget, ModeChange, Owner, chmod, chmod_recursive, chown, chown_recursive, lchown,
+    lchown_recursive,
+};
+#[cfg(windows)]
+use soil::{metadata, set_permissions};
+use std::io;
+
+use crate::ChmodArgs;
+use crate::files::expand_paths;
+#[cfg(unix)]
+use crate::files::{report_failures, walker};
+#[cfg(unix)]
+use crate::output::{EX_USAGE, Exit, error_record, kind_name};
+use crate::output::{Status, fail, output};
+
+/// `soil chmod`: apply `mode` to every path, and below directories with -R.
+pub(crate) fn run_chmod(args: ChmodArgs) -> Status {
+    let out = output();
+    let ChmodArgs {
+        paths,
+        mode,
+        recursive,
+        files_only,
+        dirs_only,
+        threads,
+    } = args;
+    let paths = expand_paths(&paths)?;
+    let mut failed = false;
+
+    #[cfg(unix)]
+    {
+        let change: ModeChange = match mode.as_str() {
+            // clear all write bits (owner/group/other)
+            "readonly" => "a-w".parse(),
+            // ensure owner-writable; do not broaden group/other write bits
+            "writable" => "u+w".parse(),
+            spec => spec.parse(),
+        }
+        .map_err(|error: io::Error| {
+            let message = format!(
+                "{}. Use octal (0755), symbolic (u+x,go-w) or 'readonly'/'writable'",
+                error
+            );
+            if out.is_text() {
+                eprintln!("{}", message);
+            } else {
+                eprintln!("{}", error_record(&message, kind_name(error.kind()), None));
+            }
+            Exit(EX_USAGE)
+        })?;
+        let target = if files_only {
+            ChmodTarget::Files
+        } else if dirs_only {
+            ChmodTarget::Dirs
+        } else {
+            ChmodTarget::All
+        };
+
+        for path in &paths {
+            if recursive && threads.is_some() {
+                match walker(path, threads, false).chmod(&change, target) {
+                    Ok(report) => {
+                        report_failures(&report);
+                        failed |= !report.is_success();
+                        out.status(format_args!(
+                            "Updated permissions of {} entries under '{}' with {}",
+                            report.done.len(),
+                            path,
+                            change
+                        ));
+                        out.record(json!({
+                            "path": path,
+                            "change": change.to_string(),
+                            "mode": null,
+                            "entries": report.done.len(),
+                        }));
+                    }
+                    Err(error) => {
+                        let context = format!("updating permissions under '{}'", path);
+                        out.error(&context, Some(path), &error);
+                        failed = true;
+                    }
+                }
+            } else if recursive {
+                match chmod_recursive(path, &change, target) {
+                    Ok(_) => {
+                        out.status(format_args!(
+                            "Updated permissions under '{}' with {}",
+                            path, change
+                        ));
+                        out.record(json!({
+                            "path": path,
+                            "change": change.to_string(),
+                            "mode": null,
+                            "entries": null,
+                        }));
+                    }
+                    Err(error) => {
+                        let context = format!("updating permissions under '{}'", path);
+                        out.error(&context, Some(path), &error);
+                        failed = true;
+                    }
+                }
+            } else {
+                match chmod(path, &change) {
+                    Ok(new_mode) => {
+                        out.status(format_args!(
+                            "Updated permissions of '{}' to {} ({})",
+                            path,
+                            new_mode,
+                            new_mode.to_octal()
+                        ));
+                        out.record(json!({
+                            "path": path,
+                            "change": change.to_string(),
+                            "mode": new_mode.to_octal(),
+                            "entries": 1,
+                        }));
+                    }
+                    Err(error) => {
+                        let context = format!("updating permissions of '{}'", path);
+                        out.error(&context, Some(path), &error);
+                        failed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        if recursive || files_only || dirs_only || threads.is_some() {
+            let message = "recursive chmod is not supported on this platform";
+            out.error(
+                "",
+                None,
+                &io::Error::new(io::ErrorKind::Unsupported, message),
+            );
+            return Err(fail());
+        }
+        let readonly = match mode.as_str() {
+            "readonly" => true,
+            "writable" => false,
+            _ => {
+                let message = format!("invalid mode '{}'. Use 'readonly' or 'writable'", mode);
+                out.error(
+                    "",
+                    None,
+                    &io::Error::new(io::ErrorKind::InvalidInput, message),
+                );
+                return Err(fail());
+            }
+        };
+        for path in &paths {
+            let result = metadata(path).and_then(|metadata| {
+                let mut perms = metadata.permissions();
+                perms.set_readonly(readonly);
+                set_permissions(path, perms)
+            });
+            match result {
+                Ok(_) => {
+                    out.status(format_args!(
+                        "Updated permissions of '{}' to {}",
+                        path, mode
+                    ));
+                    out.record(json!({
+                        "path": path,
+                        "change": mode,
+                        "mode": null,
+                        "entries": 1,
+                    }));
+                }
+                Err(error) => {
+                    let context = format!("updating permissions of '{}'", path);
+                    out.error(&context, Some(path), &error);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        return Err(fail());
+    }
+    Ok(())
+}
+
+/// `soil chown`: hand `path`, and everything below it with -R, to `owner`.
+#[cfg(unix)]
+pub(crate) fn run_chown(
+    owner: String,
+    path: String,
+    recursive: bool,
+    no_dereference: bool,
+    skip_symlinks: bool,
+) -> Status {
+    let out = output();
+    let parsed: Owner = owner.parse().map_err(|error| {
+        out.error(&format!("parsing owner '{}'", owner), None, &error);
+        fail()
+    })?;
+
+    let result = if recursive && no_dereference {
+        lchown_recursive(&path, parsed, skip_symlinks)
+    } else if recursive {
+        chown_recursive(&path, parsed, skip_symlinks)
+    } else if no_dereference {
+        lchown(&path, parsed)
+    } else {
+        chown(&path, parsed)
+    };
+
+    match result {
+        Ok(_) => {
+            out.status(format_args!("Changed owner of '{}' to {}", path, owner));
+            out.record(json!({
+                "path": path,
+                "uid": parsed.uid,
+                "gid": parsed.gid,
+                "recursive": recursive,
+            }));
+        }
+        Err(error) => {
+            out.error(
+                &format!("changing owner of '{}'", path),
+                Some(&path),
+                &error,
+            );
+            return Err(fail());
+        }
+    }
+    Ok(())
+}
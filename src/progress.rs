@@ -0,0 +1,557 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Backup;
+use crate::copy::{CopyOptions, CopyStrategy, copy_file_tracked};
+
+/// How far a long-running operation has come.
+///
+/// Totals are measured up front, so `bytes_done / bytes_total` is a usable
+/// fraction as soon as the first report arrives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Progress {
+    /// Bytes copied (or, for removals, bytes of files already deleted).
+    pub bytes_done: u64,
+    /// Bytes the operation will handle in total.
+    pub bytes_total: u64,
+    /// Files and directories finished so far.
+    pub files_done: u64,
+    /// Files and directories the operation will handle in total.
+    pub files_total: u64,
+}
+
+impl Progress {
+    /// Completion between `0.0` and `1.0`, by bytes when there are any and by
+    /// entries otherwise.
+    pub fn fraction(&self) -> f64 {
+        if self.bytes_total > 0 {
+            self.bytes_done as f64 / self.bytes_total as f64
+        } else if self.files_total > 0 {
+            self.files_done as f64 / self.files_total as f64
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A cheaply clonable flag to stop a long-running operation.
+///
+/// Operations check it between chunks and between directory entries; a
+/// cancelled operation returns an error of kind [`ErrorKind::Interrupted`].
+///
+/// Examples
+/// ```
+/// use soil::CancelToken;
+/// let token = CancelToken::new();
+/// let handle = token.clone();
+/// assert!(!token.is_cancelled());
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// A token that has not been cancelled yet.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Ask every operation watching this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether [`CancelToken::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Bookkeeping shared by the progress-aware operations.
+pub(crate) struct Tracker<'a> {
+    progress: Progress,
+    report: Option<&'a mut dyn FnMut(&Progress)>,
+    cancel: Option<&'a CancelToken>,
+}
+
+impl<'a> Tracker<'a> {
+    /// A tracker that reports nowhere and can't be cancelled.
+    pub(crate) fn silent() -> Tracker<'static> {
+        Tracker {
+            progress: Progress::default(),
+            report: None,
+            cancel: None,
+        }
+    }
+
+    pub(crate) fn new(
+        report: &'a mut dyn FnMut(&Progress),
+        cancel: &'a CancelToken,
+    ) -> Tracker<'a> {
+        Tracker {
+            progress: Progress::default(),
+            report: Some(report),
+            cancel: Some(cancel),
+        }
+    }
+
+    pub(crate) fn set_totals(&mut self, bytes_total: u64, files_total: u64) {
+        self.progress.bytes_total = bytes_total;
+        self.progress.files_total = files_total;
+        self.emit();
+    }
+
+    /// Fail with `Interrupted` if the operation has been cancelled.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        match self.cancel {
+            Some(token) if token.is_cancelled() => {
+                Err(Error::new(ErrorKind::Interrupted, "operation cancelled"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn add_bytes(&mut self, bytes: u64) -> Result<(), Error> {
+        self.progress.bytes_done += bytes;
+        self.emit();
+        self.check()
+    }
+
+    pub(crate) fn finish_entry(&mut self) -> Result<(), Error> {
+        self.progress.files_done += 1;
+        self.emit();
+        self.check()
+    }
+
+    fn emit(&mut self) {
+        if let Some(report) = self.report.as_mut() {
+            report(&self.progress);
+        }
+    }
+}
+
+/// Copy a file like [`crate::copy_file_with`], reporting progress and
+/// stopping early if `cancel` fires.
+///
+/// `on_progress` is called after every chunk. A cancelled copy removes the
+/// partially written destination if the copy created it; a destination
+/// that was already there is left to the strategy, which with
+/// [`CopyStrategy::Reflink`] or [`CopyStrategy::CopyFileRange`] keeps it
+/// untouched. To feed a channel instead of a callback, send from inside the
+/// closure.
+///
+/// Examples
+/// ```
+/// use soil::{copy_file_with_progress, write_file, remove_file, CancelToken, CopyStrategy};
+/// use std::sync::mpsc;
+/// write_file("progress_src.bin", vec![7u8; 1 << 20]).unwrap();
+///
+/// let (sender, receiver) = mpsc::channel();
+/// copy_file_with_progress(
+///     "progress_src.bin",
+///     "progress_dst.bin",
+///     CopyStrategy::Userspace,
+///     &CancelToken::new(),
+///     |progress| sender.send(*progress).unwrap(),
+/// )
+/// .unwrap();
+/// let last = receiver.try_iter().last().unwrap();
+/// assert_eq!(last.bytes_done, 1 << 20);
+/// assert_eq!(last.files_done, 1);
+/// remove_file("progress_src.bin").unwrap();
+/// remove_file("progress_dst.bin").unwrap();
+/// ```
+pub fn copy_file_with_progress<P1, P2, F>(
+    src: P1,
+    dst: P2,
+    strategy: CopyStrategy,
+    cancel: &CancelToken,
+    mut on_progress: F,
+) -> Result<CopyStrategy, Error>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    F: FnMut(&Progress),
+{
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let mut tracker = Tracker::new(&mut on_progress, cancel);
+    tracker.set_totals(fs::metadata(src)?.len(), 1);
+    tracker.check()?;
+
+    let existed = fs::symlink_metadata(dst).is_ok();
+    match copy_file_tracked(src, dst, strategy, true, &Backup::None, &mut tracker) {
+        Ok(used) => {
+            tracker.finish_entry()?;
+            Ok(used)
+        }
+        Err(error) => {
+            if error.kind() == ErrorKind::Interrupted && !existed {
+                discard(dst);
+            }
+            Err(error)
+        }
+    }
+}
+
+impl CopyOptions {
+    /// [`CopyOptions::copy`], reporting progress and stopping between chunks
+    /// if `cancel` fires, like [`copy_file_with_progress`].
+    ///
+    /// Examples
+    /// ```
+    /// use soil::{write_file, remove_file, CancelToken, CopyOptions};
+    /// write_file("progress_options_src.bin", vec![1u8; 4096]).unwrap();
+    /// let mut done = 0;
+    /// CopyOptions::archive()
+    ///     .copy_with_progress(
+    ///         "progress_options_src.bin",
+    ///         "progress_options_dst.bin",
+    ///         &CancelToken::new(),
+    ///         |progress| done = progress.bytes_done,
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(done, 4096);
+    /// remove_file("progress_options_src.bin").unwrap();
+    /// remove_file("progress_options_dst.bin").unwrap();
+    /// ```
+    pub fn copy_with_progress<P1, P2, F>(
+        &self,
+        src: P1,
+        dst: P2,
+        cancel: &CancelToken,
+        mut on_progress: F,
+    ) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        F: FnMut(&Progress),
+    {
+        let (src, dst) = (src.as_ref(), dst.as_ref());
+        let mut tracker = Tracker::new(&mut on_progress, cancel);
+        tracker.set_totals(fs::metadata(src).map_or(0, |metadata| metadata.len()), 1);
+        tracker.check()?;
+
+        let existed = fs::symlink_metadata(dst).is_ok();
+        match self.copy_tracked(src, dst, &mut tracker) {
+            Ok(()) => tracker.finish_entry(),
+            Err(error) => {
+                if error.kind() == ErrorKind::Interrupted && !existed {
+                    discard(dst);
+                }
+                Err(error)
+            }
+        }
+    }
+}
+
+/// Remove a directory tree like [`crate::remove_dir_all`], reporting progress
+/// and stopping between entries if `cancel` fires.
+///
+/// The tree is measured first so the totals are known up front. A cancelled
+/// removal leaves whatever has not been deleted yet in place.
+///
+/// Examples
+/// ```
+/// use soil::{remove_dir_all_with_progress, ensure_dir, write_file, exists, CancelToken};
+/// ensure_dir("progress_rm/nested").unwrap();
+/// write_file("progress_rm/a.txt", "aaaa").unwrap();
+/// write_file("progress_rm/nested/b.txt", "bb").unwrap();
+///
+/// let mut last = None;
+/// remove_dir_all_with_progress("progress_rm", &CancelToken::new(), |p| last = Some(*p)).unwrap();
+/// let last = last.unwrap();
+/// assert_eq!((last.bytes_done, last.bytes_total), (6, 6));
+/// assert_eq!((last.files_done, last.files_total), (4, 4));
+/// assert!(!exists("progress_rm"));
+/// ```
+pub fn remove_dir_all_with_progress<P, F>(
+    path: P,
+    cancel: &CancelToken,
+    mut on_progress: F,
+) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Progress),
+{
+    let path = path.as_ref();
+    let mut tracker = Tracker::new(&mut on_progress, cancel);
+    let (bytes, entries) = measure_tree(path, &tracker)?;
+    tracker.set_totals(bytes, entries);
+    remove_tree(path, &mut tracker)
+}
+
+/// Move or rename a path like [`crate::move_path`], reporting progress.
+///
+/// A rename within one filesystem is instant and reports a single finished
+/// entry. Across filesystems the tree is copied as by
+/// [`CopyOptions::archive`], keeping modes, ownership and times (with
+/// progress and cancellation), and the source removed afterwards; a copy
+/// that fails or is cancelled removes what it created at `to` and leaves the
+/// source untouched.
+///
+/// Examples
+/// ```
+/// use soil::{move_path_with_progress, write_file, exists, remove_file, CancelToken};
+/// write_file("progress_mv_src.txt", "content").unwrap();
+/// move_path_with_progress("progress_mv_src.txt", "progress_mv_dst.txt", &CancelToken::new(), |_| {}).unwrap();
+/// assert!(!exists("progress_mv_src.txt"));
+/// assert!(exists("progress_mv_dst.txt"));
+/// remove_file("progress_mv_dst.txt").unwrap();
+/// ```
+pub fn move_path_with_progress<P1, P2, F>(
+    from: P1,
+    to: P2,
+    cancel: &CancelToken,
+    mut on_progress: F,
+) -> Result<(), Error>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    F: FnMut(&Progress),
+{
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let mut tracker = Tracker::new(&mut on_progress, cancel);
+    tracker.check()?;
+
+    match crate::move_path(from, to) {
+        Ok(()) => {
+            tracker.set_totals(0, 1);
+            return tracker.finish_entry();
+        }
+        Err(error) if error.kind() == ErrorKind::CrossesDevices => {}
+        Err(error) => return Err(error),
+    }
+
+    let (bytes, entries) = measure_tree(from, &tracker)?;
+    tracker.set_totals(bytes, entries);
+
+    let is_dir = fs::symlink_metadata(from)?.is_dir();
+    let existed = fs::symlink_metadata(to).is_ok();
+    if let Err(error) = copy_tree(from, to, &CopyOptions::archive(), &mut tracker) {
+        if !existed {
+            discard(to);
+        }
+        return Err(error);
+    }
+
+    if is_dir {
+        crate::remove_dir_all(from)
+    } else {
+        crate::remove_file(from)
+    }
+}
+
+/// Remove what a failed copy left at `path`, as best it can.
+fn discard(path: &Path) {
+    let _ = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => crate::remove_dir_all(path),
+        Ok(_) => crate::remove_file(path),
+        Err(_) => Ok(()),
+    };
+}
+
+/// Total file bytes and entry count under `path`, without following links.
+fn measure_tree(path: &Path, tracker: &Tracker) -> Result<(u64, u64), Error> {
+    tracker.check()?;
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        let bytes = if metadata.is_file() {
+            metadata.len()
+        } else {
+            0
+        };
+        return Ok((bytes, 1));
+    }
+
+    let (mut bytes, mut entries) = (0, 1);
+    for entry in fs::read_dir(path)? {
+        let (entry_bytes, entry_count) = measure_tree(&entry?.path(), tracker)?;
+        bytes += entry_bytes;
+        entries += entry_count;
+    }
+    Ok((bytes, entries))
+}
+
+fn remove_tree(path: &Path, tracker: &mut Tracker) -> Result<(), Error> {
+    tracker.check()?;
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            remove_tree(&entry?.path(), tracker)?;
+        }
+        crate::remove_empty_dir(path)?;
+    } else {
+        crate::remove_file(path)?;
+        if metadata.is_file() {
+            tracker.add_bytes(metadata.len())?;
+        }
+    }
+    tracker.finish_entry()
+}
+
+/// Copy the tree at `from` to `to` with `options`, applying a directory's
+/// metadata once its entries are in place.
+fn copy_tree(
+    from: &Path,
+    to: &Path,
+    options: &CopyOptions,
+    tracker: &mut Tracker,
+) -> Result<(), Error> {
+    tracker.check()?;
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_dir() {
+        crate::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()), options, tracker)?;
+        }
+        options.copy_metadata(from, to, &metadata)?;
+    } else {
+        options.copy_tracked(from, to, tracker)?;
+    }
+    tracker.finish_entry()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_copy_reports_monotonic_progress() {
+        let guard = setup_test();
+        let src = &*format!("{}/progress_src.bin", guard.test_root);
+        let dst = &*format!("{}/progress_dst.bin", guard.test_root);
+        fs::write(src, vec![1u8; 3 << 20]).unwrap();
+
+        let mut reports = Vec::new();
+        copy_file_with_progress(
+            src,
+            dst,
+            CopyStrategy::Userspace,
+            &CancelToken::new(),
+            |p| reports.push(*p),
+        )
+        .unwrap();
+
+        assert!(reports.len() > 2);
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].bytes_done <= w[1].bytes_done)
+        );
+        let last = reports.last().unwrap();
+        assert_eq!(last.bytes_done, 3 << 20);
+        assert_eq!(last.bytes_total, 3 << 20);
+        assert_eq!(last.fraction(), 1.0);
+    }
+
+    #[test]
+    fn test_cancelled_copy_removes_destination() {
+        let guard = setup_test();
+        let src = &*format!("{}/cancel_src.bin", guard.test_root);
+        let dst = &*format!("{}/cancel_dst.bin", guard.test_root);
+        fs::write(src, vec![1u8; 4 << 20]).unwrap();
+
+        let token = CancelToken::new();
+        let error = copy_file_with_progress(src, dst, CopyStrategy::Userspace, &token, |p| {
+            if p.bytes_done > 0 {
+                token.cancel();
+            }
+        })
+        .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert!(!Path::new(dst).exists());
+        assert!(Path::new(src).exists());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_cancelled_strict_copy_keeps_existing_destination() {
+        let guard = setup_test();
+        let src = &*format!("{}/strict_src.bin", guard.test_root);
+        let dst = &*format!("{}/strict_dst.bin", guard.test_root);
+        fs::write(src, vec![1u8; 4 << 20]).unwrap();
+        fs::write(dst, "keep me").unwrap();
+
+        let token = CancelToken::new();
+        let error = copy_file_with_progress(src, dst, CopyStrategy::CopyFileRange, &token, |p| {
+            if p.bytes_done > 0 {
+                token.cancel();
+            }
+        })
+        .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert_eq!(fs::read_to_string(dst).unwrap(), "keep me");
+        assert_eq!(fs::read_dir(&guard.test_root).unwrap().count(), 2);
+
+        let token = CancelToken::new();
+        let error = CopyOptions::new()
+            .strategy(CopyStrategy::CopyFileRange)
+            .copy_with_progress(src, dst, &token, |p| {
+                if p.bytes_done > 0 {
+                    token.cancel();
+                }
+            })
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert_eq!(fs::read_to_string(dst).unwrap(), "keep me");
+    }
+
+    #[test]
+    fn test_cancelled_removal_stops_early() {
+        let guard = setup_test();
+        let root = &*format!("{}/cancel_rm", guard.test_root);
+        fs::create_dir_all(root).unwrap();
+        for index in 0..20 {
+            fs::write(format!("{}/file{}.txt", root, index), "content").unwrap();
+        }
+
+        let token = CancelToken::new();
+        let error = remove_dir_all_with_progress(root, &token, |p| {
+            if p.files_done == 5 {
+                token.cancel();
+            }
+        })
+        .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert_eq!(fs::read_dir(root).unwrap().count(), 15);
+    }
+
+    #[test]
+    fn test_copy_tree_copies_nested_entries() {
+        let guard = setup_test();
+        let from = &*format!("{}/tree_from", guard.test_root);
+        let to = &*format!("{}/tree_to", guard.test_root);
+        fs::create_dir_all(format!("{}/a/b", from)).unwrap();
+        fs::write(format!("{}/a/b/deep.txt", from), "deep").unwrap();
+        fs::write(format!("{}/top.txt", from), "top").unwrap();
+
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        crate::set_times(format!("{}/a/b/deep.txt", from), old, old).unwrap();
+        crate::set_times(format!("{}/a", from), old, old).unwrap();
+
+        let mut tracker = Tracker::silent();
+        let options = CopyOptions::archive();
+        copy_tree(Path::new(from), Path::new(to), &options, &mut tracker).unwrap();
+        assert_eq!(
+            fs::read_to_string(format!("{}/a/b/deep.txt", to)).unwrap(),
+            "deep"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}/top.txt", to)).unwrap(),
+            "top"
+        );
+        let modified = |path: String| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified(format!("{}/a/b/deep.txt", to)), old);
+        assert_eq!(modified(format!("{}/a", to)), old);
+    }
+}
@@ -0,0 +1,208 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::trace::TraceLevel::All;
+use crate::trace::traced;
+
+/// Read a file line by line without loading it into memory.
+///
+/// Lines are yielded without their `\n` or `\r\n` terminator. A line that is
+/// not valid UTF-8 yields an `ErrorKind::InvalidData` error naming its line
+/// number, and reading carries on with the next line.
+///
+/// Examples
+/// ```
+/// use soil::{read_lines, write_file, remove_file};
+/// write_file("read_lines_example.txt", b"one\r\ntwo\n\xff\nfour").unwrap();
+///
+/// let lines: Vec<_> = read_lines("read_lines_example.txt").unwrap().collect();
+/// assert_eq!(lines.len(), 4);
+/// assert_eq!(lines[0].as_ref().unwrap(), "one");
+/// assert_eq!(lines[1].as_ref().unwrap(), "two");
+/// assert!(lines[2].is_err());
+/// assert_eq!(lines[3].as_ref().unwrap(), "four");
+/// remove_file("read_lines_example.txt").unwrap();
+/// ```
+pub fn read_lines<P: AsRef<Path>>(path: P) -> Result<Lines, Error> {
+    Ok(Lines {
+        reader: BufReader::new(open(path.as_ref())?),
+        line_number: 0,
+        buffer: Vec::new(),
+    })
+}
+
+/// Iterator over the lines of a file, from [`read_lines`].
+pub struct Lines {
+    reader: BufReader<File>,
+    line_number: usize,
+    buffer: Vec<u8>,
+}
+
+impl Iterator for Lines {
+    type Item = Result<String, Error>;
+
+    fn next(&mut self) -> Option<Result<String, Error>> {
+        self.buffer.clear();
+        match self.reader.read_until(b'\n', &mut self.buffer) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(error)),
+        }
+        self.line_number += 1;
+        if self.buffer.ends_with(b"\n") {
+            self.buffer.pop();
+            if self.buffer.ends_with(b"\r") {
+                self.buffer.pop();
+            }
+        }
+        Some(
+            String::from_utf8(std::mem::take(&mut self.buffer)).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("line {}: invalid UTF-8", self.line_number),
+                )
+            }),
+        )
+    }
+}
+
+/// Read a file in pieces of `size` bytes; only the last one may be shorter.
+///
+/// Fails with `ErrorKind::InvalidInput` when `size` is zero.
+///
+/// Examples
+/// ```
+/// use soil::{read_chunks, write_file, remove_file};
+/// write_file("read_chunks_example.txt", "abcdefg").unwrap();
+///
+/// let chunks: Vec<_> = read_chunks("read_chunks_example.txt", 3)
+///     .unwrap()
+///     .map(Result::unwrap)
+///     .collect();
+/// assert_eq!(chunks, [b"abc".to_vec(), b"def".to_vec(), b"g".to_vec()]);
+/// remove_file("read_chunks_example.txt").unwrap();
+/// ```
+pub fn read_chunks<P: AsRef<Path>>(path: P, size: usize) -> Result<Chunks, Error> {
+    if size == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "chunk size must be greater than zero",
+        ));
+    }
+    Ok(Chunks {
+        file: open(path.as_ref())?,
+        size,
+        done: false,
+    })
+}
+
+/// Iterator over fixed-size pieces of a file, from [`read_chunks`].
+pub struct Chunks {
+    file: File,
+    size: usize,
+    done: bool,
+}
+
+impl Iterator for Chunks {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>, Error>> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = Vec::with_capacity(self.size);
+        match (&mut self.file)
+            .take(self.size as u64)
+            .read_to_end(&mut chunk)
+        {
+            Ok(0) => {
+                self.done = true;
+                None
+            }
+            Ok(read) => {
+                self.done = read < self.size;
+                Some(Ok(chunk))
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Read up to `len` bytes starting at byte `offset`.
+///
+/// The result is shorter than `len` when the file ends first, and empty when
+/// `offset` is past the end.
+///
+/// Examples
+/// ```
+/// use soil::{read_range, write_file, remove_file};
+/// write_file("read_range_example.txt", "0123456789").unwrap();
+/// assert_eq!(read_range("read_range_example.txt", 3, 4).unwrap(), b"3456");
+/// assert_eq!(read_range("read_range_example.txt", 8, 100).unwrap(), b"89");
+/// assert!(read_range("read_range_example.txt", 20, 1).unwrap().is_empty());
+/// remove_file("read_range_example.txt").unwrap();
+/// ```
+pub fn read_range<P: AsRef<Path>>(path: P, offset: u64, len: u64) -> Result<Vec<u8>, Error> {
+    let mut file = open(path.as_ref())?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Open `path` for reading.
+fn open(path: &Path) -> Result<File, Error> {
+    traced(All, format_args!("open({:?})", path), File::open(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::write_file;
+
+    #[test]
+    fn test_read_lines_terminators() {
+        let guard = setup_test();
+        let file = format!("{}/lines.txt", guard.test_root);
+        write_file(&file, "a\r\n\nb\r\nc\r").unwrap();
+
+        let lines: Vec<_> = read_lines(&file).unwrap().map(Result::unwrap).collect();
+        assert_eq!(lines, ["a", "", "b", "c\r"]);
+
+        write_file(&file, "").unwrap();
+        assert_eq!(read_lines(&file).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_read_lines_invalid_utf8() {
+        let guard = setup_test();
+        let file = format!("{}/mixed.txt", guard.test_root);
+        write_file(&file, b"ok\n\xc3\x28\nfine\n").unwrap();
+
+        let lines: Vec<_> = read_lines(&file).unwrap().collect();
+        let error = lines[1].as_ref().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 2"));
+        assert_eq!(lines[2].as_ref().unwrap(), "fine");
+    }
+
+    #[test]
+    fn test_read_chunks_exact_multiple() {
+        let guard = setup_test();
+        let file = format!("{}/chunks.bin", guard.test_root);
+        write_file(&file, [7u8; 8]).unwrap();
+
+        let sizes: Vec<_> = read_chunks(&file, 4)
+            .unwrap()
+            .map(|chunk| chunk.unwrap().len())
+            .collect();
+        assert_eq!(sizes, [4, 4]);
+        assert!(read_chunks(&file, 0).is_err());
+        assert!(read_chunks(format!("{}/missing", guard.test_root), 4).is_err());
+    }
+}
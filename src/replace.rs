@@ -0,0 +1,359 @@
+use std::fs::{self, File};
+use std::io::{Error, Write};
+use std::path::{Path, PathBuf};
+
+use regex::NoExpand;
+
+use crate::backup::Backup;
+use crate::diff::unified_diff;
+use crate::find::{EntryType, find};
+use crate::glob::Pattern;
+use crate::search::{build_regex, looks_binary};
+use crate::trace::TraceLevel::Changes;
+use crate::trace::traced;
+use crate::walk::BulkReport;
+
+/// Lines of unchanged context around each change in the preview diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// Settings for [`replace_in_files`].
+///
+/// Include and exclude globs are matched against the file name, or against
+/// the path relative to the root when the glob contains a `/` (use `**` to
+/// cross directories there). A file is
+/// considered when it matches any include (or there are none) and no
+/// exclude.
+///
+/// Examples
+/// ```
+/// use soil::ReplaceOptions;
+/// let options = ReplaceOptions::new()
+///     .include("*.toml")
+///     .exclude("target/**")
+///     .dry_run(true);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ReplaceOptions {
+    literal: bool,
+    case_insensitive: bool,
+    ignore: bool,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    dry_run: bool,
+    backup: Backup,
+}
+
+impl ReplaceOptions {
+    /// Regex pattern with `$1`/`${name}` expansion, every text file, writes enabled.
+    pub fn new() -> ReplaceOptions {
+        ReplaceOptions::default()
+    }
+
+    /// Treat the pattern and the replacement as plain text.
+    pub fn literal(mut self, literal: bool) -> ReplaceOptions {
+        self.literal = literal;
+        self
+    }
+
+    /// Ignore case when matching.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> ReplaceOptions {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Skip files excluded by ignore files (see [`IgnoreRules`](crate::IgnoreRules)).
+    pub fn ignore(mut self, ignore: bool) -> ReplaceOptions {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Only touch files matching this glob. May be given several times.
+    pub fn include(mut self, glob: &str) -> ReplaceOptions {
+        self.include.push(Pattern::new(glob));
+        self
+    }
+
+    /// Never touch files matching this glob. May be given several times.
+    pub fn exclude(mut self, glob: &str) -> ReplaceOptions {
+        self.exclude.push(Pattern::new(glob));
+        self
+    }
+
+    /// Compute the changes and diffs without writing anything.
+    pub fn dry_run(mut self, dry_run: bool) -> ReplaceOptions {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Back up every file before rewriting it (see [`Backup`]).
+    pub fn backup(mut self, backup: Backup) -> ReplaceOptions {
+        self.backup = backup;
+        self
+    }
+
+    fn selects(&self, relative: &Path) -> bool {
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let relative = relative.to_string_lossy();
+        let hit = |pattern: &Pattern| {
+            if pattern.as_str().contains('/') {
+                pattern.matches_path(&relative)
+            } else {
+                pattern.matches(&name)
+            }
+        };
+        (self.include.is_empty() || self.include.iter().any(hit)) && !self.exclude.iter().any(hit)
+    }
+}
+
+/// What [`replace_in_files`] did to one file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replacement {
+    /// Number of matches replaced.
+    pub count: usize,
+    /// Unified diff from the old contents to the new ones.
+    pub diff: String,
+}
+
+/// Replace every match of `pattern` in the text files under `root`.
+///
+/// The replacement may refer to capture groups as `$1` or `${name}` (use
+/// `$$` for a literal dollar) unless the options ask for literal text.
+/// Binary and non-UTF-8 files are skipped, as are files without a match.
+///
+/// Each file is written to a temporary sibling and renamed over the
+/// original, so a failure never leaves a half-written file; the original
+/// permissions are kept. The report lists every changed file with its diff,
+/// and the files that could not be read or written. With
+/// [`ReplaceOptions::dry_run`] nothing is written.
+///
+/// Examples
+/// ```
+/// use soil::{replace_in_files, write_file, read_text, ensure_dir, remove_dir_all, ReplaceOptions};
+/// ensure_dir("replace_example").unwrap();
+/// write_file("replace_example/Cargo.toml", "version = \"1.2.3\"\n").unwrap();
+///
+/// let report = replace_in_files(
+///     "replace_example",
+///     r#"version = "(\d+)\.(\d+)\.\d+""#,
+///     r#"version = "$1.${2}.4""#,
+///     ReplaceOptions::new().include("*.toml"),
+/// )
+/// .unwrap();
+/// assert_eq!(report.done.len(), 1);
+/// assert_eq!(report.done[0].1.count, 1);
+/// assert_eq!(read_text("replace_example/Cargo.toml").unwrap(), "version = \"1.2.4\"\n");
+/// remove_dir_all("replace_example").unwrap();
+/// ```
+pub fn replace_in_files<P: AsRef<Path>>(
+    root: P,
+    pattern: &str,
+    replacement: &str,
+    options: ReplaceOptions,
+) -> Result<BulkReport<Replacement>, Error> {
+    let root = root.as_ref();
+    let regex = build_regex(pattern, options.literal, options.case_insensitive)?;
+    let mut report = BulkReport {
+        done: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    for entry in find(root).file_type(EntryType::File).ignore(options.ignore) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                report.failed.push((root.to_path_buf(), error));
+                continue;
+            }
+        };
+        let relative = match entry.path.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+            _ => PathBuf::from(entry.path.file_name().unwrap_or_default()),
+        };
+        if !options.selects(&relative) {
+            continue;
+        }
+
+        let bytes = match fs::read(&entry.path) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                report.failed.push((entry.path, error));
+                continue;
+            }
+        };
+        if looks_binary(&bytes) {
+            continue;
+        }
+        let Ok(old) = String::from_utf8(bytes) else {
+            continue;
+        };
+
+        let count = regex.find_iter(&old).count();
+        if count == 0 {
+            continue;
+        }
+        let new = if options.literal {
+            regex.replace_all(&old, NoExpand(replacement))
+        } else {
+            regex.replace_all(&old, replacement)
+        };
+        let label = relative.to_string_lossy();
+        let diff = unified_diff(
+            &old,
+            &new,
+            &format!("a/{}", label),
+            &format!("b/{}", label),
+            DIFF_CONTEXT,
+        );
+
+        if !options.dry_run
+            && let Err(error) = options
+                .backup
+                .backup(&entry.path)
+                .and_then(|_| write_atomic(&entry.path, new.as_bytes()))
+        {
+            report.failed.push((entry.path, error));
+            continue;
+        }
+        report.done.push((entry.path, Replacement { count, diff }));
+    }
+    Ok(report)
+}
+
+/// Replace the contents of `path` by writing a temporary sibling, syncing it
+/// and renaming it into place, keeping the original permissions.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(format!(".soil-replace-{}", std::process::id()));
+
+    let result = (|| {
+        let permissions = fs::metadata(path)?.permissions();
+        let create = File::create(&staging);
+        let mut file = traced(
+            Changes,
+            format_args!("open({:?}, O_WRONLY|O_CREAT|O_TRUNC)", staging),
+            create,
+        )?;
+        file.write_all(contents)?;
+        file.set_permissions(permissions)?;
+        file.sync_all()?;
+        crate::move_path(&staging, path)
+    })();
+    if result.is_err() {
+        let _ = crate::remove_file(&staging);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{ensure_dir, read_text, write_file};
+
+    fn build_tree(root: &str) {
+        ensure_dir(format!("{}/src/util", root)).unwrap();
+        ensure_dir(format!("{}/target", root)).unwrap();
+        write_file(
+            format!("{}/src/lib.rs", root),
+            "use old_name::Thing;\nfn f() {}\n",
+        )
+        .unwrap();
+        write_file(format!("{}/src/util/mod.rs", root), "// old_name here\n").unwrap();
+        write_file(format!("{}/target/gen.rs", root), "old_name\n").unwrap();
+        write_file(format!("{}/README.md", root), "old_name docs\n").unwrap();
+        write_file(format!("{}/blob.bin", root), b"old_name\0").unwrap();
+    }
+
+    #[test]
+    fn test_replace_with_globs() {
+        let guard = setup_test();
+        let root = guard.test_root.clone();
+        build_tree(&root);
+
+        let options = ReplaceOptions::new().include("*.rs").exclude("target/**");
+        let report = replace_in_files(&root, "old_name", "new_name", options).unwrap();
+        assert!(report.is_success());
+        let changed: Vec<_> = report.done.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(changed.len(), 2);
+        assert!(changed[0].ends_with("src/lib.rs"));
+        assert!(changed[1].ends_with("src/util/mod.rs"));
+
+        assert_eq!(
+            read_text(format!("{}/src/lib.rs", root)).unwrap(),
+            "use new_name::Thing;\nfn f() {}\n"
+        );
+        assert_eq!(
+            read_text(format!("{}/target/gen.rs", root)).unwrap(),
+            "old_name\n"
+        );
+        assert_eq!(
+            read_text(format!("{}/README.md", root)).unwrap(),
+            "old_name docs\n"
+        );
+        assert_eq!(
+            fs::read(format!("{}/blob.bin", root)).unwrap(),
+            b"old_name\0"
+        );
+    }
+
+    #[test]
+    fn test_replace_dry_run_diff() {
+        let guard = setup_test();
+        let root = guard.test_root.clone();
+        build_tree(&root);
+
+        let options = ReplaceOptions::new().include("lib.rs").dry_run(true);
+        let report = replace_in_files(&root, r"old_(\w+)", "new_$1", options).unwrap();
+        assert_eq!(report.done.len(), 1);
+        let (_, replacement) = &report.done[0];
+        assert_eq!(replacement.count, 1);
+        assert_eq!(
+            replacement.diff,
+            "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,2 @@\n\
+             -use old_name::Thing;\n+use new_name::Thing;\n fn f() {}\n"
+        );
+        assert_eq!(
+            read_text(format!("{}/src/lib.rs", root)).unwrap(),
+            "use old_name::Thing;\nfn f() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_replace_literal_and_case() {
+        let guard = setup_test();
+        let file = format!("{}/prices.txt", guard.test_root);
+        write_file(&file, "Cost: $1 (USD), cost: $1 (usd)\n").unwrap();
+
+        let options = ReplaceOptions::new().literal(true).case_insensitive(true);
+        let report = replace_in_files(&file, "$1 (usd)", "$2 (USD)", options).unwrap();
+        assert_eq!(report.done[0].1.count, 2);
+        assert_eq!(
+            read_text(&file).unwrap(),
+            "Cost: $2 (USD), cost: $2 (USD)\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let guard = setup_test();
+        let file = format!("{}/run.sh", guard.test_root);
+        write_file(&file, "echo old\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o750)).unwrap();
+
+        replace_in_files(&file, "old", "new", ReplaceOptions::new()).unwrap();
+        assert_eq!(read_text(&file).unwrap(), "echo new\n");
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[test]
+    fn test_replace_invalid_pattern() {
+        assert!(replace_in_files(".", "(", "x", ReplaceOptions::new()).is_err());
+    }
+}
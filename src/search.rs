@@ -0,0 +1,382 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::find::{EntryType, FindIter, find};
+
+/// How much of a file is inspected for NUL bytes to decide it is binary.
+const BINARY_PROBE: usize = 8 * 1024;
+
+/// Settings for [`search`].
+///
+/// Examples
+/// ```
+/// use soil::SearchOptions;
+/// let options = SearchOptions::new()
+///     .literal(true)
+///     .case_insensitive(true)
+///     .context(2)
+///     .max_matches(10);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    literal: bool,
+    case_insensitive: bool,
+    ignore: bool,
+    include_binary: bool,
+    before: usize,
+    after: usize,
+    max_matches: Option<usize>,
+}
+
+impl SearchOptions {
+    /// Regex search, case-sensitive, binary files skipped, no context.
+    pub fn new() -> SearchOptions {
+        SearchOptions::default()
+    }
+
+    /// Treat the pattern as plain text rather than a regular expression.
+    pub fn literal(mut self, literal: bool) -> SearchOptions {
+        self.literal = literal;
+        self
+    }
+
+    /// Ignore case when matching.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> SearchOptions {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Skip files excluded by ignore files (see [`IgnoreRules`](crate::IgnoreRules)).
+    pub fn ignore(mut self, ignore: bool) -> SearchOptions {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Also search files that look binary (contain a NUL byte near the start).
+    pub fn include_binary(mut self, include_binary: bool) -> SearchOptions {
+        self.include_binary = include_binary;
+        self
+    }
+
+    /// Keep this many lines before and after each match.
+    pub fn context(self, lines: usize) -> SearchOptions {
+        self.before(lines).after(lines)
+    }
+
+    /// Keep this many lines before each match.
+    pub fn before(mut self, lines: usize) -> SearchOptions {
+        self.before = lines;
+        self
+    }
+
+    /// Keep this many lines after each match.
+    pub fn after(mut self, lines: usize) -> SearchOptions {
+        self.after = lines;
+        self
+    }
+
+    /// Stop reading a file after this many matching lines.
+    pub fn max_matches(mut self, max: usize) -> SearchOptions {
+        self.max_matches = Some(max);
+        self
+    }
+}
+
+/// A line that matched a [`search`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    /// The file the line came from.
+    pub path: PathBuf,
+    /// 1-based line number.
+    pub line_number: usize,
+    /// 1-based column, in characters, of the first match on the line.
+    pub column: usize,
+    /// The line without its terminator. Invalid UTF-8 is replaced with `�`.
+    pub line: String,
+    /// Byte ranges of every match within `line`.
+    pub ranges: Vec<Range<usize>>,
+    /// Up to the requested number of lines preceding this one.
+    pub before: Vec<String>,
+    /// Up to the requested number of lines following this one.
+    pub after: Vec<String>,
+}
+
+/// Search every file under `root` (or `root` itself if it is a file) for
+/// lines matching `pattern`.
+///
+/// Files are read one at a time in the same order as [`find`] and matches
+/// are yielded as each file is finished, so large trees stream. A file that
+/// cannot be read yields an `Err` and the search moves on. An invalid
+/// regular expression fails up front with `ErrorKind::InvalidInput`.
+///
+/// Examples
+/// ```
+/// use soil::{search, write_file, ensure_dir, remove_dir_all, SearchOptions};
+/// ensure_dir("search_example").unwrap();
+/// write_file("search_example/notes.txt", "alpha\nTODO: beta\ngamma\n").unwrap();
+///
+/// let options = SearchOptions::new().case_insensitive(true).context(1);
+/// let hits: Vec<_> = search("search_example", "todo", options)
+///     .unwrap()
+///     .map(Result::unwrap)
+///     .collect();
+/// assert_eq!(hits.len(), 1);
+/// assert_eq!((hits[0].line_number, hits[0].column), (2, 1));
+/// assert_eq!(hits[0].before, ["alpha"]);
+/// assert_eq!(hits[0].after, ["gamma"]);
+/// remove_dir_all("search_example").unwrap();
+/// ```
+pub fn search<P: AsRef<Path>>(
+    root: P,
+    pattern: &str,
+    options: SearchOptions,
+) -> Result<Search, Error> {
+    Ok(Search {
+        files: find(root)
+            .file_type(EntryType::File)
+            .ignore(options.ignore)
+            .into_iter(),
+        regex: build_regex(pattern, options.literal, options.case_insensitive)?,
+        options,
+        ready: VecDeque::new(),
+    })
+}
+
+/// Compile a search pattern, escaping it first when `literal`.
+pub(crate) fn build_regex(
+    pattern: &str,
+    literal: bool,
+    case_insensitive: bool,
+) -> Result<Regex, Error> {
+    let source = if literal {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))
+}
+
+/// Whether the start of a file contains a NUL byte.
+pub(crate) fn looks_binary(head: &[u8]) -> bool {
+    head[..head.len().min(BINARY_PROBE)].contains(&0)
+}
+
+/// Iterator over the matches of a [`search`].
+pub struct Search {
+    files: FindIter,
+    regex: Regex,
+    options: SearchOptions,
+    ready: VecDeque<Match>,
+}
+
+impl Search {
+    fn search_file(&self, path: &Path) -> Result<Vec<Match>, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if !self.options.include_binary {
+            let probe = reader.fill_buf()?;
+            if looks_binary(probe) {
+                return Ok(Vec::new());
+            }
+        }
+
+        let mut matches: Vec<Match> = Vec::new();
+        let mut recent: VecDeque<String> = VecDeque::new();
+        let mut raw = Vec::new();
+        let mut line_number = 0;
+
+        loop {
+            raw.clear();
+            if reader.read_until(b'\n', &mut raw)? == 0 {
+                break;
+            }
+            line_number += 1;
+            if raw.ends_with(b"\n") {
+                raw.pop();
+                if raw.ends_with(b"\r") {
+                    raw.pop();
+                }
+            }
+            let line = String::from_utf8_lossy(&raw).into_owned();
+
+            for earlier in matches.iter_mut().rev() {
+                if earlier.after.len() >= self.options.after {
+                    break;
+                }
+                if line_number - earlier.line_number <= self.options.after {
+                    earlier.after.push(line.clone());
+                }
+            }
+
+            let limit_reached = self
+                .options
+                .max_matches
+                .is_some_and(|max| matches.len() >= max);
+            if limit_reached {
+                if matches
+                    .last()
+                    .is_none_or(|last| last.after.len() >= self.options.after)
+                {
+                    break;
+                }
+                continue;
+            }
+
+            let ranges: Vec<Range<usize>> = self
+                .regex
+                .find_iter(&line)
+                .map(|found| found.range())
+                .collect();
+            if let Some(first) = ranges.first() {
+                matches.push(Match {
+                    path: path.to_path_buf(),
+                    line_number,
+                    column: line[..first.start].chars().count() + 1,
+                    before: recent.iter().cloned().collect(),
+                    after: Vec::new(),
+                    line: line.clone(),
+                    ranges,
+                });
+            }
+
+            if self.options.before > 0 {
+                if recent.len() == self.options.before {
+                    recent.pop_front();
+                }
+                recent.push_back(line);
+            }
+        }
+        Ok(matches)
+    }
+}
+
+impl Iterator for Search {
+    type Item = Result<Match, Error>;
+
+    fn next(&mut self) -> Option<Result<Match, Error>> {
+        loop {
+            if let Some(found) = self.ready.pop_front() {
+                return Some(Ok(found));
+            }
+            let entry = match self.files.next()? {
+                Ok(entry) => entry,
+                Err(error) => return Some(Err(error)),
+            };
+            match self.search_file(&entry.path) {
+                Ok(matches) => self.ready.extend(matches),
+                Err(error) => {
+                    return Some(Err(Error::new(
+                        error.kind(),
+                        format!("{}: {}", entry.path.display(), error),
+                    )));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{ensure_dir, write_file};
+
+    fn run(root: &str, pattern: &str, options: SearchOptions) -> Vec<Match> {
+        search(root, pattern, options)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_search_regex_and_columns() {
+        let guard = setup_test();
+        let root = guard.test_root.clone();
+        ensure_dir(format!("{}/src", root)).unwrap();
+        write_file(
+            format!("{}/src/a.rs", root),
+            "fn main() {}\nlet x = foo(1) + foo(2);\n",
+        )
+        .unwrap();
+        write_file(format!("{}/src/b.rs", root), "// nothing here\r\nfoo\r\n").unwrap();
+
+        let hits = run(&root, r"foo\(\d\)", SearchOptions::new());
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].path.ends_with("src/a.rs"));
+        assert_eq!(hits[0].line_number, 2);
+        assert_eq!(hits[0].column, 9);
+        assert_eq!(hits[0].ranges, [8..14, 17..23]);
+
+        let hits = run(&root, "foo", SearchOptions::new());
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[1].line, "foo");
+        assert_eq!(hits[1].line_number, 2);
+
+        assert!(search(&root, "(", SearchOptions::new()).is_err());
+        assert_eq!(
+            run(&root, "foo(", SearchOptions::new().literal(true)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_search_case_and_unicode_column() {
+        let guard = setup_test();
+        let file = format!("{}/greek.txt", guard.test_root);
+        write_file(&file, "αβγ Hello\n").unwrap();
+
+        assert!(run(&file, "hello", SearchOptions::new()).is_empty());
+        let hits = run(&file, "hello", SearchOptions::new().case_insensitive(true));
+        assert_eq!(hits[0].column, 5);
+        assert_eq!(hits[0].ranges[0], 7..12);
+    }
+
+    #[test]
+    fn test_search_skips_binary() {
+        let guard = setup_test();
+        let file = format!("{}/blob.bin", guard.test_root);
+        write_file(&file, b"needle\0\x01\x02").unwrap();
+
+        assert!(run(&file, "needle", SearchOptions::new()).is_empty());
+        let hits = run(&file, "needle", SearchOptions::new().include_binary(true));
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[test]
+    fn test_search_context_and_limit() {
+        let guard = setup_test();
+        let file = format!("{}/lines.txt", guard.test_root);
+        let text: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        write_file(&file, text).unwrap();
+
+        let hits = run(&file, "line [37]$", SearchOptions::new().before(2).after(1));
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].before, ["line 1", "line 2"]);
+        assert_eq!(hits[0].after, ["line 4"]);
+        assert_eq!(hits[1].before, ["line 5", "line 6"]);
+        assert_eq!(hits[1].after, ["line 8"]);
+
+        let hits = run(&file, "line", SearchOptions::new().max_matches(3).after(2));
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[2].line_number, 3);
+        assert_eq!(hits[2].after, ["line 4", "line 5"]);
+
+        let hits = run(&file, "line 10", SearchOptions::new().context(3));
+        assert_eq!(hits[0].before.len(), 3);
+        assert!(hits[0].after.is_empty());
+    }
+
+    #[test]
+    fn test_search_missing_root() {
+        let mut results = search("/definitely/not/here", "x", SearchOptions::new()).unwrap();
+        assert!(results.next().unwrap().is_err());
+        assert!(results.next().is_none());
+    }
+}
@@ -0,0 +1,253 @@
+use clap::{CommandFactory, Parser, Subcommand, ValueHint};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+use serde_json::Value;
+use soil::expand;
+use std::io;
+use std::path::PathBuf;
+
+use crate::output::{EX_USAGE, Format, Status, exit_code, fail, output};
+use crate::undo::{LastChange, Stash, plan_undo, undo_last};
+use crate::{Commands, run};
+
+/// A line typed into `soil shell`: a soil command or one of the shell's own.
+#[derive(Parser)]
+#[command(name = "soil", no_binary_name = true)]
+pub(crate) struct ShellLine {
+    #[command(subcommand)]
+    pub(crate) command: ShellCommand,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum ShellCommand {
+    /// Change the working directory: to DIR, back to the previous one with
+    /// `-`, or home
+    Cd {
+        /// The directory to change to; `~` and `$NAME` are expanded
+        #[arg(value_hint = ValueHint::DirPath)]
+        dir: Option<String>,
+    },
+    /// Print the working directory
+    Pwd,
+    /// Print the commands typed so far
+    History,
+    /// Take back the last command that changed something, where possible
+    Undo,
+    /// Leave the shell
+    #[command(visible_alias = "quit")]
+    Exit,
+    #[command(flatten)]
+    Soil(Commands),
+}
+
+/// Tab completion for `soil shell`, by the engine behind `soil completions`.
+struct ShellHelper;
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before
+            .rfind(char::is_whitespace)
+            .map_or(0, |index| index + 1);
+        let mut args: Vec<std::ffi::OsString> =
+            before[..start].split_whitespace().map(Into::into).collect();
+        args.push(before[start..].into());
+        let index = args.len() - 1;
+        let current = std::env::current_dir().ok();
+        let candidates = clap_complete::engine::complete(
+            &mut ShellLine::command(),
+            args,
+            index,
+            current.as_deref(),
+        )
+        .unwrap_or_default();
+        let pairs = candidates
+            .iter()
+            .filter(|candidate| !candidate.is_hide_set())
+            .map(|candidate| {
+                let value = candidate.get_value().to_string_lossy();
+                let replacement =
+                    shlex::try_quote(&value).map_or_else(|_| value.to_string(), Into::into);
+                Pair {
+                    display: value.into_owned(),
+                    replacement,
+                }
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+pub(crate) fn run_shell(history: Option<PathBuf>) -> Status {
+    let out = output();
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor: Editor<ShellHelper, DefaultHistory> =
+        Editor::with_config(config).map_err(|error| {
+            out.error("starting the shell", None, &io::Error::other(error));
+            fail()
+        })?;
+    editor.set_helper(Some(ShellHelper));
+    let history = history
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".soil_history")));
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    let mut stash = Stash::new();
+    let mut last: Option<LastChange> = None;
+    let mut previous_dir = None;
+    let mut status = 0;
+    loop {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let prompt = match status {
+            0 => format!("soil {}> ", cwd.display()),
+            _ => format!("soil {} [{}]> ", cwd.display(), status),
+        };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                out.error("reading input", None, &io::Error::other(error));
+                break;
+            }
+        };
+        let Some(args) = shlex::split(&line) else {
+            eprintln!("soil: unbalanced quotes");
+            status = EX_USAGE;
+            continue;
+        };
+        if args.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+        let command = match ShellLine::try_parse_from(&args) {
+            Ok(parsed) => parsed.command,
+            Err(error) => {
+                let _ = error.print();
+                status = if error.use_stderr() { EX_USAGE } else { 0 };
+                continue;
+            }
+        };
+
+        status = match command {
+            ShellCommand::Exit => break,
+            ShellCommand::Pwd => {
+                println!("{}", cwd.display());
+                0
+            }
+            ShellCommand::Cd { dir } => change_dir(dir.as_deref(), &mut previous_dir),
+            ShellCommand::History => {
+                for (index, entry) in editor.history().iter().enumerate() {
+                    println!("{:>5}  {}", index + 1, entry);
+                }
+                0
+            }
+            ShellCommand::Undo => undo_last(last.take(), &stash),
+            ShellCommand::Soil(Commands::Shell { .. }) => {
+                eprintln!("soil: already in a shell");
+                EX_USAGE
+            }
+            ShellCommand::Soil(command) => {
+                let plan = plan_undo(&command, &mut stash);
+                let (status, records) = out.capture(|| run(command));
+                match out.format {
+                    Format::Text => {}
+                    Format::Json if !records.is_empty() => {
+                        println!("{:#}", Value::Array(records))
+                    }
+                    _ => records.iter().for_each(|record| println!("{}", record)),
+                }
+                match plan {
+                    Ok(None) => {}
+                    Ok(Some(steps)) if status != 0 => stash.discard(&steps),
+                    Err(_) if status != 0 => {}
+                    undo => {
+                        if let Some(LastChange {
+                            undo: Ok(steps), ..
+                        }) = &last
+                        {
+                            stash.discard(steps);
+                        }
+                        last = Some(LastChange {
+                            line,
+                            undo: undo.map(Option::unwrap_or_default),
+                        });
+                    }
+                }
+                status
+            }
+        };
+    }
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+    Ok(())
+}
+
+/// The shell's `cd`. Returns the exit status.
+fn change_dir(dir: Option<&str>, previous: &mut Option<PathBuf>) -> i32 {
+    let out = output();
+    let target = match dir {
+        Some("-") => match previous.clone() {
+            Some(previous) => previous,
+            None => {
+                eprintln!("soil: no previous directory");
+                return 1;
+            }
+        },
+        Some(dir) => match expand(dir) {
+            Ok(dir) => dir,
+            Err(error) => {
+                out.error("", Some(dir), &error);
+                return exit_code(error.kind());
+            }
+        },
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home),
+            None => {
+                eprintln!("soil: HOME is not set");
+                return 1;
+            }
+        },
+    };
+    let current = std::env::current_dir().ok();
+    match std::env::set_current_dir(&target) {
+        Ok(()) => {
+            *previous = current;
+            if dir == Some("-") {
+                out.text(target.display());
+            }
+            0
+        }
+        Err(error) => {
+            let context = format!("changing directory to '{}'", target.display());
+            out.error(&context, None, &error);
+            exit_code(error.kind())
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+/// A path with the soil operations as chainable methods.
+///
+/// Every error names the path it concerns, so `?` alone gives useful
+/// messages. `SoilPath` implements `AsRef<Path>` and converts to and from
+/// `PathBuf`, so it can be passed to any soil or standard library function.
+///
+/// Examples
+/// ```
+/// use soil::SoilPath;
+/// let root = SoilPath::new("soil_path_example");
+/// root.join("config").ensure_dir().unwrap();
+/// root.join("config/app.toml").write("debug = true\n").unwrap();
+///
+/// let config = root.join("config").join("app.toml");
+/// assert_eq!(config.read_text().unwrap(), "debug = true\n");
+/// assert_eq!(config.with_extension("bak").to_string(), "soil_path_example/config/app.bak");
+/// assert_eq!(config.parent().unwrap().children().unwrap(), [config.clone()]);
+///
+/// let error = root.join("missing.txt").read_text().unwrap_err();
+/// assert!(error.to_string().starts_with("soil_path_example/missing.txt: "));
+/// soil::remove_dir_all(&root).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SoilPath(PathBuf);
+
+impl SoilPath {
+    /// Wrap a path.
+    pub fn new<P: AsRef<Path>>(path: P) -> SoilPath {
+        SoilPath(path.as_ref().to_path_buf())
+    }
+
+    /// The wrapped path.
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Unwrap into the underlying `PathBuf`.
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+
+    /// This path with `part` appended.
+    pub fn join<P: AsRef<Path>>(&self, part: P) -> SoilPath {
+        SoilPath(self.0.join(part))
+    }
+
+    /// The parent directory, or `None` at a root or for an empty path.
+    pub fn parent(&self) -> Option<SoilPath> {
+        self.0.parent().map(SoilPath::new)
+    }
+
+    /// This path with its extension replaced (or removed, if `extension` is
+    /// empty).
+    pub fn with_extension<S: AsRef<OsStr>>(&self, extension: S) -> SoilPath {
+        SoilPath(self.0.with_extension(extension))
+    }
+
+    /// Whether anything exists at this path.
+    pub fn exists(&self) -> bool {
+        crate::exists(&self.0)
+    }
+
+    /// Read the whole file as UTF-8 text.
+    pub fn read_text(&self) -> Result<String, Error> {
+        fs::read_to_string(&self.0).map_err(|error| self.error(error))
+    }
+
+    /// Read the whole file as bytes.
+    pub fn read_bytes(&self) -> Result<Vec<u8>, Error> {
+        fs::read(&self.0).map_err(|error| self.error(error))
+    }
+
+    /// Create or truncate the file and write `contents` to it.
+    pub fn write<C: AsRef<[u8]>>(&self, contents: C) -> Result<&SoilPath, Error> {
+        fs::write(&self.0, contents).map_err(|error| self.error(error))?;
+        Ok(self)
+    }
+
+    /// Create this directory and any missing parents.
+    pub fn ensure_dir(&self) -> Result<&SoilPath, Error> {
+        fs::create_dir_all(&self.0).map_err(|error| self.error(error))?;
+        Ok(self)
+    }
+
+    /// The entries of this directory, sorted by name.
+    pub fn children(&self) -> Result<Vec<SoilPath>, Error> {
+        let mut children = fs::read_dir(&self.0)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| SoilPath(entry.path())))
+                    .collect::<Result<Vec<_>, Error>>()
+            })
+            .map_err(|error| self.error(error))?;
+        children.sort();
+        Ok(children)
+    }
+
+    /// `error`, with this path in front of its message.
+    fn error(&self, error: Error) -> Error {
+        Error::new(error.kind(), format!("{}: {}", self.0.display(), error))
+    }
+}
+
+impl AsRef<Path> for SoilPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl fmt::Display for SoilPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0.display(), f)
+    }
+}
+
+impl From<PathBuf> for SoilPath {
+    fn from(path: PathBuf) -> SoilPath {
+        SoilPath(path)
+    }
+}
+
+impl From<&Path> for SoilPath {
+    fn from(path: &Path) -> SoilPath {
+        SoilPath::new(path)
+    }
+}
+
+impl From<&str> for SoilPath {
+    fn from(path: &str) -> SoilPath {
+        SoilPath::new(path)
+    }
+}
+
+impl From<String> for SoilPath {
+    fn from(path: String) -> SoilPath {
+        SoilPath(PathBuf::from(path))
+    }
+}
+
+impl From<SoilPath> for PathBuf {
+    fn from(path: SoilPath) -> PathBuf {
+        path.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_chaining() {
+        let guard = setup_test();
+        let root = SoilPath::new(&guard.test_root);
+
+        let notes = root.join("a/b").ensure_dir().unwrap().join("notes.txt");
+        notes.write("first").unwrap().write("second").unwrap();
+        assert_eq!(notes.read_text().unwrap(), "second");
+        assert_eq!(notes.read_bytes().unwrap(), b"second");
+        assert!(notes.exists());
+        assert!(!notes.with_extension("md").exists());
+        assert_eq!(notes.parent().unwrap(), root.join("a").join("b"));
+        assert_eq!(SoilPath::new("/").parent(), None);
+    }
+
+    #[test]
+    fn test_children_sorted() {
+        let guard = setup_test();
+        let root = SoilPath::new(&guard.test_root);
+        for name in ["c", "a", "b"] {
+            root.join(name).write(name).unwrap();
+        }
+        let names: Vec<_> = root
+            .children()
+            .unwrap()
+            .iter()
+            .map(|child| child.as_path().file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_errors_name_the_path() {
+        let guard = setup_test();
+        let missing = SoilPath::new(&guard.test_root).join("missing");
+        let error = missing.children().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().contains(&missing.to_string()));
+
+        // Interoperates with the free functions through AsRef<Path>.
+        crate::write_file(&missing, "now here").unwrap();
+        assert_eq!(crate::read_text(&missing).unwrap(), "now here");
+        let path: PathBuf = missing.into();
+        assert!(path.ends_with("missing"));
+    }
+}
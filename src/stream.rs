@@ -0,0 +1,466 @@
+use serde_json::json;
+use soil::{
+    Mode, WriteOptions, base64_encode, escape_text, hex_dump, metadata, read_bytes, read_chunks,
+    read_lines, read_range, read_text,
+};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::BackupArgs;
+use crate::output::{Exit, Status, fail, output, reader_gone};
+
+/// Parse `START..END`, `START..` or `..END` into a start and optional end.
+pub(crate) fn parse_byte_range(text: &str) -> Result<(u64, Option<u64>), String> {
+    let (start, end) = text
+        .split_once("..")
+        .ok_or_else(|| format!("invalid range '{}': expected START..END", text))?;
+    let number = |part: &str| {
+        part.parse::<u64>()
+            .map_err(|_| format!("invalid range '{}': '{}' is not a byte offset", text, part))
+    };
+    let start = if start.is_empty() { 0 } else { number(start)? };
+    let end = if end.is_empty() {
+        None
+    } else {
+        Some(number(end)?)
+    };
+    if end.is_some_and(|end| end < start) {
+        return Err(format!("invalid range '{}': end is before start", text));
+    }
+    Ok((start, end))
+}
+
+/// How much of a file `soil tail` reads at a time.
+const TAIL_BLOCK: u64 = 64 * 1024;
+
+/// Byte offset at which the last `lines` lines of a file of `len` bytes
+/// start, found by reading backwards so the file is never loaded whole.
+fn tail_start(path: &str, len: u64, lines: usize) -> io::Result<u64> {
+    if lines == 0 {
+        return Ok(len);
+    }
+    let (mut end, mut seen) = (len, 0);
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_BLOCK);
+        let block = read_range(path, start, end - start)?;
+        for (index, byte) in block.iter().enumerate().rev() {
+            let at = start + index as u64;
+            // The terminator of the last line does not start a new one.
+            if *byte == b'\n' && at + 1 != len {
+                seen += 1;
+                if seen == lines {
+                    return Ok(at + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Copy bytes `from..to` of a file to stdout in blocks, returning where the
+/// copy stopped (earlier than `to` if the file shrank meanwhile).
+fn print_range(path: &str, from: u64, to: u64) -> io::Result<u64> {
+    let mut stdout = io::stdout().lock();
+    let mut at = from;
+    while at < to {
+        let block = read_range(path, at, (to - at).min(TAIL_BLOCK))?;
+        if block.is_empty() {
+            break;
+        }
+        stdout.write_all(&block)?;
+        at += block.len() as u64;
+    }
+    stdout.flush()?;
+    Ok(at)
+}
+
+/// Report a failure of `soil head`/`soil tail` and end, staying quiet when
+/// the reader went away (e.g. `| head`).
+fn read_failure(path: &str, error: io::Error) -> Exit {
+    if error.kind() == io::ErrorKind::BrokenPipe {
+        return reader_gone(0);
+    }
+    output().error(&format!("reading '{}'", path), Some(path), &error);
+    fail()
+}
+
+/// Parse a byte count or offset, in decimal or in hex with a `0x` prefix.
+pub(crate) fn parse_offset(text: &str) -> Result<u64, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid byte offset '{}'", text))
+}
+
+/// How `soil read-bytes` shows what it read.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BytesView {
+    Raw,
+    Hex,
+    Base64,
+    CArray,
+    Text,
+}
+
+/// How much `soil read-bytes --hex` and `--base64` read at a time: whole
+/// dump lines and whole base64 groups.
+const DUMP_BLOCK: u64 = 48 * 1024;
+
+/// `soil read-bytes`: show up to `length` bytes of `path` from `offset`.
+pub(crate) fn run_read_bytes(
+    path: &str,
+    offset: u64,
+    length: Option<u64>,
+    view: Option<BytesView>,
+) -> Status {
+    let out = output();
+    let end = length.map_or(u64::MAX, |length| offset.saturating_add(length));
+    let whole = offset == 0 && length.is_none();
+    let streamed = match view {
+        Some(BytesView::Raw) if whole => print_file(path).map(|_| true),
+        Some(BytesView::Raw) => print_range(path, offset, end).map(|_| true),
+        Some(view @ (BytesView::Hex | BytesView::Base64)) if out.is_text() => {
+            print_encoded(path, offset, end, view).map(|_| true)
+        }
+        _ => Ok(false),
+    };
+    match streamed {
+        Ok(true) => return Ok(()),
+        Ok(false) => {}
+        Err(error) => return Err(read_failure(path, error)),
+    }
+
+    let read = if whole {
+        read_bytes(path)
+    } else {
+        read_range(path, offset, end - offset)
+    };
+    let content = read.map_err(|error| {
+        out.error(
+            &format!("reading bytes from '{}'", path),
+            Some(path),
+            &error,
+        );
+        fail()
+    })?;
+    let (key, shown) = match view {
+        Some(BytesView::Hex) => ("hex", hex_dump(&content, offset)),
+        Some(BytesView::Base64) => ("base64", base64_encode(&content)),
+        Some(BytesView::CArray) => ("c_array", soil::c_array(&content, &c_name(path))),
+        Some(BytesView::Text) => ("text", escape_text(&content)),
+        Some(BytesView::Raw) | None => {
+            out.text(format_args!("Read {} bytes from '{}'", content.len(), path));
+            let size = content.len();
+            let text = String::from_utf8(content).ok();
+            match &text {
+                Some(text) => out.text(format_args!("Content:\n{}", text)),
+                None => out.text("Content contains non-UTF8 data; see it with --hex or --text"),
+            }
+            out.record(json!({ "path": path, "offset": offset, "size": size, "text": text }));
+            return Ok(());
+        }
+    };
+    if out.is_text() {
+        let shown = if key == "base64" { shown + "\n" } else { shown };
+        print_raw(shown.as_bytes()).map_err(|error| read_failure(path, error))?;
+    } else {
+        out.record(json!({ "path": path, "offset": offset, "size": content.len(), key: shown }));
+    }
+    Ok(())
+}
+
+/// Print bytes `from..to` of `path` as a hex dump or base64, a block at a
+/// time.
+fn print_encoded(path: &str, from: u64, to: u64, view: BytesView) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut at = from;
+    while at < to {
+        let block = read_range(path, at, (to - at).min(DUMP_BLOCK))?;
+        if block.is_empty() {
+            break;
+        }
+        match view {
+            BytesView::Hex => stdout.write_all(hex_dump(&block, at).as_bytes())?,
+            _ => stdout.write_all(base64_encode(&block).as_bytes())?,
+        }
+        at += block.len() as u64;
+    }
+    if view == BytesView::Base64 {
+        stdout.write_all(b"\n")?;
+    }
+    stdout.flush()
+}
+
+/// A C identifier for the array `--c-array` prints for `path`, made like
+/// `xxd -i` does from the file name.
+fn c_name(path: &str) -> String {
+    let name = Path::new(path)
+        .file_name()
+        .map_or_else(|| "data".into(), |name| name.to_string_lossy());
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+/// Copy the file at `path` to stdout as it is, a block at a time.
+fn print_file(path: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for block in read_chunks(path, TAIL_BLOCK as usize)? {
+        stdout.write_all(&block?)?;
+    }
+    stdout.flush()
+}
+
+/// Write `content` to stdout as it is.
+fn print_raw(content: &[u8]) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(content)?;
+    stdout.flush()
+}
+
+/// `soil tee`: copy stdin to stdout (in text mode) and to every file in
+/// `paths`. A file that cannot be opened or written is reported and
+/// dropped, and the rest carry on.
+pub(crate) fn run_tee(paths: &[String], append: bool) -> Status {
+    let out = output();
+    let options = WriteOptions::new().append(append);
+    let mut files: Vec<(&String, fs::File, u64)> = Vec::new();
+    for path in paths {
+        match options.open(path) {
+            Ok(file) => files.push((path, file, 0)),
+            Err(error) => out.error(&format!("opening '{}'", path), Some(path), &error),
+        }
+    }
+
+    let mut echo = out.is_text().then(io::stdout);
+    let mut stdin = io::stdin().lock();
+    let mut buffer = vec![0; TAIL_BLOCK as usize];
+    loop {
+        let read = match stdin.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => {
+                out.error("reading stdin", None, &error);
+                break;
+            }
+        };
+        let block = &buffer[..read];
+        // Like a pipe reader going away, a closed stdout only ends the echo.
+        if let Some(stdout) = &mut echo
+            && stdout.write_all(block).is_err()
+        {
+            echo = None;
+        }
+        files.retain_mut(|(path, file, written)| match file.write_all(block) {
+            Ok(()) => {
+                *written += read as u64;
+                true
+            }
+            Err(error) => {
+                out.error(&format!("writing to '{}'", path), Some(path), &error);
+                false
+            }
+        });
+    }
+    if let Some(stdout) = &mut echo {
+        let _ = stdout.flush();
+    }
+    for (path, _, bytes) in files {
+        out.record(json!({ "path": path, "bytes": bytes, "append": append }));
+    }
+    if out.status.load(Ordering::Relaxed) != 0 {
+        return Err(fail());
+    }
+    Ok(())
+}
+
+/// `soil read-text`: show `path`, or the bytes of it in `range`, as text.
+pub(crate) fn run_read_text(path: String, range: Option<(u64, Option<u64>)>, raw: bool) -> Status {
+    let out = output();
+    match range {
+        Some((start, end)) => {
+            let len = end.map_or(u64::MAX, |end| end - start);
+            match read_range(&path, start, len) {
+                Ok(content) if raw => {
+                    let text = String::from_utf8_lossy(&content);
+                    print_raw(text.as_bytes()).map_err(|error| read_failure(&path, error))?;
+                }
+                Ok(content) => {
+                    let end = start + content.len() as u64;
+                    let text = String::from_utf8_lossy(&content);
+                    out.text(format_args!(
+                        "Text from '{}' (bytes {}..{}):",
+                        path, start, end
+                    ));
+                    out.text(&text);
+                    out.record(json!({ "path": path, "start": start, "end": end, "text": text }));
+                }
+                Err(error) => {
+                    out.error(
+                        &format!("reading text from '{}'", path),
+                        Some(&path),
+                        &error,
+                    );
+                    return Err(fail());
+                }
+            }
+        }
+        None => match read_text(&path) {
+            Ok(content) if raw => {
+                print_raw(content.as_bytes()).map_err(|error| read_failure(&path, error))?;
+            }
+            Ok(content) => {
+                out.text(format_args!("Text from '{}':", path));
+                out.text(&content);
+                let end = content.len();
+                out.record(json!({ "path": path, "start": 0, "end": end, "text": content }));
+            }
+            Err(error) => {
+                out.error(
+                    &format!("reading text from '{}'", path),
+                    Some(&path),
+                    &error,
+                );
+                return Err(fail());
+            }
+        },
+    }
+    Ok(())
+}
+
+/// `soil head`: print the first `lines` lines, or `bytes` bytes, of `path`.
+pub(crate) fn run_head(path: String, lines: usize, bytes: Option<u64>) -> Status {
+    let out = output();
+    if let Some(bytes) = bytes {
+        print_range(&path, 0, bytes).map_err(|error| read_failure(&path, error))?;
+        return Ok(());
+    }
+    let reader = read_lines(&path).map_err(|error| read_failure(&path, error))?;
+    let mut stdout = io::stdout().lock();
+    let mut failed = false;
+    for line in reader.take(lines) {
+        match line {
+            Ok(line) => {
+                writeln!(stdout, "{}", line).map_err(|error| read_failure(&path, error))?;
+            }
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                out.error(&format!("reading '{}'", path), Some(&path), &error);
+                failed = true;
+            }
+            Err(error) => return Err(read_failure(&path, error)),
+        }
+    }
+    if failed {
+        return Err(fail());
+    }
+    Ok(())
+}
+
+/// `soil tail`: print the last `lines` lines, or `bytes` bytes, of `path`,
+/// then with `follow` whatever is appended to it.
+pub(crate) fn run_tail(path: String, lines: usize, bytes: Option<u64>, follow: bool) -> Status {
+    let out = output();
+    let len = metadata(&path)
+        .map_err(|error| read_failure(&path, error))?
+        .len();
+    let start = match bytes {
+        Some(bytes) => Ok(len.saturating_sub(bytes)),
+        None => tail_start(&path, len, lines),
+    };
+    let mut position = start
+        .and_then(|start| print_range(&path, start, len))
+        .map_err(|error| read_failure(&path, error))?;
+    if !follow {
+        return Ok(());
+    }
+    loop {
+        std::thread::sleep(Duration::from_millis(250));
+        let len = metadata(&path)
+            .map_err(|error| read_failure(&path, error))?
+            .len();
+        if len < position {
+            if out.is_text() {
+                eprintln!("soil: '{}' was truncated", path);
+            }
+            position = 0;
+        }
+        if len > position {
+            position =
+                print_range(&path, position, len).map_err(|error| read_failure(&path, error))?;
+        }
+    }
+}
+
+/// `soil write`: write `content`, or stdin for `-`, to `path`.
+pub(crate) fn run_write(
+    path: String,
+    content: String,
+    append: bool,
+    no_clobber: bool,
+    mode: Option<Mode>,
+    sync: bool,
+    backup: BackupArgs,
+) -> Status {
+    let out = output();
+    let mut options = WriteOptions::new()
+        .append(append)
+        .create_new(no_clobber)
+        .sync(sync)
+        .backup(backup.policy());
+    if let Some(mode) = mode {
+        options = options.mode(mode);
+    }
+    let written = if content == "-" {
+        options.write_from(&path, &mut io::stdin().lock())
+    } else {
+        options
+            .write(&path, &content)
+            .map(|()| content.len() as u64)
+    };
+    match written {
+        Ok(bytes) => {
+            let verb = if append { "Appended" } else { "Wrote" };
+            out.status(format_args!("{} {} bytes to '{}'", verb, bytes, path));
+            out.record(json!({ "path": path, "bytes": bytes, "append": append }));
+        }
+        Err(error) => {
+            out.error(&format!("writing to '{}'", path), Some(&path), &error);
+            return Err(fail());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_range() {
+        assert_eq!(parse_byte_range("10..20"), Ok((10, Some(20))));
+        assert_eq!(parse_byte_range("10.."), Ok((10, None)));
+        assert_eq!(parse_byte_range("..5"), Ok((0, Some(5))));
+        assert!(parse_byte_range("20..10").is_err());
+        assert!(parse_byte_range("10").is_err());
+        assert!(parse_byte_range("a..b").is_err());
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("512"), Ok(512));
+        assert_eq!(parse_offset("0x200"), Ok(512));
+        assert!(parse_offset("0xzz").is_err());
+        assert_eq!(c_name("./assets/1-icon.png"), "_1_icon_png");
+    }
+}
@@ -0,0 +1,338 @@
+use serde_json::{Value, json};
+use soil::{Match, ReplaceOptions, SearchOptions, replace_in_files, search};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::files::report_failures;
+use crate::output::{Exit, Status, fail, output, reader_gone};
+use crate::{ColorChoice, GrepArgs, ReplaceArgs};
+
+/// Prints `soil grep` matches as `path:line:column:text`, with context
+/// lines as `path-line-text` and `--` between separate groups.
+///
+/// Trailing context is held back until the next match is seen, so a line
+/// that is both context and a match is printed once, as a match.
+struct GrepPrinter {
+    out: io::StdoutLock<'static>,
+    color: bool,
+    context: bool,
+    last: Option<(PathBuf, usize)>,
+    pending: Vec<(usize, String)>,
+}
+
+impl GrepPrinter {
+    const PATH: &str = "\x1b[35m";
+    const NUMBER: &str = "\x1b[32m";
+    const MATCH: &str = "\x1b[1;31m";
+    const RESET: &str = "\x1b[0m";
+
+    fn new(color: bool, context: bool) -> GrepPrinter {
+        GrepPrinter {
+            out: io::stdout().lock(),
+            color,
+            context,
+            last: None,
+            pending: Vec::new(),
+        }
+    }
+
+    fn print(&mut self, found: &Match) -> io::Result<()> {
+        let first = found.line_number - found.before.len();
+        let same_file = self
+            .last
+            .as_ref()
+            .is_some_and(|(path, _)| *path == found.path);
+        let limit = if same_file { first } else { usize::MAX };
+        self.flush_pending(limit)?;
+
+        let last_line = match &self.last {
+            Some((_, line)) if same_file => *line,
+            _ => 0,
+        };
+        if self.context && self.last.is_some() && (!same_file || last_line + 1 < first) {
+            writeln!(self.out, "--")?;
+        }
+        for (offset, line) in found.before.iter().enumerate() {
+            let number = first + offset;
+            if number > last_line {
+                self.write_context(&found.path, number, line)?;
+            }
+        }
+
+        let path = found.path.display();
+        if self.color {
+            let mut highlighted = String::new();
+            let mut position = 0;
+            for range in &found.ranges {
+                highlighted.push_str(&found.line[position..range.start]);
+                highlighted.push_str(Self::MATCH);
+                highlighted.push_str(&found.line[range.clone()]);
+                highlighted.push_str(Self::RESET);
+                position = range.end;
+            }
+            highlighted.push_str(&found.line[position..]);
+            writeln!(
+                self.out,
+                "{}{}{}:{}{}{}:{}:{}",
+                Self::PATH,
+                path,
+                Self::RESET,
+                Self::NUMBER,
+                found.line_number,
+                Self::RESET,
+                found.column,
+                highlighted
+            )?;
+        } else {
+            writeln!(
+                self.out,
+                "{}:{}:{}:{}",
+                path, found.line_number, found.column, found.line
+            )?;
+        }
+
+        self.last = Some((found.path.clone(), found.line_number));
+        self.pending = (found.line_number + 1..)
+            .zip(found.after.iter().cloned())
+            .collect();
+        Ok(())
+    }
+
+    fn print_json(&mut self, found: &Match) -> io::Result<()> {
+        writeln!(self.out, "{}", match_record(found))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush_pending(usize::MAX)?;
+        self.out.flush()
+    }
+
+    /// Print held-back trailing context numbered below `limit`.
+    fn flush_pending(&mut self, limit: usize) -> io::Result<()> {
+        let Some((path, _)) = self.last.clone() else {
+            return Ok(());
+        };
+        for (number, line) in std::mem::take(&mut self.pending) {
+            if number >= limit {
+                break;
+            }
+            self.write_context(&path, number, &line)?;
+            self.last = Some((path.clone(), number));
+        }
+        Ok(())
+    }
+
+    fn write_context(&mut self, path: &Path, number: usize, line: &str) -> io::Result<()> {
+        if self.color {
+            writeln!(
+                self.out,
+                "{}{}{}-{}{}{}-{}",
+                Self::PATH,
+                path.display(),
+                Self::RESET,
+                Self::NUMBER,
+                number,
+                Self::RESET,
+                line
+            )
+        } else {
+            writeln!(self.out, "{}-{}-{}", path.display(), number, line)
+        }
+    }
+}
+
+/// The JSON record of a `soil grep` match.
+fn match_record(found: &Match) -> Value {
+    let ranges: Vec<[usize; 2]> = found.ranges.iter().map(|r| [r.start, r.end]).collect();
+    json!({
+        "path": found.path.to_string_lossy(),
+        "line_number": found.line_number,
+        "column": found.column,
+        "line": found.line,
+        "ranges": ranges,
+        "before": found.before,
+        "after": found.after,
+    })
+}
+
+/// Print a unified diff, coloring headers and changed lines when asked.
+fn print_diff(diff: &str, color: bool) {
+    for line in diff.lines() {
+        let code = match line.as_bytes().first() {
+            _ if !color => None,
+            Some(b'-') if line.starts_with("---") => Some("1"),
+            Some(b'+') if line.starts_with("+++") => Some("1"),
+            Some(b'-') => Some("31"),
+            Some(b'+') => Some("32"),
+            Some(b'@') => Some("36"),
+            _ => None,
+        };
+        match code {
+            Some(code) => println!("\x1b[{}m{}\x1b[0m", code, line),
+            None => println!("{}", line),
+        }
+    }
+}
+
+/// `soil grep`: print the lines under `path` that match `pattern`.
+pub(crate) fn run_grep(args: GrepArgs) -> Status {
+    let out = output();
+    let GrepArgs {
+        pattern,
+        path,
+        fixed_strings,
+        ignore_case,
+        text,
+        after_context,
+        before_context,
+        context,
+        max_count,
+        json,
+        color,
+        respect_ignore,
+    } = args;
+    let context = context.unwrap_or(0);
+    let before = before_context.unwrap_or(context);
+    let after = after_context.unwrap_or(context);
+    let mut options = SearchOptions::new()
+        .literal(fixed_strings)
+        .case_insensitive(ignore_case)
+        .include_binary(text)
+        .ignore(respect_ignore)
+        .before(before)
+        .after(after);
+    if let Some(max) = max_count {
+        options = options.max_matches(max);
+    }
+    if let Err(error) = fs::symlink_metadata(&path) {
+        out.error(&format!("reading '{}'", path), Some(&path), &error);
+        return Err(Exit(2));
+    }
+    let matches = match search(&path, &pattern, options) {
+        Ok(matches) => matches,
+        Err(error) => {
+            out.error("", Some(&path), &error);
+            return Err(Exit(2));
+        }
+    };
+
+    let color = match color {
+        ColorChoice::Auto => io::stdout().is_terminal(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    let mut printer = GrepPrinter::new(color, before > 0 || after > 0);
+    let (mut found, mut failed) = (false, false);
+    for result in matches {
+        let written = match result {
+            Ok(found_match) => {
+                found = true;
+                // In a batch or shell running as text, `--json` still
+                // prints NDJSON; otherwise the format decides.
+                if json && out.is_text() {
+                    printer.print_json(&found_match)
+                } else if !out.is_text() {
+                    out.record(match_record(&found_match));
+                    Ok(())
+                } else {
+                    printer.print(&found_match)
+                }
+            }
+            Err(error) => {
+                out.error("", None, &error);
+                failed = true;
+                Ok(())
+            }
+        };
+        if written.is_err() {
+            // The reader went away (e.g. `| head`); stop quietly.
+            return Err(reader_gone(0));
+        }
+    }
+    let _ = printer.finish();
+    Exit::with(if failed {
+        2
+    } else if found {
+        0
+    } else {
+        1
+    })
+}
+
+/// `soil replace`: rewrite `pattern` under `path`, or show the diff with -n.
+pub(crate) fn run_replace(args: ReplaceArgs) -> Status {
+    let out = output();
+    let ReplaceArgs {
+        pattern,
+        replacement,
+        path,
+        fixed_strings,
+        ignore_case,
+        include,
+        exclude,
+        dry_run,
+        respect_ignore,
+        backup,
+    } = args;
+    let mut options = ReplaceOptions::new()
+        .backup(backup.policy())
+        .literal(fixed_strings)
+        .case_insensitive(ignore_case)
+        .ignore(respect_ignore)
+        .dry_run(dry_run);
+    for glob in &include {
+        options = options.include(glob);
+    }
+    for glob in &exclude {
+        options = options.exclude(glob);
+    }
+    let report = match replace_in_files(&path, &pattern, &replacement, options) {
+        Ok(report) => report,
+        Err(error) => {
+            out.error("", Some(&path), &error);
+            return Err(fail());
+        }
+    };
+
+    let count: usize = report.done.iter().map(|(_, change)| change.count).sum();
+    if !out.is_text() {
+        for (file, change) in &report.done {
+            out.record(json!({
+                "path": file.to_string_lossy(),
+                "replacements": change.count,
+                "diff": dry_run.then_some(&change.diff),
+            }));
+        }
+    } else if dry_run {
+        let color = io::stdout().is_terminal();
+        for (_, change) in &report.done {
+            print_diff(&change.diff, color);
+        }
+        if !out.quiet {
+            eprintln!(
+                "Would replace {} matches in {} files",
+                count,
+                report.done.len()
+            );
+        }
+    } else {
+        for (file, change) in &report.done {
+            out.status(format_args!(
+                "{}: {} replaced",
+                file.display(),
+                change.count
+            ));
+        }
+        out.status(format_args!(
+            "Replaced {} matches in {} files",
+            count,
+            report.done.len()
+        ));
+    }
+    report_failures(&report);
+    if !report.is_success() {
+        return Err(fail());
+    }
+    Ok(())
+}
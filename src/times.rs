@@ -0,0 +1,589 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::trace::TraceLevel::Changes;
+use crate::trace::traced;
+
+/// A value for one of the timestamps passed to [`set_times`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    /// The current time, taken by the kernel when the change is made.
+    Now,
+    /// Leave this timestamp as it is.
+    Unchanged,
+    /// An explicit point in time, with nanosecond precision.
+    At(SystemTime),
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Timestamp {
+        Timestamp::At(time)
+    }
+}
+
+/// Set the access and modification times of a path.
+///
+/// Follows symbolic links; use [`set_symlink_times`] to change the link
+/// itself.
+///
+/// Examples
+/// ```
+/// use soil::{set_times, metadata, write_file, remove_file, Timestamp};
+/// use std::time::{Duration, UNIX_EPOCH};
+/// write_file("set_times_example.txt", "content").unwrap();
+/// let mtime = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+/// set_times("set_times_example.txt", Timestamp::Now, mtime).unwrap();
+/// assert_eq!(metadata("set_times_example.txt").unwrap().modified().unwrap(), mtime);
+/// remove_file("set_times_example.txt").unwrap();
+/// ```
+pub fn set_times<P: AsRef<Path>, A: Into<Timestamp>, M: Into<Timestamp>>(
+    path: P,
+    atime: A,
+    mtime: M,
+) -> Result<(), Error> {
+    update_times(path.as_ref(), atime.into(), mtime.into(), true)
+}
+
+/// Set the access and modification times of a symbolic link itself.
+#[cfg(unix)]
+pub fn set_symlink_times<P: AsRef<Path>, A: Into<Timestamp>, M: Into<Timestamp>>(
+    path: P,
+    atime: A,
+    mtime: M,
+) -> Result<(), Error> {
+    update_times(path.as_ref(), atime.into(), mtime.into(), false)
+}
+
+/// Create a file if it does not exist and set both its times to now
+/// (like `touch`).
+///
+/// Examples
+/// ```
+/// use soil::{touch, exists, remove_file};
+/// touch("touch_example.txt").unwrap();
+/// assert!(exists("touch_example.txt"));
+/// touch("touch_example.txt").unwrap();
+/// remove_file("touch_example.txt").unwrap();
+/// ```
+pub fn touch<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let open = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path.as_ref());
+    traced(
+        Changes,
+        format_args!("open({:?}, O_WRONLY|O_CREAT)", path.as_ref()),
+        open,
+    )?;
+    set_times(path, Timestamp::Now, Timestamp::Now)
+}
+
+/// Copy the access and modification times of `reference` onto `path`.
+///
+/// Examples
+/// ```
+/// use soil::{copy_times, metadata, write_file, remove_file, set_times};
+/// use std::time::{Duration, UNIX_EPOCH};
+/// write_file("copy_times_ref.txt", "reference").unwrap();
+/// write_file("copy_times_dst.txt", "target").unwrap();
+/// let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+/// set_times("copy_times_ref.txt", old, old).unwrap();
+/// copy_times("copy_times_ref.txt", "copy_times_dst.txt").unwrap();
+/// assert_eq!(metadata("copy_times_dst.txt").unwrap().modified().unwrap(), old);
+/// remove_file("copy_times_ref.txt").unwrap();
+/// remove_file("copy_times_dst.txt").unwrap();
+/// ```
+pub fn copy_times<P1: AsRef<Path>, P2: AsRef<Path>>(reference: P1, path: P2) -> Result<(), Error> {
+    let metadata = fs::metadata(reference.as_ref())?;
+    set_times(path, metadata.accessed()?, metadata.modified()?)
+}
+
+#[cfg(unix)]
+fn update_times(
+    path: &Path,
+    atime: Timestamp,
+    mtime: Timestamp,
+    follow_symlinks: bool,
+) -> Result<(), Error> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+    let times = [to_timespec(atime)?, to_timespec(mtime)?];
+    let flags = if follow_symlinks {
+        0
+    } else {
+        libc::AT_SYMLINK_NOFOLLOW
+    };
+
+    // SAFETY: `c_path` is a valid NUL-terminated string and `times` holds
+    // exactly the two entries utimensat reads.
+    let result = unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), flags) };
+    let result = if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    };
+    traced(Changes, format_args!("utimensat({:?})", path), result)
+}
+
+#[cfg(unix)]
+fn to_timespec(timestamp: Timestamp) -> Result<libc::timespec, Error> {
+    let (tv_sec, tv_nsec) = match timestamp {
+        Timestamp::Now => (0, libc::UTIME_NOW),
+        Timestamp::Unchanged => (0, libc::UTIME_OMIT),
+        Timestamp::At(time) => {
+            let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+                Ok(since) => (since.as_secs() as i64, since.subsec_nanos() as i64),
+                Err(error) => {
+                    // Before the epoch: borrow a second so nanoseconds stay positive.
+                    let before = error.duration();
+                    let mut secs = -(before.as_secs() as i64);
+                    let mut nanos = before.subsec_nanos() as i64;
+                    if nanos > 0 {
+                        secs -= 1;
+                        nanos = 1_000_000_000 - nanos;
+                    }
+                    (secs, nanos)
+                }
+            };
+            let secs = libc::time_t::try_from(secs)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "timestamp out of range"))?;
+            (secs, nanos as _)
+        }
+    };
+    Ok(libc::timespec { tv_sec, tv_nsec })
+}
+
+#[cfg(not(unix))]
+fn update_times(
+    path: &Path,
+    atime: Timestamp,
+    mtime: Timestamp,
+    _follow_symlinks: bool,
+) -> Result<(), Error> {
+    let now = SystemTime::now();
+    let mut times = fs::FileTimes::new();
+    match atime {
+        Timestamp::Now => times = times.set_accessed(now),
+        Timestamp::At(time) => times = times.set_accessed(time),
+        Timestamp::Unchanged => {}
+    }
+    match mtime {
+        Timestamp::Now => times = times.set_modified(now),
+        Timestamp::At(time) => times = times.set_modified(time),
+        Timestamp::Unchanged => {}
+    }
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_times(times));
+    traced(Changes, format_args!("set_times({:?})", path), result)
+}
+
+/// Parse a date and time as accepted by `soil touch -d`.
+///
+/// Supported forms:
+/// - `@1700000000` or `@1700000000.25`: seconds since the Unix epoch
+/// - `2024-05-01`: midnight of that day
+/// - `2024-05-01 13:45`, `2024-05-01T13:45:30.123456789`: date and time
+///
+/// A trailing `Z` or `±HH:MM` offset pins the time zone; without one the
+/// time is taken as local time (UTC on platforms without a local zone).
+/// Times the clock cannot represent fail with `ErrorKind::InvalidInput`.
+///
+/// Examples
+/// ```
+/// use soil::parse_datetime;
+/// use std::time::{Duration, UNIX_EPOCH};
+/// let time = parse_datetime("2023-11-14T22:13:20Z").unwrap();
+/// assert_eq!(time, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+/// assert_eq!(parse_datetime("@1700000000").unwrap(), time);
+/// assert_eq!(parse_datetime("2023-11-15T00:13:20+02:00").unwrap(), time);
+/// assert!(parse_datetime("yesterday-ish").is_err());
+/// assert!(parse_datetime("99999999999999-01-01").is_err());
+/// ```
+pub fn parse_datetime(input: &str) -> Result<SystemTime, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid date '{}'", input));
+    let out_of_range = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("date out of range '{}'", input),
+        )
+    };
+    let input = input.trim();
+
+    if let Some(epoch) = input.strip_prefix('@') {
+        let (secs, nanos) = parse_seconds(epoch).ok_or_else(invalid)?;
+        return from_epoch(secs, nanos).ok_or_else(out_of_range);
+    }
+
+    let (date, rest) = match input.find(['T', ' ']) {
+        Some(index) => (&input[..index], Some(&input[index + 1..])),
+        None => (input, None),
+    };
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts
+        .next()
+        .and_then(parse_digits)
+        .ok_or_else(invalid)?;
+    let month: i64 = date_parts
+        .next()
+        .and_then(parse_digits)
+        .ok_or_else(invalid)?;
+    let day: i64 = date_parts
+        .next()
+        .and_then(parse_digits)
+        .ok_or_else(invalid)?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(invalid());
+    }
+
+    let (clock, offset) = match rest {
+        None => ("00:00", None),
+        Some(rest) => split_offset(rest).ok_or_else(invalid)?,
+    };
+
+    let mut clock_parts = clock.splitn(3, ':');
+    let hour: i64 = clock_parts
+        .next()
+        .and_then(parse_digits)
+        .ok_or_else(invalid)?;
+    let minute: i64 = clock_parts
+        .next()
+        .and_then(parse_digits)
+        .ok_or_else(invalid)?;
+    let (second, nanos) = match clock_parts.next() {
+        Some(second) => parse_seconds(second).ok_or_else(invalid)?,
+        None => (0, 0),
+    };
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid());
+    }
+
+    let secs = days_from_civil(year, month, day)
+        .and_then(|days| days.checked_mul(86_400))
+        .and_then(|days| days.checked_add(hour * 3_600 + minute * 60 + second))
+        .and_then(|civil| civil.checked_sub(offset.unwrap_or_else(|| local_offset(civil))))
+        .ok_or_else(out_of_range)?;
+    from_epoch(secs, nanos).ok_or_else(out_of_range)
+}
+
+/// `secs` (possibly negative) plus a positive nanosecond part, or `None`
+/// if the clock cannot represent it.
+fn from_epoch(secs: i64, nanos: u32) -> Option<SystemTime> {
+    let whole = Duration::from_secs(secs.unsigned_abs());
+    let base = if secs < 0 {
+        UNIX_EPOCH.checked_sub(whole)?
+    } else {
+        UNIX_EPOCH.checked_add(whole)?
+    };
+    base.checked_add(Duration::from_nanos(nanos as u64))
+}
+
+fn parse_digits(text: &str) -> Option<i64> {
+    if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// Parse `SS` or `SS.fraction` (an optional leading `-` is allowed).
+fn parse_seconds(text: &str) -> Option<(i64, u32)> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (whole, fraction) = match text.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (text, None),
+    };
+    let secs = parse_digits(whole)?;
+    let nanos = match fraction {
+        None => 0,
+        Some(fraction) => {
+            if fraction.is_empty()
+                || fraction.len() > 9
+                || !fraction.bytes().all(|byte| byte.is_ascii_digit())
+            {
+                return None;
+            }
+            format!("{:0<9}", fraction).parse().ok()?
+        }
+    };
+    if negative {
+        // -1.25 is 1.25 seconds before the epoch: -2 s + 0.75 s.
+        if nanos == 0 {
+            Some((-secs, 0))
+        } else {
+            Some((-secs - 1, 1_000_000_000 - nanos))
+        }
+    } else {
+        Some((secs, nanos))
+    }
+}
+
+/// Split `13:45:00+02:00` into the clock part and the offset in seconds.
+fn split_offset(text: &str) -> Option<(&str, Option<i64>)> {
+    if let Some(clock) = text.strip_suffix('Z') {
+        return Some((clock, Some(0)));
+    }
+    match text.rfind(['+', '-']) {
+        Some(index) => {
+            let (clock, offset) = text.split_at(index);
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let seconds = parse_digits(hours)?
+                .checked_mul(3_600)?
+                .checked_add(parse_digits(minutes)?.checked_mul(60)?)?;
+            Some((clock, Some(sign * seconds)))
+        }
+        None => Some((text, None)),
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days between 1970-01-01 and the given date in the proleptic Gregorian
+/// calendar (Howard Hinnant's `days_from_civil`), or `None` if that does
+/// not fit in an `i64`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146_097)?.checked_add(day_of_era - 719_468)
+}
+
+/// The date for a day count since 1970-01-01 (Howard Hinnant's
+/// `civil_from_days`), as `(year, month, day)`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format a time as an RFC 3339 date and time in UTC, such as
+/// `2023-11-14T22:13:20Z`, with nanoseconds (trailing zeros dropped) when
+/// the time has any. [`parse_datetime`] reads the result back exactly.
+///
+/// Examples
+/// ```
+/// use soil::{format_datetime, parse_datetime};
+/// use std::time::{Duration, UNIX_EPOCH};
+/// let time = UNIX_EPOCH + Duration::new(1_700_000_000, 250_000_000);
+/// assert_eq!(format_datetime(time), "2023-11-14T22:13:20.25Z");
+/// assert_eq!(parse_datetime(&format_datetime(time)).unwrap(), time);
+/// ```
+pub fn format_datetime(time: SystemTime) -> String {
+    let (secs, nanos) = epoch_parts(time);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let of_day = secs.rem_euclid(86_400);
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        of_day / 3_600,
+        of_day % 3_600 / 60,
+        of_day % 60
+    );
+    if nanos > 0 {
+        let fraction = format!("{:09}", nanos);
+        text.push('.');
+        text.push_str(fraction.trim_end_matches('0'));
+    }
+    text.push('Z');
+    text
+}
+
+/// Format a time as a compact UTC stamp like `20240131T235959Z`, which
+/// sorts in chronological order.
+pub(crate) fn format_utc_stamp(time: SystemTime) -> String {
+    let (secs, _) = epoch_parts(time);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        of_day / 3_600,
+        of_day % 3_600 / 60,
+        of_day % 60
+    )
+}
+
+/// Whole seconds since the Unix epoch, rounded down, and the nanoseconds
+/// past them.
+fn epoch_parts(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => (elapsed.as_secs() as i64, elapsed.subsec_nanos()),
+        Err(before) => {
+            let before = before.duration();
+            let secs = -(before.as_secs() as i64);
+            match before.subsec_nanos() {
+                0 => (secs, 0),
+                nanos => (secs - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// Offset of local time from UTC, in seconds, around the given wall-clock
+/// time (interpreted as if it were UTC).
+#[cfg(unix)]
+fn local_offset(civil: i64) -> i64 {
+    let time = civil as libc::time_t;
+    // SAFETY: localtime_r only writes into the zeroed `tm` we hand it.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+#[cfg(not(unix))]
+fn local_offset(_civil: i64) -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc_stamp() {
+        assert_eq!(format_utc_stamp(UNIX_EPOCH), "19700101T000000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_661);
+        assert_eq!(format_utc_stamp(leap_day), "20000229T010101Z");
+        for days in [-800_000, -1, 0, 59, 11_017, 20_000, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), Some(days));
+        }
+    }
+
+    #[test]
+    fn test_format_datetime() {
+        assert_eq!(format_datetime(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let before = UNIX_EPOCH - Duration::from_millis(1_500);
+        assert_eq!(format_datetime(before), "1969-12-31T23:59:58.5Z");
+        let precise = UNIX_EPOCH + Duration::new(951_782_400, 123_456_789);
+        assert_eq!(format_datetime(precise), "2000-02-29T00:00:00.123456789Z");
+        assert_eq!(parse_datetime(&format_datetime(precise)).unwrap(), precise);
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), Some(0));
+        assert_eq!(days_from_civil(2000, 3, 1), Some(11_017));
+        assert_eq!(days_from_civil(1969, 12, 31), Some(-1));
+        assert_eq!(days_from_civil(2024, 2, 29), Some(19_782));
+        assert_eq!(days_from_civil(i64::MAX, 1, 1), None);
+    }
+
+    #[test]
+    fn test_parse_datetime_forms() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(parse_datetime("2023-11-14 22:13:20Z").unwrap(), base);
+        assert_eq!(
+            parse_datetime("2023-11-14T22:13Z").unwrap(),
+            base - Duration::from_secs(20)
+        );
+        assert_eq!(parse_datetime("2023-11-14T17:13:20-05:00").unwrap(), base);
+        assert_eq!(
+            parse_datetime("2023-11-14T22:13:20.5Z").unwrap(),
+            base + Duration::from_millis(500)
+        );
+        assert_eq!(
+            parse_datetime("@1700000000.000000001").unwrap(),
+            base + Duration::from_nanos(1)
+        );
+        assert_eq!(
+            parse_datetime("@-1.25").unwrap(),
+            UNIX_EPOCH - Duration::from_millis(1250)
+        );
+        assert_eq!(
+            parse_datetime("1969-12-31T23:59:59Z").unwrap(),
+            UNIX_EPOCH - Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_parse_datetime_rejects_garbage() {
+        for input in [
+            "",
+            "@",
+            "@abc",
+            "2023-13-01",
+            "2023-02-29",
+            "2023-11-14T25:00Z",
+            "2023-11-14T22",
+            "2023-11-14T22:13:20.1234567891Z",
+            "now",
+        ] {
+            assert!(parse_datetime(input).is_err(), "accepted '{}'", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_datetime_out_of_range() {
+        for input in [
+            "99999999999999-01-01",
+            "9223372036854775807-12-31T23:59:59Z",
+            "292277026596-12-04T15:30:08Z",
+            "2023-11-14T22:13+99999999999999999:00",
+            "2023-11-14T22:13-2562047788015215:30",
+            "@99999999999999999999",
+        ] {
+            let error = parse_datetime(input).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "for '{}'", input);
+        }
+        // At the edge of the epoch range the result is either a time or an
+        // error, never a panic.
+        for input in ["@9223372036854775807.999999999", "@-9223372036854775807.5"] {
+            if let Err(error) = parse_datetime(input) {
+                assert_eq!(error.kind(), ErrorKind::InvalidInput);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_timestamp_before_epoch() {
+        let spec = to_timespec(Timestamp::At(UNIX_EPOCH - Duration::from_millis(1250))).unwrap();
+        assert_eq!(spec.tv_sec, -2);
+        assert_eq!(spec.tv_nsec, 750_000_000);
+    }
+}
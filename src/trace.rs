@@ -0,0 +1,128 @@
+use std::fmt;
+use std::io::Error;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much of soil's filesystem activity [`set_trace`] reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceLevel {
+    /// Calls that change the filesystem: creating, writing, renaming,
+    /// removing, linking and changing permissions, owners or times.
+    Changes = 1,
+    /// Also the calls that only look, such as opening files for reading,
+    /// stats and link reads.
+    All = 2,
+}
+
+type Sink = Box<dyn Fn(&str) + Send + Sync>;
+
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+/// Send a line describing each filesystem call soil makes, at or below
+/// `level`, to `sink`, for the whole process.
+///
+/// Lines look like `rename("a.txt", "b.txt")`, with `: <error>` appended
+/// when the call failed. Tracing stays on until [`clear_trace`].
+///
+/// Examples
+/// ```
+/// use soil::{TraceLevel, clear_trace, remove_file, set_trace, write_file};
+/// use std::sync::{Arc, Mutex};
+///
+/// let lines = Arc::new(Mutex::new(Vec::new()));
+/// let sink = Arc::clone(&lines);
+/// set_trace(TraceLevel::Changes, move |line| sink.lock().unwrap().push(line.to_string()));
+/// write_file("trace_example.txt", "hello").unwrap();
+/// remove_file("trace_example.txt").unwrap();
+/// clear_trace();
+///
+/// let lines = lines.lock().unwrap();
+/// assert!(lines.contains(&r#"unlink("trace_example.txt")"#.to_string()));
+/// ```
+pub fn set_trace<F>(level: TraceLevel, sink: F)
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    *SINK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Box::new(sink));
+    LEVEL.store(level as u8, Ordering::Release);
+}
+
+/// Stop the tracing started by [`set_trace`].
+pub fn clear_trace() {
+    LEVEL.store(0, Ordering::Release);
+    *SINK
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+/// Whether calls at `level` are being traced.
+pub(crate) fn enabled(level: TraceLevel) -> bool {
+    LEVEL.load(Ordering::Acquire) >= level as u8
+}
+
+/// Report `line` at `level`.
+pub(crate) fn emit(level: TraceLevel, line: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    if let Some(sink) = SINK
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+    {
+        sink(&line.to_string());
+    }
+}
+
+/// Report the call described by `call` at `level`, with its error if
+/// `result` failed, and pass `result` on.
+pub(crate) fn traced<T>(
+    level: TraceLevel,
+    call: fmt::Arguments,
+    result: Result<T, Error>,
+) -> Result<T, Error> {
+    if enabled(level) {
+        match &result {
+            Ok(_) => emit(level, call),
+            Err(error) => emit(level, format_args!("{}: {}", call, error)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_levels_and_errors() {
+        let guard = setup_test();
+        let file = format!("{}/traced.txt", guard.test_root);
+        let missing = format!("{}/missing", guard.test_root);
+        crate::write_file(&file, "x").unwrap();
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&lines);
+        set_trace(TraceLevel::Changes, move |line| {
+            sink.lock().unwrap().push(line.to_string())
+        });
+        crate::metadata(&file).unwrap();
+        crate::remove_file(&missing).unwrap_err();
+        clear_trace();
+        crate::remove_file(&file).unwrap();
+
+        let lines = lines.lock().unwrap();
+        // Other tests may trace concurrently; look only at our own paths.
+        let ours: Vec<_> = lines
+            .iter()
+            .filter(|line| line.contains(&format!("{}/", guard.test_root)))
+            .collect();
+        assert_eq!(ours.len(), 1, "{:?}", ours);
+        assert!(ours[0].starts_with(&format!("unlink({:?}): ", missing)));
+    }
+}
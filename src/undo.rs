@@ -0,0 +1,423 @@
+use soil::{
+    CancelToken, CopyOptions, Mode, create_dir, create_hard_link, ensure_dir, exists, glob,
+    is_glob, metadata, move_path_with_progress, remove_dir_all, remove_empty_dir, remove_file,
+    set_times, symlink_metadata,
+};
+#[cfg(unix)]
+use soil::{Owner, chown, lchown};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use crate::output::{exit_code, output};
+use crate::{Commands, CopyArgs};
+
+/// One step of taking back a command run in `soil shell`.
+pub(crate) enum UndoStep {
+    /// Remove a file, link or empty directory the command created.
+    Remove(PathBuf),
+    /// Remove a directory tree the command created.
+    RemoveTree(PathBuf),
+    /// Move a copy saved before the command back into place.
+    Restore { saved: PathBuf, path: PathBuf },
+    /// Move an entry back to where it was.
+    Rename { from: PathBuf, to: PathBuf },
+    /// Recreate a directory the command removed.
+    CreateDir(PathBuf),
+    /// Put back access and modification times.
+    Times {
+        path: PathBuf,
+        accessed: SystemTime,
+        modified: SystemTime,
+    },
+    /// Put back permission bits.
+    #[cfg(unix)]
+    Mode { path: PathBuf, mode: Mode },
+    /// Put back the owner and group, of a link itself unless `follow`.
+    #[cfg(unix)]
+    Owner {
+        path: PathBuf,
+        owner: Owner,
+        follow: bool,
+    },
+}
+
+impl UndoStep {
+    fn apply(&self) -> io::Result<()> {
+        let (path, result) = match self {
+            UndoStep::Remove(path) => {
+                let result = symlink_metadata(path).and_then(|metadata| {
+                    if metadata.is_dir() {
+                        remove_empty_dir(path)
+                    } else {
+                        remove_file(path)
+                    }
+                });
+                (path, result)
+            }
+            UndoStep::RemoveTree(path) => (path, remove_dir_all(path)),
+            UndoStep::Restore { saved, path } => {
+                let result = move_path_with_progress(saved, path, &CancelToken::new(), |_| {});
+                (path, result)
+            }
+            UndoStep::Rename { from, to } => {
+                let result = move_path_with_progress(from, to, &CancelToken::new(), |_| {});
+                (from, result)
+            }
+            UndoStep::CreateDir(path) => (path, create_dir(path)),
+            UndoStep::Times {
+                path,
+                accessed,
+                modified,
+            } => (path, set_times(path, *accessed, *modified)),
+            #[cfg(unix)]
+            UndoStep::Mode { path, mode } => {
+                use std::os::unix::fs::PermissionsExt;
+                let permissions = fs::Permissions::from_mode(mode.bits());
+                (path, soil::set_permissions(path, permissions))
+            }
+            #[cfg(unix)]
+            UndoStep::Owner {
+                path,
+                owner,
+                follow,
+            } => {
+                let result = if *follow {
+                    chown(path, *owner)
+                } else {
+                    lchown(path, *owner)
+                };
+                (path, result)
+            }
+        };
+        result
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path.display(), error)))
+    }
+}
+
+/// Where `soil shell` keeps what a command is about to overwrite or
+/// remove, so that `undo` can put it back.
+pub(crate) struct Stash {
+    dir: PathBuf,
+    next: usize,
+}
+
+impl Stash {
+    pub(crate) fn new() -> Stash {
+        Stash {
+            dir: std::env::temp_dir().join(format!("soil-shell-{}", process::id())),
+            next: 0,
+        }
+    }
+
+    /// Keep the file or link at `path`. A file about to be removed is kept
+    /// by a hard link where possible; one about to be overwritten in place
+    /// must be copied.
+    fn save(&mut self, path: &Path, removing: bool) -> io::Result<PathBuf> {
+        ensure_dir(&self.dir)?;
+        let saved = self.dir.join(self.next.to_string());
+        self.next += 1;
+        if removing && create_hard_link(path, &saved).is_ok() {
+            return Ok(saved);
+        }
+        CopyOptions::archive().copy(path, &saved)?;
+        Ok(saved)
+    }
+
+    /// A step that restores `path` as it is now, or removes it if it does
+    /// not exist yet.
+    fn restore_or_remove(&mut self, path: PathBuf, removing: bool) -> io::Result<UndoStep> {
+        Ok(match symlink_metadata(&path) {
+            Ok(_) => UndoStep::Restore {
+                saved: self.save(&path, removing)?,
+                path,
+            },
+            Err(_) => UndoStep::Remove(path),
+        })
+    }
+
+    /// Drop what was kept for `steps`, which will not be undone.
+    pub(crate) fn discard(&self, steps: &[UndoStep]) {
+        for step in steps {
+            if let UndoStep::Restore { saved, .. } = step {
+                let _ = remove_file(saved);
+            }
+        }
+    }
+}
+
+impl Drop for Stash {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.dir);
+    }
+}
+
+/// How to take back `command`, worked out before it runs: `None` if it
+/// changes nothing, or why it cannot be undone.
+pub(crate) fn plan_undo(
+    command: &Commands,
+    stash: &mut Stash,
+) -> Result<Option<Vec<UndoStep>>, String> {
+    let cannot = |what: &str| Err(format!("cannot undo {}", what));
+    let absolute = |path: &str| std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path));
+    let saving = |error: io::Error| format!("cannot undo: {}", error);
+    let mut steps = Vec::new();
+    match command {
+        Commands::Mkdir { path } => steps.push(UndoStep::Remove(absolute(path))),
+        Commands::Mkdirp { path } => {
+            let path = absolute(path);
+            for dir in path.ancestors().take_while(|dir| !exists(dir)) {
+                steps.push(UndoStep::Remove(dir.to_path_buf()));
+            }
+        }
+        Commands::Rmdir { path } => {
+            let path = absolute(path);
+            #[cfg(unix)]
+            let mode = metadata(&path).map(|metadata| {
+                use std::os::unix::fs::PermissionsExt;
+                Mode::from_bits(metadata.permissions().mode())
+            });
+            steps.push(UndoStep::CreateDir(path.clone()));
+            #[cfg(unix)]
+            if let Ok(mode) = mode {
+                steps.push(UndoStep::Mode { path, mode });
+            }
+        }
+        Commands::Rm { paths } => {
+            for path in glob_paths(paths) {
+                let path = absolute(&path);
+                if symlink_metadata(&path).is_ok_and(|metadata| !metadata.is_dir()) {
+                    let saved = stash.save(&path, true).map_err(saving)?;
+                    steps.push(UndoStep::Restore { saved, path });
+                }
+            }
+        }
+        Commands::Write { path, .. } => {
+            steps.push(
+                stash
+                    .restore_or_remove(absolute(path), false)
+                    .map_err(saving)?,
+            );
+        }
+        Commands::Tee { paths, .. } => {
+            for path in paths {
+                steps.push(
+                    stash
+                        .restore_or_remove(absolute(path), false)
+                        .map_err(saving)?,
+                );
+            }
+        }
+        Commands::Touch(args) => match metadata(&args.path) {
+            Ok(metadata) => steps.push(UndoStep::Times {
+                path: absolute(&args.path),
+                accessed: metadata.accessed().map_err(saving)?,
+                modified: metadata.modified().map_err(saving)?,
+            }),
+            Err(_) if !args.no_create => steps.push(UndoStep::Remove(absolute(&args.path))),
+            Err(_) => {}
+        },
+        Commands::Copy(args) => {
+            let CopyArgs {
+                sources,
+                dst,
+                recursive,
+                ..
+            } = &**args;
+            let into_dir = Path::new(dst).is_dir();
+            for src in glob_paths(sources) {
+                let target = match Path::new(&src).file_name() {
+                    Some(name) if into_dir => Path::new(dst).join(name),
+                    _ => PathBuf::from(dst),
+                };
+                let target = absolute(&target.to_string_lossy());
+                match symlink_metadata(&target) {
+                    Err(_) if *recursive && Path::new(&src).is_dir() => {
+                        steps.push(UndoStep::RemoveTree(target))
+                    }
+                    Ok(metadata) if metadata.is_dir() => {
+                        return cannot("a copy into an existing directory");
+                    }
+                    _ => steps.push(stash.restore_or_remove(target, false).map_err(saving)?),
+                }
+            }
+        }
+        Commands::Mv { from, to, .. } => {
+            let (from, to) = (absolute(from), absolute(to));
+            let replaced = match symlink_metadata(&to) {
+                Ok(metadata) if metadata.is_dir() => return cannot("a move over a directory"),
+                Ok(_) => Some(stash.save(&to, true).map_err(saving)?),
+                Err(_) => None,
+            };
+            steps.push(UndoStep::Rename {
+                from: to.clone(),
+                to: from,
+            });
+            if let Some(saved) = replaced {
+                steps.push(UndoStep::Restore { saved, path: to });
+            }
+        }
+        Commands::Hardlink { link, .. } | Commands::Symlink { link, .. } => {
+            steps.push(UndoStep::Remove(absolute(link)));
+        }
+        #[cfg(unix)]
+        Commands::Chmod(args) if !args.recursive => {
+            use std::os::unix::fs::PermissionsExt;
+            for path in glob_paths(&args.paths) {
+                if let Ok(metadata) = metadata(&path) {
+                    steps.push(UndoStep::Mode {
+                        path: absolute(&path),
+                        mode: Mode::from_bits(metadata.permissions().mode()),
+                    });
+                }
+            }
+        }
+        Commands::Chmod(_) => return cannot("chmod --recursive"),
+        #[cfg(unix)]
+        Commands::Chown {
+            path,
+            recursive: false,
+            no_dereference,
+            ..
+        } => {
+            use std::os::unix::fs::MetadataExt;
+            let current = if *no_dereference {
+                symlink_metadata(path)
+            } else {
+                metadata(path)
+            };
+            if let Ok(metadata) = current {
+                steps.push(UndoStep::Owner {
+                    path: absolute(path),
+                    owner: Owner {
+                        uid: Some(metadata.uid()),
+                        gid: Some(metadata.gid()),
+                    },
+                    follow: !no_dereference,
+                });
+            }
+        }
+        #[cfg(unix)]
+        Commands::Chown { .. } => return cannot("chown --recursive"),
+        Commands::Rmrf { .. } => return cannot("rmrf"),
+        Commands::Replace(args) if !args.dry_run => return cannot("replace"),
+        Commands::Find(args) if !args.exec.is_empty() => return cannot("find --exec"),
+        Commands::Batch { .. } => return cannot("batch"),
+        Commands::Man { out_dir: Some(_) } => return cannot("man --out-dir"),
+        _ => return Ok(None),
+    }
+    Ok(Some(steps))
+}
+
+/// The paths `args` name, with wildcards expanded as the commands do: an
+/// existing file by the exact name wins, and a pattern that matches nothing
+/// is kept as it is.
+fn glob_paths(args: &[String]) -> Vec<String> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if !is_glob(arg) || symlink_metadata(arg).is_ok() {
+            paths.push(arg.clone());
+            continue;
+        }
+        match glob(arg) {
+            Ok(matches) if !matches.is_empty() => paths.extend(
+                matches
+                    .iter()
+                    .map(|path| path.to_string_lossy().into_owned()),
+            ),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    paths
+}
+
+/// The last command run in `soil shell` that changed something, and how to
+/// take it back (or why that is not possible).
+pub(crate) struct LastChange {
+    pub(crate) line: String,
+    pub(crate) undo: Result<Vec<UndoStep>, String>,
+}
+
+/// The shell's `undo`. Returns the exit status.
+pub(crate) fn undo_last(last: Option<LastChange>, stash: &Stash) -> i32 {
+    let out = output();
+    let Some(LastChange { line, undo }) = last else {
+        eprintln!("soil: nothing to undo");
+        return 1;
+    };
+    let steps = match undo {
+        Ok(steps) => steps,
+        Err(reason) => {
+            eprintln!("soil: {} ('{}')", reason, line);
+            return 1;
+        }
+    };
+    let mut status = 0;
+    for step in &steps {
+        if let Err(error) = step.apply() {
+            out.error("undoing", None, &error);
+            if status == 0 {
+                status = exit_code(error.kind());
+            }
+        }
+    }
+    stash.discard(&steps);
+    if status == 0 {
+        out.status(format_args!("Undid '{}'", line));
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::{ShellCommand, ShellLine};
+    use clap::Parser;
+    use soil::read_text;
+
+    #[test]
+    fn test_plan_undo() {
+        let root = std::env::temp_dir().join(format!("soil-plan-undo-{}", process::id()));
+        let file = root.join("file.txt");
+        ensure_dir(&root).unwrap();
+        soil::write_file(&file, "before").unwrap();
+        let mut stash = Stash::new();
+        let parse = |line: &str| match ShellLine::try_parse_from(shlex::split(line).unwrap()) {
+            Ok(ShellLine {
+                command: ShellCommand::Soil(command),
+            }) => command,
+            _ => panic!("not a soil command: {}", line),
+        };
+
+        let nested = root.join("a/b");
+        let steps = plan_undo(
+            &parse(&format!("mkdirp '{}'", nested.display())),
+            &mut stash,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(
+            matches!(&steps[..], [UndoStep::Remove(b), UndoStep::Remove(a)]
+            if *b == nested && *a == root.join("a"))
+        );
+
+        let steps = plan_undo(
+            &parse(&format!("write '{}' after", file.display())),
+            &mut stash,
+        )
+        .unwrap()
+        .unwrap();
+        soil::write_file(&file, "after").unwrap();
+        steps.iter().for_each(|step| step.apply().unwrap());
+        assert_eq!(read_text(&file).unwrap(), "before");
+
+        assert_eq!(
+            plan_undo(&parse("rmrf x"), &mut stash).err(),
+            Some("cannot undo rmrf".to_string())
+        );
+        assert!(plan_undo(&parse("ls ."), &mut stash).unwrap().is_none());
+        soil::remove_dir_all(&root).unwrap();
+    }
+}
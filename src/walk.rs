@@ -0,0 +1,761 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::copy::{CopyOptions, copy_file_tracked, copy_symlink};
+use crate::ignore::IgnoreRules;
+use crate::progress::Tracker;
+#[cfg(unix)]
+use crate::{Backup, ChmodTarget, Mode, ModeChange};
+
+/// One file, directory or link found by a [`Walker`].
+#[derive(Clone, Debug)]
+pub struct WalkEntry {
+    /// Full path, starting with the walker's root.
+    pub path: PathBuf,
+    /// `0` for the root, `1` for its children and so on.
+    pub depth: usize,
+    /// Metadata of the entry itself; symbolic links are not followed.
+    pub metadata: fs::Metadata,
+}
+
+/// The outcome of a bulk operation: what succeeded and what failed, each
+/// sorted by path so reports are identical from run to run regardless of
+/// thread scheduling.
+#[derive(Debug)]
+pub struct BulkReport<T> {
+    /// Entries the operation handled, with a per-entry result.
+    pub done: Vec<(PathBuf, T)>,
+    /// Entries that could not be handled.
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+impl<T> BulkReport<T> {
+    /// Whether every entry was handled.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    fn sorted(mut self) -> BulkReport<T> {
+        self.done.sort_by(|a, b| a.0.cmp(&b.0));
+        self.failed.sort_by(|a, b| a.0.cmp(&b.0));
+        self
+    }
+}
+
+/// A parallel directory walker backed by a small work-stealing thread pool.
+///
+/// Each worker keeps its own queue of directories to read and steals from
+/// the others when it runs dry, so one huge subdirectory does not leave the
+/// remaining threads idle. Symbolic links are reported but never followed.
+///
+/// Besides plain traversal, the walker drives the bulk operations
+/// ([`Walker::copy_to`], [`Walker::remove`], [`Walker::hash`] and
+/// `Walker::chmod` on Unix). Their reports are sorted, so the output does not
+/// depend on the thread count.
+///
+/// Examples
+/// ```
+/// use soil::{ensure_dir, write_file, remove_dir_all, Walker};
+/// ensure_dir("walker_example/a/b").unwrap();
+/// write_file("walker_example/a/b/file.txt", "content").unwrap();
+///
+/// let entries = Walker::new("walker_example").threads(4).entries().unwrap();
+/// let paths: Vec<_> = entries.iter().map(|e| e.path.to_string_lossy().into_owned()).collect();
+/// assert_eq!(
+///     paths,
+///     ["walker_example", "walker_example/a", "walker_example/a/b", "walker_example/a/b/file.txt"]
+/// );
+/// remove_dir_all("walker_example").unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Walker {
+    root: PathBuf,
+    threads: usize,
+    ignore: bool,
+}
+
+impl Walker {
+    /// Walk `root` with one thread per available CPU.
+    pub fn new<P: AsRef<Path>>(root: P) -> Walker {
+        Walker {
+            root: root.as_ref().to_path_buf(),
+            threads: thread::available_parallelism().map_or(1, |count| count.get()),
+            ignore: false,
+        }
+    }
+
+    /// Use `threads` worker threads (at least one).
+    pub fn threads(mut self, threads: usize) -> Walker {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Skip entries excluded by ignore files (see [`IgnoreRules`]), in the
+    /// walk and in every bulk operation. Off by default.
+    pub fn ignore(mut self, ignore: bool) -> Walker {
+        self.ignore = ignore;
+        self
+    }
+
+    /// The directory this walker starts from.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Call `visit` for every entry, in parallel and in no particular order.
+    ///
+    /// Errors returned by `visit` or hit while reading directories are
+    /// collected per path; only a missing or unreadable root fails outright.
+    pub fn for_each<F>(&self, visit: F) -> Result<BulkReport<()>, Error>
+    where
+        F: Fn(&WalkEntry) -> Result<(), Error> + Sync,
+    {
+        self.run(|entry| visit(entry).map(Some))
+    }
+
+    /// Collect every entry, sorted by path.
+    pub fn entries(&self) -> Result<Vec<WalkEntry>, Error> {
+        let report = self.run(|entry| Ok(Some(entry.clone())))?;
+        if let Some((path, error)) = report.failed.into_iter().next() {
+            return Err(Error::new(
+                error.kind(),
+                format!("{}: {}", path.display(), error),
+            ));
+        }
+        Ok(report.done.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    /// Copy the tree to `dst`, which must not exist yet.
+    ///
+    /// Files are copied in parallel; directory permissions are applied once
+    /// their contents are in place. Symbolic links are recreated as links.
+    /// Reports the bytes copied per file. Same as
+    /// [`Walker::copy_to_with`] with the default [`CopyOptions`].
+    ///
+    /// Examples
+    /// ```
+    /// use soil::{ensure_dir, write_file, read_text, remove_dir_all, Walker};
+    /// ensure_dir("walker_copy_src/nested").unwrap();
+    /// write_file("walker_copy_src/nested/file.txt", "content").unwrap();
+    /// let report = Walker::new("walker_copy_src").copy_to("walker_copy_dst").unwrap();
+    /// assert!(report.is_success());
+    /// assert_eq!(read_text("walker_copy_dst/nested/file.txt").unwrap(), "content");
+    /// remove_dir_all("walker_copy_src").unwrap();
+    /// remove_dir_all("walker_copy_dst").unwrap();
+    /// ```
+    pub fn copy_to<P: AsRef<Path>>(&self, dst: P) -> Result<BulkReport<u64>, Error> {
+        self.copy_to_with(dst, &CopyOptions::new())
+    }
+
+    /// Copy the tree to `dst`, which must not exist yet, keeping the metadata
+    /// `options` asks for.
+    ///
+    /// Symbolic links are always recreated as links. Directory metadata is
+    /// applied once their contents are in place, deepest first, so preserved
+    /// directory times survive the copy. When preserving links, files that
+    /// are hard-linked together in the source are linked the same way in the
+    /// copy (linked entries report zero bytes).
+    ///
+    /// Examples
+    /// ```
+    /// use soil::{ensure_dir, metadata, write_file, remove_dir_all, set_times, CopyOptions, Timestamp, Walker};
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// ensure_dir("walker_archive_src/cache").unwrap();
+    /// write_file("walker_archive_src/cache/entry", "built").unwrap();
+    /// let mtime = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    /// set_times("walker_archive_src/cache/entry", Timestamp::Now, mtime).unwrap();
+    ///
+    /// Walker::new("walker_archive_src")
+    ///     .copy_to_with("walker_archive_dst", &CopyOptions::archive())
+    ///     .unwrap();
+    /// let copied = metadata("walker_archive_dst/cache/entry").unwrap();
+    /// assert_eq!(copied.modified().unwrap(), mtime);
+    /// remove_dir_all("walker_archive_src").unwrap();
+    /// remove_dir_all("walker_archive_dst").unwrap();
+    /// ```
+    pub fn copy_to_with<P: AsRef<Path>>(
+        &self,
+        dst: P,
+        options: &CopyOptions,
+    ) -> Result<BulkReport<u64>, Error> {
+        let dst = dst.as_ref();
+        if fs::symlink_metadata(dst).is_ok() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("'{}' already exists", dst.display()),
+            ));
+        }
+
+        let directories = Mutex::new(Vec::new());
+        // First copy of each multiply-linked inode, and the later sightings
+        // to link to it once every first copy exists.
+        let linked = Mutex::new(HashMap::new());
+        let deferred = Mutex::new(Vec::new());
+        let report = self.run(|entry| {
+            let relative = entry.path.strip_prefix(&self.root).unwrap_or(&entry.path);
+            let target = dst.join(relative);
+            let file_type = entry.metadata.file_type();
+            if file_type.is_dir() {
+                crate::create_dir(&target)?;
+                lock(&directories).push((entry.depth, entry.path.clone(), target));
+                return Ok(None);
+            }
+            if file_type.is_symlink() {
+                copy_symlink(&entry.path, &target)?;
+                options.copy_metadata(&entry.path, &target, &entry.metadata)?;
+                return Ok(Some(0));
+            }
+            if options.preserve.links
+                && let Some(key) = link_key(&entry.metadata)
+            {
+                let mut linked = lock(&linked);
+                if let Some(first) = linked.get(&key) {
+                    lock(&deferred).push((entry.path.clone(), PathBuf::clone(first), target));
+                    return Ok(None);
+                }
+                linked.insert(key, target.clone());
+            }
+            copy_file_tracked(
+                &entry.path,
+                &target,
+                options.strategy,
+                options.preserve.mode,
+                &Backup::None,
+                &mut Tracker::silent(),
+            )?;
+            options.copy_metadata(&entry.path, &target, &entry.metadata)?;
+            Ok(Some(entry.metadata.len()))
+        })?;
+
+        let (mut done, mut failed) = (report.done, report.failed);
+        for (source, first, target) in deferred.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            match crate::create_hard_link(&first, &target) {
+                Ok(()) => done.push((source, 0)),
+                Err(error) => failed.push((source, error)),
+            }
+        }
+
+        let mut directories = directories.into_inner().unwrap_or_else(|e| e.into_inner());
+        directories.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(&b.2)));
+        for (_, source, target) in directories {
+            let applied = fs::symlink_metadata(&source)
+                .and_then(|metadata| options.copy_metadata(&source, &target, &metadata));
+            if let Err(error) = applied {
+                failed.push((target, error));
+            }
+        }
+
+        Ok(BulkReport { done, failed }.sorted())
+    }
+
+    /// Remove the tree, root included.
+    ///
+    /// Files and links are deleted in parallel while walking; directories are
+    /// removed afterwards, deepest first.
+    ///
+    /// Examples
+    /// ```
+    /// use soil::{ensure_dir, write_file, exists, Walker};
+    /// ensure_dir("walker_remove/a/b").unwrap();
+    /// write_file("walker_remove/a/b/file.txt", "content").unwrap();
+    /// let report = Walker::new("walker_remove").threads(2).remove().unwrap();
+    /// assert!(report.is_success());
+    /// assert_eq!(report.done.len(), 4);
+    /// assert!(!exists("walker_remove"));
+    /// ```
+    pub fn remove(&self) -> Result<BulkReport<()>, Error> {
+        let directories = Mutex::new(Vec::new());
+        let report = self.run(|entry| {
+            if entry.metadata.is_dir() {
+                lock(&directories).push((entry.depth, entry.path.clone()));
+                Ok(None)
+            } else {
+                crate::remove_file(&entry.path)?;
+                Ok(Some(()))
+            }
+        })?;
+
+        let mut directories = directories.into_inner().unwrap_or_else(|e| e.into_inner());
+        let (mut done, mut failed) = (report.done, report.failed);
+        directories.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        for (_, path) in directories {
+            match crate::remove_empty_dir(&path) {
+                Ok(()) => done.push((path, ())),
+                // Ignored entries were left in place, so their parents stay.
+                Err(error) if self.ignore && error.kind() == ErrorKind::DirectoryNotEmpty => {}
+                Err(error) => failed.push((path, error)),
+            }
+        }
+
+        Ok(BulkReport { done, failed }.sorted())
+    }
+
+    /// SHA-256 every regular file in the tree, as lowercase hex.
+    ///
+    /// Examples
+    /// ```
+    /// use soil::{ensure_dir, write_file, remove_dir_all, Walker};
+    /// ensure_dir("walker_hash").unwrap();
+    /// write_file("walker_hash/abc.txt", "abc").unwrap();
+    /// let report = Walker::new("walker_hash").hash().unwrap();
+    /// assert_eq!(
+    ///     report.done[0].1,
+    ///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    /// );
+    /// remove_dir_all("walker_hash").unwrap();
+    /// ```
+    pub fn hash(&self) -> Result<BulkReport<String>, Error> {
+        self.run(|entry| {
+            if entry.metadata.is_file() {
+                hash_file(&entry.path).map(Some)
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    /// Apply a mode change across the tree, like [`crate::chmod_recursive`].
+    ///
+    /// Files are changed in parallel while walking. A directory the change
+    /// leaves traversable (u+rx) is changed before its contents are read, so
+    /// a locked tree can be opened up; one the change locks is left until
+    /// after the walk and done deepest first, so locking never hides its
+    /// contents. Reports the new mode of every changed entry.
+    #[cfg(unix)]
+    pub fn chmod(
+        &self,
+        change: &ModeChange,
+        target: ChmodTarget,
+    ) -> Result<BulkReport<Mode>, Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let apply = |path: &Path, metadata: &fs::Metadata| -> Result<Mode, Error> {
+            let current = Mode::from_bits(metadata.permissions().mode());
+            let mode = change.apply(current, metadata.is_dir());
+            crate::set_permissions(path, fs::Permissions::from_mode(mode.bits()))?;
+            Ok(mode)
+        };
+
+        let directories = Mutex::new(Vec::new());
+        let report = self.run(|entry| {
+            let file_type = entry.metadata.file_type();
+            if file_type.is_symlink() {
+                Ok(None)
+            } else if file_type.is_dir() {
+                if target == ChmodTarget::Files {
+                    return Ok(None);
+                }
+                let current = Mode::from_bits(entry.metadata.permissions().mode());
+                if change.apply(current, true).bits() & 0o500 == 0o500 {
+                    return apply(&entry.path, &entry.metadata).map(Some);
+                }
+                lock(&directories).push((entry.depth, entry.clone()));
+                Ok(None)
+            } else if target != ChmodTarget::Dirs {
+                apply(&entry.path, &entry.metadata).map(Some)
+            } else {
+                Ok(None)
+            }
+        })?;
+
+        let mut directories = directories.into_inner().unwrap_or_else(|e| e.into_inner());
+        let (mut done, mut failed) = (report.done, report.failed);
+        directories.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.path.cmp(&b.1.path)));
+        for (_, entry) in directories {
+            match apply(&entry.path, &entry.metadata) {
+                Ok(mode) => done.push((entry.path, mode)),
+                Err(error) => failed.push((entry.path, error)),
+            }
+        }
+
+        Ok(BulkReport { done, failed }.sorted())
+    }
+
+    /// Walk the tree in parallel, calling `visit` for every entry and keeping
+    /// the `Some` results.
+    fn run<T, F>(&self, visit: F) -> Result<BulkReport<T>, Error>
+    where
+        T: Send,
+        F: Fn(&WalkEntry) -> Result<Option<T>, Error> + Sync,
+    {
+        let root = WalkEntry {
+            path: self.root.clone(),
+            depth: 0,
+            metadata: fs::symlink_metadata(&self.root)?,
+        };
+
+        let done = Mutex::new(Vec::new());
+        let failed = Mutex::new(Vec::new());
+        let record = |entry: &WalkEntry| match visit(entry) {
+            Ok(Some(value)) => lock(&done).push((entry.path.clone(), value)),
+            Ok(None) => {}
+            Err(error) => lock(&failed).push((entry.path.clone(), error)),
+        };
+
+        let rules = self.ignore.then(|| IgnoreRules::new(&self.root));
+        record(&root);
+        if root.metadata.is_dir() {
+            let pool = Pool::new(self.threads);
+            pool.push(0, (root.path, 0));
+            thread::scope(|scope| {
+                for worker in 0..self.threads {
+                    let pool = &pool;
+                    let record = &record;
+                    let failed = &failed;
+                    let rules = &rules;
+                    scope.spawn(move || {
+                        while let Some((dir, depth)) = pool.next(worker) {
+                            match fs::read_dir(&dir) {
+                                Ok(children) => {
+                                    for child in children {
+                                        let entry = child.and_then(|child| {
+                                            Ok(WalkEntry {
+                                                path: child.path(),
+                                                depth: depth + 1,
+                                                metadata: child.metadata()?,
+                                            })
+                                        });
+                                        match entry {
+                                            Ok(entry)
+                                                if rules.as_ref().is_some_and(|rules| {
+                                                    rules.ignores_entry(
+                                                        &entry.path,
+                                                        entry.metadata.is_dir(),
+                                                    )
+                                                }) => {}
+                                            Ok(entry) => {
+                                                record(&entry);
+                                                if entry.metadata.is_dir() {
+                                                    pool.push(worker, (entry.path, depth + 1));
+                                                }
+                                            }
+                                            Err(error) => lock(failed).push((dir.clone(), error)),
+                                        }
+                                    }
+                                }
+                                Err(error) => lock(failed).push((dir, error)),
+                            }
+                            pool.finish();
+                        }
+                    });
+                }
+            });
+        }
+
+        Ok(BulkReport {
+            done: done.into_inner().unwrap_or_else(|e| e.into_inner()),
+            failed: failed.into_inner().unwrap_or_else(|e| e.into_inner()),
+        }
+        .sorted())
+    }
+}
+
+/// Identity of a file with more than one hard link, used to keep links
+/// together when copying.
+#[cfg(unix)]
+fn link_key(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn link_key(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Per-worker queues of directories still to read.
+///
+/// `pending` counts directories that were queued but not finished; workers
+/// exit once it drops to zero and there is nothing left to steal.
+struct Pool {
+    queues: Vec<Mutex<VecDeque<(PathBuf, usize)>>>,
+    pending: AtomicUsize,
+}
+
+impl Pool {
+    fn new(workers: usize) -> Pool {
+        Pool {
+            queues: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, worker: usize, job: (PathBuf, usize)) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        lock(&self.queues[worker]).push_back(job);
+    }
+
+    /// Take the newest job from our own queue, or steal the oldest job from
+    /// someone else's. Returns `None` once all work is done.
+    fn next(&self, worker: usize) -> Option<(PathBuf, usize)> {
+        loop {
+            if let Some(job) = lock(&self.queues[worker]).pop_back() {
+                return Some(job);
+            }
+            let count = self.queues.len();
+            for offset in 1..count {
+                if let Some(job) = lock(&self.queues[(worker + offset) % count]).pop_front() {
+                    return Some(job);
+                }
+            }
+            if self.pending.load(Ordering::SeqCst) == 0 {
+                return None;
+            }
+            thread::sleep(Duration::from_micros(50));
+        }
+    }
+
+    fn finish(&self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+
+    fn build_tree(root: &str) {
+        for dir in 0..6 {
+            fs::create_dir_all(format!("{}/d{}/inner", root, dir)).unwrap();
+            for file in 0..5 {
+                fs::write(
+                    format!("{}/d{}/f{}.txt", root, dir, file),
+                    format!("{}-{}", dir, file),
+                )
+                .unwrap();
+                fs::write(format!("{}/d{}/inner/g{}.txt", root, dir, file), "inner").unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_entries_are_sorted_and_complete() {
+        let guard = setup_test();
+        let root = &*format!("{}/walk_tree", guard.test_root);
+        build_tree(root);
+
+        let single = Walker::new(root).threads(1).entries().unwrap();
+        let many = Walker::new(root).threads(8).entries().unwrap();
+        // root + 6 dirs + 6 inner dirs + 60 files
+        assert_eq!(single.len(), 73);
+        let paths =
+            |entries: &[WalkEntry]| entries.iter().map(|e| e.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&single), paths(&many));
+        assert!(paths(&many).windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(many[0].depth, 0);
+    }
+
+    #[test]
+    fn test_hash_is_deterministic_across_thread_counts() {
+        let guard = setup_test();
+        let root = &*format!("{}/hash_tree", guard.test_root);
+        build_tree(root);
+
+        let one = Walker::new(root).threads(1).hash().unwrap();
+        let many = Walker::new(root).threads(6).hash().unwrap();
+        assert_eq!(one.done.len(), 60);
+        assert_eq!(one.done, many.done);
+    }
+
+    #[test]
+    fn test_copy_and_remove() {
+        let guard = setup_test();
+        let src = &*format!("{}/copy_src", guard.test_root);
+        let dst = &*format!("{}/copy_dst", guard.test_root);
+        build_tree(src);
+
+        let report = Walker::new(src).threads(4).copy_to(dst).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.done.len(), 60);
+        assert_eq!(
+            fs::read_to_string(format!("{}/d3/f2.txt", dst)).unwrap(),
+            "3-2"
+        );
+        assert!(Walker::new(src).copy_to(dst).is_err());
+
+        let report = Walker::new(dst).threads(4).remove().unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.done.len(), 73);
+        assert!(!Path::new(dst).exists());
+    }
+
+    #[test]
+    fn test_ignore_rules() {
+        let guard = setup_test();
+        let root = &*format!("{}/ignore_tree", guard.test_root);
+        build_tree(root);
+        fs::write(format!("{}/.gitignore", root), "d1/\nf0.txt\n").unwrap();
+        fs::write(format!("{}/.soilignore", root), "!d2/f0.txt\n").unwrap();
+
+        let all = Walker::new(root).entries().unwrap().len();
+        let kept = Walker::new(root).ignore(true).entries().unwrap();
+        assert!(
+            kept.iter()
+                .all(|entry| !entry.path.starts_with(format!("{}/d1", root)))
+        );
+        assert!(kept.iter().any(|entry| entry.path.ends_with("d2/f0.txt")));
+        assert!(!kept.iter().any(|entry| entry.path.ends_with("d3/f0.txt")));
+        assert!(kept.len() < all);
+
+        let report = Walker::new(root).ignore(true).remove().unwrap();
+        assert!(report.is_success());
+        assert!(Path::new(&format!("{}/d1/inner/g1.txt", root)).exists());
+        assert!(Path::new(&format!("{}/d3/f0.txt", root)).exists());
+        assert!(!Path::new(&format!("{}/d2", root)).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_to_with_archive() {
+        use crate::{CopyOptions, Timestamp, set_times};
+        use std::os::unix::fs::{MetadataExt, symlink};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let guard = setup_test();
+        let src = &*format!("{}/archive_src", guard.test_root);
+        let dst = &*format!("{}/archive_dst", guard.test_root);
+        build_tree(src);
+        fs::hard_link(
+            format!("{}/d0/f0.txt", src),
+            format!("{}/d5/inner/same.txt", src),
+        )
+        .unwrap();
+        symlink("../d0/f0.txt", format!("{}/d1/link.txt", src)).unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_234_567_890);
+        set_times(format!("{}/d2/f2.txt", src), Timestamp::Now, mtime).unwrap();
+        set_times(format!("{}/d3", src), Timestamp::Now, mtime).unwrap();
+
+        let report = Walker::new(src)
+            .threads(4)
+            .copy_to_with(dst, &CopyOptions::archive())
+            .unwrap();
+        assert!(report.is_success());
+
+        let first = fs::metadata(format!("{}/d0/f0.txt", dst)).unwrap();
+        let second = fs::metadata(format!("{}/d5/inner/same.txt", dst)).unwrap();
+        assert_eq!(first.ino(), second.ino());
+        assert_eq!(first.nlink(), 2);
+        assert_eq!(
+            fs::read_link(format!("{}/d1/link.txt", dst)).unwrap(),
+            Path::new("../d0/f0.txt")
+        );
+        let modified = |path: String| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified(format!("{}/d2/f2.txt", dst)), mtime);
+        assert_eq!(modified(format!("{}/d3", dst)), mtime);
+
+        // Without preserving links the copies are independent files.
+        let plain = &*format!("{}/plain_dst", guard.test_root);
+        Walker::new(src).copy_to(plain).unwrap();
+        let second = fs::metadata(format!("{}/d5/inner/same.txt", plain)).unwrap();
+        assert_eq!(second.nlink(), 1);
+    }
+
+    #[test]
+    fn test_missing_root_fails() {
+        assert!(Walker::new("./definitely/not/here").entries().is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_chmod_parallel() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let guard = setup_test();
+        let root = &*format!("{}/chmod_tree", guard.test_root);
+        build_tree(root);
+
+        let report = Walker::new(root)
+            .threads(3)
+            .chmod(&"0600".parse().unwrap(), ChmodTarget::Files)
+            .unwrap();
+        assert_eq!(report.done.len(), 60);
+        let mode = fs::metadata(format!("{}/d1/inner/g1.txt", root))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let report = Walker::new(root)
+            .chmod(&"go-rwx".parse().unwrap(), ChmodTarget::Dirs)
+            .unwrap();
+        assert_eq!(report.done.len(), 13);
+        assert!(report.is_success());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_chmod_opens_locked_directories_first() {
+        use crate::{TraceLevel, clear_trace, set_trace};
+        use std::os::unix::fs::PermissionsExt;
+        use std::sync::Arc;
+
+        let guard = setup_test();
+        let root = &*format!("{}/locked_tree", guard.test_root);
+        let (outer, inner) = (format!("{}/d", root), format!("{}/d/e", root));
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(format!("{}/file.txt", inner), "x").unwrap();
+        for dir in [&inner, &outer] {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o000)).unwrap();
+        }
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&lines);
+        set_trace(TraceLevel::Changes, move |line| {
+            lock(&sink).push(line.to_string())
+        });
+        let report = Walker::new(root)
+            .threads(4)
+            .chmod(&"u+rwx".parse().unwrap(), ChmodTarget::All)
+            .unwrap();
+        clear_trace();
+
+        assert!(report.is_success(), "{:?}", report.failed);
+        for dir in [&outer, &inner] {
+            let mode = fs::metadata(dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o700, 0o700, "{}", dir);
+        }
+        // The outer directory is opened up before anything inside it.
+        let lines = lock(&lines);
+        let position = |path: &str| {
+            lines
+                .iter()
+                .position(|line| line.starts_with(&format!("chmod({:?}", path)))
+                .unwrap()
+        };
+        assert!(position(&outer) < position(&inner));
+    }
+}
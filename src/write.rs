@@ -0,0 +1,295 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, Read, Write};
+use std::path::Path;
+
+use crate::trace::TraceLevel::Changes;
+use crate::trace::traced;
+use crate::{Backup, Mode};
+
+/// How [`WriteOptions::write`] opens its file, in the spirit of
+/// [`std::fs::OpenOptions`].
+///
+/// The default truncates an existing file or creates a new one, like
+/// [`write_file`](crate::write_file).
+///
+/// Examples
+/// ```
+/// use soil::{read_text, remove_file, Mode, WriteOptions};
+/// let log = WriteOptions::new().append(true);
+/// log.write("write_options_example.log", "started\n").unwrap();
+/// log.write("write_options_example.log", "stopped\n").unwrap();
+/// assert_eq!(read_text("write_options_example.log").unwrap(), "started\nstopped\n");
+///
+/// let exclusive = WriteOptions::new().create_new(true);
+/// assert!(exclusive.write("write_options_example.log", "again").is_err());
+/// remove_file("write_options_example.log").unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    append: bool,
+    create_new: bool,
+    mode: Option<Mode>,
+    sync: bool,
+    backup: Backup,
+}
+
+impl WriteOptions {
+    /// Create or truncate, default permissions, no forced sync.
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    /// Add to the end of an existing file instead of truncating it.
+    pub fn append(mut self, append: bool) -> WriteOptions {
+        self.append = append;
+        self
+    }
+
+    /// Fail with `ErrorKind::AlreadyExists` if the file exists. The check
+    /// and the creation are one atomic step.
+    pub fn create_new(mut self, create_new: bool) -> WriteOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Give a newly created file exactly these permissions, regardless of
+    /// the umask. Existing files keep theirs. Ignored outside Unix.
+    pub fn mode(mut self, mode: Mode) -> WriteOptions {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Open with `O_SYNC` so every write reaches the disk before returning
+    /// (outside Unix, the file is synced once written).
+    pub fn sync(mut self, sync: bool) -> WriteOptions {
+        self.sync = sync;
+        self
+    }
+
+    /// Back up an existing file before opening it (see [`Backup`]).
+    pub fn backup(mut self, backup: Backup) -> WriteOptions {
+        self.backup = backup;
+        self
+    }
+
+    /// Open `path` for writing with these options.
+    ///
+    /// The backup, if any, is taken once the file has been opened, right
+    /// before it is truncated, so a failed open leaves no backup behind. A
+    /// file the open creates has nothing to back up.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<File, Error> {
+        let path = path.as_ref();
+        let existed = !self.create_new && fs::symlink_metadata(path).is_ok();
+        let file = self.open_untruncated(path)?;
+        if existed {
+            self.backup.backup(path)?;
+        }
+        if !self.append && !self.create_new {
+            traced(
+                Changes,
+                format_args!("ftruncate({:?}, 0)", path),
+                file.set_len(0),
+            )?;
+        }
+        Ok(file)
+    }
+
+    /// Open `path` with these options, but without truncating it yet.
+    fn open_untruncated(&self, path: &Path) -> Result<File, Error> {
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if self.create_new {
+            options.create_new(true);
+        } else {
+            options.create(true);
+        }
+        if self.append {
+            options.append(true);
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+            if self.sync {
+                options.custom_flags(libc::O_SYNC);
+            }
+            if let Some(mode) = self.mode {
+                // The umask can only narrow the mode, so the file is never
+                // more open than asked for, even before the fix-up below.
+                options.mode(mode.bits());
+                let created = self.create_new || !path.exists();
+                let file = self.traced_open(path, options.open(path))?;
+                if created {
+                    file.set_permissions(std::fs::Permissions::from_mode(mode.bits()))?;
+                }
+                return Ok(file);
+            }
+        }
+        self.traced_open(path, options.open(path))
+    }
+
+    /// Report the open of `path`, with the flags these options asked for.
+    fn traced_open(&self, path: &Path, result: Result<File, Error>) -> Result<File, Error> {
+        let flags = match (self.create_new, self.append) {
+            (true, _) => "O_CREAT|O_EXCL",
+            (false, true) => "O_CREAT|O_APPEND",
+            (false, false) => "O_CREAT",
+        };
+        traced(
+            Changes,
+            format_args!("open({:?}, O_WRONLY|{})", path, flags),
+            result,
+        )
+    }
+
+    /// Write `contents` to `path` with these options.
+    pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> Result<(), Error> {
+        let mut file = self.open(path)?;
+        file.write_all(contents.as_ref())?;
+        if self.sync && cfg!(not(unix)) {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Write everything `reader` yields to `path` with these options, without
+    /// holding it all in memory. Returns the number of bytes written.
+    ///
+    /// Examples
+    /// ```
+    /// use soil::{read_text, remove_file, WriteOptions};
+    /// let mut input = "streamed\n".as_bytes();
+    /// let written = WriteOptions::new()
+    ///     .write_from("write_from_example.txt", &mut input)
+    ///     .unwrap();
+    /// assert_eq!(written, 9);
+    /// assert_eq!(read_text("write_from_example.txt").unwrap(), "streamed\n");
+    /// remove_file("write_from_example.txt").unwrap();
+    /// ```
+    pub fn write_from<P: AsRef<Path>, R: Read + ?Sized>(
+        &self,
+        path: P,
+        reader: &mut R,
+    ) -> Result<u64, Error> {
+        let path = path.as_ref();
+        let mut file = self.open(path)?;
+        let result = io::copy(reader, &mut file);
+        let bytes = *result.as_ref().unwrap_or(&0);
+        let written = traced(
+            Changes,
+            format_args!("write({:?}, {} bytes)", path, bytes),
+            result,
+        )?;
+        if self.sync && cfg!(not(unix)) {
+            file.sync_all()?;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup_test;
+    use crate::{read_text, write_file};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_truncate_and_append() {
+        let guard = setup_test();
+        let file = format!("{}/state.txt", guard.test_root);
+        write_file(&file, "old contents").unwrap();
+
+        WriteOptions::new().write(&file, "new").unwrap();
+        assert_eq!(read_text(&file).unwrap(), "new");
+        WriteOptions::new()
+            .append(true)
+            .sync(true)
+            .write(&file, "er")
+            .unwrap();
+        assert_eq!(read_text(&file).unwrap(), "newer");
+    }
+
+    #[test]
+    fn test_backup_only_existing_files() {
+        let guard = setup_test();
+        let file = format!("{}/fresh.txt", guard.test_root);
+        let simple = WriteOptions::new().backup(Backup::Simple);
+        let numbered = WriteOptions::new().backup(Backup::Numbered { keep: None });
+
+        simple.write(&file, "first").unwrap();
+        numbered.write(&file, "first").unwrap();
+        assert!(!crate::exists(format!("{}~", file)));
+        assert!(crate::exists(format!("{}.~1~", file)));
+
+        let other = format!("{}/other.txt", guard.test_root);
+        numbered.write(&other, "new").unwrap();
+        assert!(!crate::exists(format!("{}.~1~", other)));
+
+        simple.write(&file, "second").unwrap();
+        assert_eq!(read_text(format!("{}~", file)).unwrap(), "first");
+    }
+
+    #[test]
+    fn test_create_new() {
+        let guard = setup_test();
+        let file = format!("{}/lock", guard.test_root);
+        let exclusive = WriteOptions::new().create_new(true);
+
+        exclusive.write(&file, "1").unwrap();
+        let error = exclusive.write(&file, "2").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(read_text(&file).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_write_from_reader() {
+        let guard = setup_test();
+        let file = format!("{}/stream.bin", guard.test_root);
+        let data: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+
+        let written = WriteOptions::new()
+            .write_from(&file, &mut data.as_slice())
+            .unwrap();
+        assert_eq!(written, data.len() as u64);
+        assert_eq!(crate::read_bytes(&file).unwrap(), data);
+
+        let more = WriteOptions::new()
+            .append(true)
+            .write_from(&file, &mut &b"tail"[..])
+            .unwrap();
+        assert_eq!(more, 4);
+        assert_eq!(crate::read_bytes(&file).unwrap().len(), data.len() + 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_on_creation_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let guard = setup_test();
+        let file = format!("{}/secret", guard.test_root);
+        let mode_of = |path: &str| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        WriteOptions::new()
+            .mode(Mode::from_bits(0o600))
+            .write(&file, "token")
+            .unwrap();
+        assert_eq!(mode_of(&file), 0o600);
+
+        let shared = format!("{}/shared", guard.test_root);
+        WriteOptions::new()
+            .mode(Mode::from_bits(0o666))
+            .write(&shared, "")
+            .unwrap();
+        assert_eq!(mode_of(&shared), 0o666);
+
+        // Rewriting an existing file keeps its permissions.
+        WriteOptions::new()
+            .mode(Mode::from_bits(0o644))
+            .write(&file, "rotated")
+            .unwrap();
+        assert_eq!(mode_of(&file), 0o600);
+    }
+}